@@ -85,9 +85,11 @@ function _incant_widget() {
     local cmd
     # Connect stdin to /dev/tty so TUI can read input
     # stdout is captured, stderr and TUI go to terminal
-    cmd=$(incant </dev/tty)
+    # With text on the line, incant rewrites it instead
+    cmd=$(incant --edit "$BUFFER" </dev/tty)
     if [[ -n "$cmd" ]]; then
-        LBUFFER+="$cmd"
+        BUFFER="$cmd"
+        CURSOR=${#BUFFER}
     fi
     zle redisplay
 }
@@ -101,9 +103,12 @@ bindkey '"'"'^k'"'"' _incant_widget'
 _incant_readline() {
     local cmd
     # Connect stdin to /dev/tty so TUI can read input
-    cmd=$(incant </dev/tty)
-    READLINE_LINE="${READLINE_LINE}${cmd}"
-    READLINE_POINT=${#READLINE_LINE}
+    # With text on the line, incant rewrites it instead
+    cmd=$(incant --edit "$READLINE_LINE" </dev/tty)
+    if [[ -n "$cmd" ]]; then
+        READLINE_LINE="$cmd"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
 }
 bind -x '"'"'"\C-k": _incant_readline'"'"''
             ;;
@@ -113,8 +118,12 @@ bind -x '"'"'"\C-k": _incant_readline'"'"''
 # incant shell integration
 function _incant_fish
     # Connect stdin to /dev/tty so TUI can read input
-    set -l cmd (incant </dev/tty)
-    commandline -i $cmd
+    # With text on the line, incant rewrites it instead
+    set -l cmd (incant --edit=(commandline | string collect) </dev/tty | string collect)
+    if test -n "$cmd"
+        commandline -r -- $cmd
+    end
+    commandline -f repaint
 end
 bind \ck _incant_fish'
             ;;
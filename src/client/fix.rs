@@ -0,0 +1,105 @@
+//! Input for `incant fix`.
+//!
+//! The shell hooks from `incant install --fix` pass the last command line
+//! and its exit status as arguments, and the zsh and bash hooks tee the
+//! shell's stderr into a per-shell file that is truncated before each
+//! command. Nothing here reads the history file.
+
+use crate::config::Config;
+use crate::history::Redactor;
+use crate::protocol::FailedCommand;
+use anyhow::{bail, Result};
+use std::io::Read;
+use std::path::Path;
+
+/// Most stderr sent, in bytes. The end of the output is where the error is.
+const MAX_STDERR_BYTES: u64 = 4 * 1024;
+
+/// Build the failed command to send, redacted like history context.
+pub fn failed_command(
+    config: &Config,
+    command: &str,
+    exit_code: Option<i32>,
+    stderr_file: Option<&Path>,
+) -> Result<FailedCommand> {
+    let command = command.trim();
+    if command.is_empty() {
+        bail!("No previous command to fix. Set up the shell hooks with: incant install --fix");
+    }
+    let redactor = Redactor::new(&config.context.history)?;
+    let stderr = stderr_file.map(read_captured_stderr).unwrap_or_default();
+    Ok(FailedCommand {
+        command: redactor.redact(command),
+        exit_code,
+        stderr: redactor.redact(&stderr),
+    })
+}
+
+/// The last command's stderr from the hook's capture file.
+///
+/// The bash hook writes a NUL after each command so that the prompt, which
+/// bash draws on stderr, can be cut off. A missing file means the hook does
+/// not capture stderr.
+fn read_captured_stderr(path: &Path) -> String {
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return String::new();
+    };
+    let mut bytes = Vec::new();
+    if file.read_to_end(&mut bytes).is_err() {
+        return String::new();
+    }
+    if let Some(end) = bytes.iter().position(|b| *b == 0) {
+        bytes.truncate(end);
+    }
+    let start = bytes.len().saturating_sub(MAX_STDERR_BYTES as usize);
+    let tail = String::from_utf8_lossy(&bytes[start..]).into_owned();
+    // Start at a line boundary when the head was cut.
+    match (start > 0, tail.split_once('\n')) {
+        (true, Some((_, rest))) => rest.to_string(),
+        _ => tail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stderr_is_cut_at_the_marker_and_keeps_the_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stderr");
+        std::fs::write(&path, b"error: no such file\n\0user@host:~$ ").unwrap();
+        assert_eq!(read_captured_stderr(&path), "error: no such file\n");
+
+        let long = format!("{}last line\n", "noise line\n".repeat(1000));
+        std::fs::write(&path, &long).unwrap();
+        let tail = read_captured_stderr(&path);
+        assert!(tail.len() <= MAX_STDERR_BYTES as usize);
+        assert!(tail.starts_with("noise line\n") && tail.ends_with("last line\n"));
+
+        assert_eq!(read_captured_stderr(&dir.path().join("missing")), "");
+    }
+
+    #[test]
+    fn failed_command_is_redacted_and_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stderr");
+        std::fs::write(
+            &path,
+            "curl: (6) Could not resolve https://bob:pw@exmaple.com/api\n",
+        )
+        .unwrap();
+        let failed = failed_command(
+            &Config::default(),
+            "  curl https://bob:pw@exmaple.com/api\n",
+            Some(6),
+            Some(&path),
+        )
+        .unwrap();
+        assert_eq!(failed.command, "curl https://[REDACTED]@exmaple.com/api");
+        assert_eq!(failed.exit_code, Some(6));
+        assert!(!failed.stderr.contains("pw"), "{}", failed.stderr);
+
+        assert!(failed_command(&Config::default(), " ", Some(1), None).is_err());
+    }
+}
@@ -7,6 +7,8 @@
 //! - Outputs the generated command to stdout
 
 pub mod clipboard;
+pub mod fix;
+pub mod sample;
 pub mod socket;
 pub mod tui;
 
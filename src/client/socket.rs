@@ -1,6 +1,6 @@
 //! Platform-native client for communicating with the daemon.
 
-use crate::protocol::{framing, Context, Message, Request, Response};
+use crate::protocol::{framing, Message, Request, Response};
 use crate::safety::Assessment;
 use crate::transport::{self, ClientStream};
 use anyhow::{Context as AnyhowContext, Result};
@@ -14,14 +14,10 @@ pub struct GeneratedCommand {
     pub explanation: Option<String>,
 }
 
-/// Send a query to the daemon and return the generated command.
-pub async fn send_query(
-    query: String,
-    context: Context,
-    model: Option<String>,
-    temperature: Option<f32>,
-    explain: bool,
-) -> Result<GeneratedCommand> {
+/// Send a query to the daemon and return the generated command. The
+/// request's mode fields (`failed`, `edit`) and any data sample are passed
+/// through; progress frames are always requested.
+pub async fn send_query(request: Request) -> Result<GeneratedCommand> {
     let endpoint = transport::endpoint()?;
 
     // Windows validates the pipe owner's SID inside connect(), before this
@@ -33,37 +29,44 @@ pub async fn send_query(
             format!("Failed to connect to daemon at {endpoint}. Start it with: incant daemon start")
         })?;
 
-    send_query_to_stream(stream, query, context, model, temperature, explain).await
+    let request = Request {
+        progress: true,
+        ..request
+    };
+    send_request_to_stream(stream, request).await
 }
 
-/// Send a query to an existing stream.
-async fn send_query_to_stream(
+/// Send a request to an existing stream.
+async fn send_request_to_stream(
     mut stream: ClientStream,
-    query: String,
-    context: Context,
-    model: Option<String>,
-    temperature: Option<f32>,
-    explain: bool,
+    request: Request,
 ) -> Result<GeneratedCommand> {
-    let request = Request {
-        query,
-        context,
-        model,
-        temperature,
-        explain,
-    };
     let message = Message::Query(Box::new(request));
 
     // Send the request
     framing::write_message(&mut stream, &message).await?;
 
-    // Read the response with timeout
-    let response: Response = tokio::time::timeout(
-        Duration::from_secs(60), // LLM can take a while
-        framing::read_message(&mut stream),
-    )
-    .await
-    .map_err(|_| anyhow::anyhow!("Request timeout - LLM took too long"))??;
+    // Read the response with timeout. Progress frames (a model being
+    // pulled) go to stderr and restart the clock.
+    let mut showed_progress = false;
+    let response = loop {
+        let response: Response = tokio::time::timeout(
+            Duration::from_secs(60), // LLM can take a while
+            framing::read_message(&mut stream),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Request timeout - LLM took too long"))??;
+        match response.progress {
+            Some(status) => {
+                eprint!("\r\x1b[2K{}", status);
+                showed_progress = true;
+            }
+            None => break response,
+        }
+    };
+    if showed_progress {
+        eprintln!();
+    }
 
     // Extract the command or error
     if let Some(command) = response.command {
@@ -79,6 +82,32 @@ async fn send_query_to_stream(
     }
 }
 
+/// Ask the daemon to assess a command line the user typed. Fails fast when
+/// the daemon is not running, so the caller can fall back to assessing it
+/// in-process.
+pub async fn assess(command: &str) -> Result<Assessment> {
+    let endpoint = transport::endpoint()?;
+    let mut stream =
+        tokio::time::timeout(Duration::from_millis(200), transport::connect(&endpoint))
+            .await
+            .map_err(|_| anyhow::anyhow!("Connection timeout"))??;
+
+    let message = Message::Assess {
+        command: command.to_string(),
+    };
+    framing::write_message(&mut stream, &message).await?;
+    let response: Response =
+        tokio::time::timeout(Duration::from_secs(1), framing::read_message(&mut stream))
+            .await
+            .map_err(|_| anyhow::anyhow!("Assessment timeout"))??;
+
+    match (response.risk, response.error) {
+        (Some(risk), _) => Ok(risk),
+        (None, Some(error)) => Err(anyhow::anyhow!("{}", error)),
+        (None, None) => Err(anyhow::anyhow!("Invalid response from daemon")),
+    }
+}
+
 /// Check if the daemon is reachable.
 #[allow(dead_code)]
 pub async fn check_daemon() -> Result<()> {
@@ -31,8 +31,32 @@ pub enum TuiResult {
     Cancelled,
 }
 
-/// Run the TUI and return the user's query.
-pub fn run_tui() -> Result<TuiResult> {
+/// Run the TUI and return the user's query. With `original` set the
+/// popup shows the command being edited above the input.
+pub fn run_tui(original: Option<&str>) -> Result<TuiResult> {
+    #[cfg(not(windows))]
+    return with_terminal(|terminal, tty| run_input_loop(terminal, tty, original));
+    #[cfg(windows)]
+    return with_terminal(|terminal| run_input_loop(terminal, original));
+}
+
+/// Show an edited command next to the original. Returns whether the user
+/// accepted the new command.
+pub fn review_edit(original: &str, command: &str) -> Result<bool> {
+    #[cfg(not(windows))]
+    return with_terminal(|terminal, tty| run_review_loop(terminal, tty, original, command));
+    #[cfg(windows)]
+    return with_terminal(|terminal| run_review_loop(terminal, original, command));
+}
+
+/// Set up the popup terminal, run `screen`, and restore the terminal.
+fn with_terminal<T>(
+    #[cfg(not(windows))] screen: impl FnOnce(
+        &mut Terminal<CrosstermBackend<io::Stderr>>,
+        &mut std::fs::File,
+    ) -> Result<T>,
+    #[cfg(windows)] screen: impl FnOnce(&mut Terminal<CrosstermBackend<io::Stderr>>) -> Result<T>,
+) -> Result<T> {
     // Like fzf, we use:
     // - stderr for TUI output (goes to terminal even in command substitution)
     // - the controlling terminal (/dev/tty) for keyboard input, so a piped
@@ -58,11 +82,10 @@ pub fn run_tui() -> Result<TuiResult> {
     let backend = CrosstermBackend::new(io::stderr());
     let mut terminal = Terminal::new(backend)?;
 
-    // Run the input loop
     #[cfg(not(windows))]
-    let result = run_input_loop(&mut terminal, &mut tty);
+    let result = screen(&mut terminal, &mut tty);
     #[cfg(windows)]
-    let result = run_input_loop(&mut terminal);
+    let result = screen(&mut terminal);
 
     // Restore terminal state
     disable_raw_mode()?;
@@ -112,12 +135,13 @@ fn apply_input_command(input_text: &mut String, command: InputCommand) -> Option
 fn run_input_loop<W: Write>(
     terminal: &mut Terminal<CrosstermBackend<W>>,
     tty: &mut impl Read,
+    original: Option<&str>,
 ) -> Result<TuiResult> {
     let mut input_text = String::new();
     let mut buf = [0u8; 1];
 
     loop {
-        terminal.draw(|frame| draw_ui(frame, &input_text))?;
+        terminal.draw(|frame| draw_ui(frame, &input_text, original))?;
 
         if tty.read(&mut buf)? == 0 {
             // EOF on the controlling terminal means the terminal went away,
@@ -175,11 +199,14 @@ fn run_input_loop<W: Write>(
 }
 
 #[cfg(windows)]
-fn run_input_loop<W: Write>(terminal: &mut Terminal<CrosstermBackend<W>>) -> Result<TuiResult> {
+fn run_input_loop<W: Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    original: Option<&str>,
+) -> Result<TuiResult> {
     let mut input_text = String::new();
 
     loop {
-        terminal.draw(|frame| draw_ui(frame, &input_text))?;
+        terminal.draw(|frame| draw_ui(frame, &input_text, original))?;
 
         let Event::Key(key) = event::read()? else {
             continue;
@@ -211,21 +238,64 @@ fn run_input_loop<W: Write>(terminal: &mut Terminal<CrosstermBackend<W>>) -> Res
     }
 }
 
-/// Draw the TUI.
-fn draw_ui(frame: &mut Frame, input_text: &str) {
-    let size = frame.area();
+/// Wait for Enter (accept) or Escape/Ctrl+C (keep the original).
+#[cfg(not(windows))]
+fn run_review_loop<W: Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    tty: &mut impl Read,
+    original: &str,
+    command: &str,
+) -> Result<bool> {
+    let mut buf = [0u8; 1];
+    loop {
+        terminal.draw(|frame| draw_review(frame, original, command))?;
+        if tty.read(&mut buf)? == 0 {
+            return Err(anyhow::anyhow!("terminal closed while awaiting input"));
+        }
+        match buf[0] {
+            b'\r' | b'\n' => return Ok(true),
+            0x1b | 0x03 => return Ok(false),
+            _ => {}
+        }
+    }
+}
 
-    // Create a centered popup
+#[cfg(windows)]
+fn run_review_loop<W: Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    original: &str,
+    command: &str,
+) -> Result<bool> {
+    loop {
+        terminal.draw(|frame| draw_review(frame, original, command))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(true),
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c' | 'C') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(false)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The bordered popup, centered, with `lines` rows inside.
+fn popup(frame: &mut Frame, title: &str, lines: u16) -> Rect {
+    let size = frame.area();
     let popup_width = size.width.saturating_sub(4).min(80);
-    let popup_height = 3;
-    let popup_area = centered_rect(popup_width, popup_height, size);
+    let popup_area = centered_rect(popup_width, lines + 2, size);
 
     // Clear the popup area
     frame.render_widget(Clear, popup_area);
 
-    // Create the input block
     let block = Block::default()
-        .title(" incant ")
+        .title(title.to_string())
         .title_style(
             Style::default()
                 .fg(Color::Cyan)
@@ -234,9 +304,35 @@ fn draw_ui(frame: &mut Frame, input_text: &str) {
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray));
 
-    // Inner area for the input
     let inner_area = block.inner(popup_area);
     frame.render_widget(block, popup_area);
+    inner_area
+}
+
+/// Draw the TUI.
+fn draw_ui(frame: &mut Frame, input_text: &str, original: Option<&str>) {
+    let (title, lines) = match original {
+        Some(_) => (" incant: edit ", 2),
+        None => (" incant ", 1),
+    };
+    let mut inner_area = popup(frame, title, lines);
+
+    // The command being edited sits above the input
+    if let Some(original) = original {
+        let row = Rect {
+            height: 1,
+            ..inner_area
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(Span::styled(
+                original.to_string(),
+                Style::default().fg(Color::DarkGray),
+            ))),
+            row,
+        );
+        inner_area.y += 1;
+        inner_area.height = inner_area.height.saturating_sub(1);
+    }
 
     // Calculate visible portion of input
     let input_width = inner_area.width as usize;
@@ -265,6 +361,26 @@ fn draw_ui(frame: &mut Frame, input_text: &str) {
     frame.set_cursor_position((cursor_x, cursor_y));
 }
 
+/// Draw the original and edited commands, one per line.
+fn draw_review(frame: &mut Frame, original: &str, command: &str) {
+    let inner_area = popup(frame, " incant: edit ", 3);
+    let lines = vec![
+        Line::from(vec![
+            Span::styled("- ", Style::default().fg(Color::Red)),
+            Span::styled(original.to_string(), Style::default().fg(Color::DarkGray)),
+        ]),
+        Line::from(vec![
+            Span::styled("+ ", Style::default().fg(Color::Green)),
+            Span::styled(command.to_string(), Style::default().fg(Color::White)),
+        ]),
+        Line::from(Span::styled(
+            "Enter: use it   Esc: keep the original",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    frame.render_widget(Paragraph::new(lines), inner_area);
+}
+
 /// Create a centered rectangle.
 fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
     let vertical = Layout::default()
@@ -345,4 +461,28 @@ mod tests {
             Some(TuiResult::Cancelled)
         );
     }
+
+    #[test]
+    fn edit_screens_show_the_original_command() {
+        let rendered = |draw: &dyn Fn(&mut Frame)| {
+            let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(60, 9)).unwrap();
+            terminal.draw(|frame| draw(frame)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            buffer
+                .content()
+                .chunks(60)
+                .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let input = rendered(&|frame| draw_ui(frame, "use fd", Some("find . -name x")));
+        assert!(input.contains("incant: edit"), "{input}");
+        let original_row = input.find("find . -name x").unwrap();
+        assert!(original_row < input.find("use fd").unwrap(), "{input}");
+
+        let review = rendered(&|frame| draw_review(frame, "find . -name x", "fd x"));
+        assert!(review.contains("- find . -name x"), "{review}");
+        assert!(review.contains("+ fd x"), "{review}");
+    }
 }
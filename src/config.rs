@@ -2,9 +2,10 @@
 //!
 //! Unix uses the XDG config location; Windows uses LocalAppData.
 
+use crate::prompt_budget::{Priority, Section};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 /// Main configuration structure.
@@ -19,6 +20,15 @@ pub struct Config {
     /// User preferences.
     #[serde(default)]
     pub preferences: Preferences,
+    /// Network policy; see [`crate::privacy`].
+    #[serde(default)]
+    pub privacy: crate::privacy::PrivacyConfig,
+    /// Optional context sources.
+    #[serde(default)]
+    pub context: ContextConfig,
+    /// Site-specific safety rules; see [`crate::safety`].
+    #[serde(default)]
+    pub safety: crate::safety::SafetyConfig,
 }
 
 impl Default for Config {
@@ -29,6 +39,7 @@ impl Default for Config {
             Profile {
                 model: "qwen2.5-coder:7b".to_string(),
                 temperature: Some(0.1),
+                prompt_tokens: None,
             },
         );
         profiles.insert(
@@ -36,6 +47,7 @@ impl Default for Config {
             Profile {
                 model: "qwen2.5-coder:1.5b".to_string(),
                 temperature: Some(0.1),
+                prompt_tokens: None,
             },
         );
         profiles.insert(
@@ -43,6 +55,7 @@ impl Default for Config {
             Profile {
                 model: "qwen2.5-coder:32b".to_string(),
                 temperature: Some(0.1),
+                prompt_tokens: None,
             },
         );
 
@@ -50,6 +63,9 @@ impl Default for Config {
             backend: BackendConfig::default(),
             profiles,
             preferences: Preferences::default(),
+            privacy: crate::privacy::PrivacyConfig::default(),
+            context: ContextConfig::default(),
+            safety: crate::safety::SafetyConfig::default(),
         }
     }
 }
@@ -62,6 +78,11 @@ pub struct Profile {
     /// Temperature for generation (0.0-1.0).
     #[serde(default)]
     pub temperature: Option<f32>,
+    /// Budget for the system prompt, in estimated tokens. Low-priority
+    /// context is shortened or left out to fit. Ollama models default to
+    /// 1536; other backends are unbudgeted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<usize>,
 }
 
 /// Backend configuration for LLM providers.
@@ -76,6 +97,12 @@ pub enum BackendConfig {
         /// Default profile name (default: "default").
         #[serde(default = "default_profile_name")]
         default_profile: String,
+        /// Pull profile models that are not installed, in the background.
+        #[serde(default)]
+        auto_pull: bool,
+        /// Record responses to the cassette file for later replay.
+        #[serde(default)]
+        record: bool,
     },
     /// Anthropic Claude API.
     Anthropic {
@@ -85,15 +112,49 @@ pub enum BackendConfig {
         /// API key (prefer ANTHROPIC_API_KEY env var).
         #[serde(default)]
         api_key: Option<String>,
+        /// Record responses to the cassette file for later replay.
+        #[serde(default)]
+        record: bool,
     },
     /// OpenAI API.
     OpenAI {
         /// Default profile name (default: "default").
         #[serde(default = "default_profile_name")]
         default_profile: String,
-        /// API key (prefer OPENAI_API_KEY env var).
+        /// API key (prefer OPENAI_API_KEY env var). Optional for
+        /// compatible servers that do not authenticate.
         #[serde(default)]
         api_key: Option<String>,
+        /// API root (default: https://api.openai.com/v1). Point this at any
+        /// OpenAI-compatible server.
+        #[serde(default = "default_openai_base_url")]
+        base_url: String,
+        /// Record responses to the cassette file for later replay.
+        #[serde(default)]
+        record: bool,
+    },
+    /// External plugin process speaking JSON lines over stdin/stdout.
+    Exec {
+        /// Program to spawn (looked up on PATH when not a path).
+        command: String,
+        /// Arguments passed to the program.
+        #[serde(default)]
+        args: Vec<String>,
+        /// Default profile name (default: "default").
+        #[serde(default = "default_profile_name")]
+        default_profile: String,
+        /// Record responses to the cassette file for later replay.
+        #[serde(default)]
+        record: bool,
+    },
+    /// Serve recorded responses from a cassette; never touches the network.
+    Replay {
+        /// Cassette file (default: cassette.jsonl in the config directory).
+        #[serde(default)]
+        cassette: Option<PathBuf>,
+        /// Default profile name (default: "default").
+        #[serde(default = "default_profile_name")]
+        default_profile: String,
     },
 }
 
@@ -102,6 +163,8 @@ impl Default for BackendConfig {
         BackendConfig::Ollama {
             host: default_ollama_host(),
             default_profile: default_profile_name(),
+            auto_pull: false,
+            record: false,
         }
     }
 }
@@ -110,6 +173,10 @@ fn default_ollama_host() -> String {
     "http://localhost:11434".to_string()
 }
 
+fn default_openai_base_url() -> String {
+    crate::daemon::llm::openai::OPENAI_BASE_URL.to_string()
+}
+
 fn default_profile_name() -> String {
     "default".to_string()
 }
@@ -147,6 +214,397 @@ fn default_true() -> bool {
     true
 }
 
+/// `[context]` section: opt-in context sources, the tool and project
+/// marker tables, and the time allowed for gathering.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Wall-clock budget for all context probes, in milliseconds. Probes
+    /// run concurrently; one that misses the deadline is left out.
+    #[serde(default = "default_context_budget_ms")]
+    pub budget_ms: u64,
+    /// Shell history; see [`crate::history`].
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Aliases and functions exported by the shell widget.
+    #[serde(default)]
+    pub aliases: AliasConfig,
+    /// Active cloud and cluster targets; see [`crate::context`].
+    #[serde(default)]
+    pub infra: InfraConfig,
+    /// Top-level entries of cwd; see [`crate::context`].
+    #[serde(default)]
+    pub listing: ListingConfig,
+    /// External programs that add key/value facts; see
+    /// [`crate::context`].
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+    /// Which of the always-on fields are sent, and how paths are shown.
+    #[serde(default)]
+    pub send: SendConfig,
+    /// Whether `tools` extends or replaces the built-in probe list.
+    #[serde(default)]
+    pub tools_mode: TableMode,
+    /// Extra CLI tools to look for on PATH, mapped to a one-line
+    /// description for the prompt (may be empty).
+    #[serde(default)]
+    pub tools: BTreeMap<String, String>,
+    /// Whether `markers` extends or replaces the built-in marker list.
+    #[serde(default)]
+    pub markers_mode: TableMode,
+    /// Extra project marker files in cwd, mapped to the project type they
+    /// indicate, e.g. `"main.tf" = "terraform"`.
+    #[serde(default)]
+    pub markers: BTreeMap<String, String>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            budget_ms: default_context_budget_ms(),
+            history: HistoryConfig::default(),
+            aliases: AliasConfig::default(),
+            infra: InfraConfig::default(),
+            listing: ListingConfig::default(),
+            providers: Vec::new(),
+            send: SendConfig::default(),
+            tools_mode: TableMode::default(),
+            tools: BTreeMap::new(),
+            markers_mode: TableMode::default(),
+            markers: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_context_budget_ms() -> u64 {
+    100
+}
+
+/// How a configured table combines with the built-in one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TableMode {
+    /// Built-in entries first, then the configured ones.
+    #[default]
+    Extend,
+    /// Only the configured entries.
+    Replace,
+}
+
+impl std::fmt::Display for TableMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableMode::Extend => write!(f, "extend"),
+            TableMode::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+/// Longest tool description accepted, in characters.
+const MAX_TOOL_DESCRIPTION: usize = 200;
+
+impl ContextConfig {
+    /// Check the `tools` and `markers` tables, the path redaction patterns,
+    /// and the providers, reporting every bad entry.
+    pub fn validate(&self) -> Result<()> {
+        let problems: Vec<String> = self
+            .tools
+            .iter()
+            .filter_map(|(name, description)| check_tool(name, description).err())
+            .chain(
+                self.markers
+                    .iter()
+                    .filter_map(|(file, project)| check_marker(file, project).err()),
+            )
+            .chain(self.send.redact_paths.iter().filter_map(|pattern| {
+                regex::Regex::new(pattern)
+                    .err()
+                    .map(|e| format!("send.redact_paths: {:?}: {}", pattern, e))
+            }))
+            .chain(
+                self.providers
+                    .iter()
+                    .filter(|provider| provider.command.trim().is_empty())
+                    .map(|_| "providers: an entry has an empty command".to_string()),
+            )
+            .collect();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("Invalid [context] entries:\n  {}", problems.join("\n  "))
+        }
+    }
+
+    /// Configured tools that pass validation, in name order.
+    pub fn valid_tools(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tools
+            .iter()
+            .filter(|(name, description)| check_tool(name, description).is_ok())
+            .map(|(name, description)| (name.as_str(), description.as_str()))
+    }
+
+    /// Configured markers that pass validation, in file name order.
+    pub fn valid_markers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.markers
+            .iter()
+            .filter(|(file, project)| check_marker(file, project).is_ok())
+            .map(|(file, project)| (file.as_str(), project.as_str()))
+    }
+
+    /// The configured description for `tool`, if it has a non-empty one.
+    pub fn tool_description(&self, tool: &str) -> Option<&str> {
+        self.valid_tools()
+            .find(|(name, _)| *name == tool)
+            .map(|(_, description)| description.trim())
+            .filter(|description| !description.is_empty())
+    }
+}
+
+/// A tool is a bare executable name; its description one short line.
+fn check_tool(name: &str, description: &str) -> std::result::Result<(), String> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_whitespace() || c.is_control())
+    {
+        return Err(format!("tools: {:?} is not a bare executable name", name));
+    }
+    if description.contains(['\n', '\r']) {
+        return Err(format!("tools.{}: description must be one line", name));
+    }
+    if description.chars().count() > MAX_TOOL_DESCRIPTION {
+        return Err(format!(
+            "tools.{}: description is longer than {} characters",
+            name, MAX_TOOL_DESCRIPTION
+        ));
+    }
+    Ok(())
+}
+
+/// A marker is a relative path inside cwd; its project type one word.
+fn check_marker(file: &str, project: &str) -> std::result::Result<(), String> {
+    let path = std::path::Path::new(file);
+    if file.is_empty()
+        || path.is_absolute()
+        || file.starts_with(['/', '\\'])
+        || path
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(format!(
+            "markers: {:?} must be a relative path inside the project",
+            file
+        ));
+    }
+    if project.is_empty() || project.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(format!(
+            "markers.{:?}: project type {:?} must be a single word",
+            file, project
+        ));
+    }
+    Ok(())
+}
+
+/// `[context.send]` section. Everything defaults to on, matching what
+/// earlier versions sent; turning a field off also skips gathering it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendConfig {
+    /// The working directory path.
+    #[serde(default = "default_true")]
+    pub cwd: bool,
+    /// Branch and dirty state.
+    #[serde(default = "default_true")]
+    pub git: bool,
+    /// Linux distribution name.
+    #[serde(default = "default_true")]
+    pub distro: bool,
+    /// ssh/tmux/docker flags.
+    #[serde(default = "default_true")]
+    pub env_flags: bool,
+    /// Installed tools from the probe list.
+    #[serde(default = "default_true")]
+    pub tools: bool,
+    /// Project types and project tasks.
+    #[serde(default = "default_true")]
+    pub projects: bool,
+    /// Show the home directory as `~` so the username is not sent
+    /// (default: false).
+    #[serde(default)]
+    pub home_as_tilde: bool,
+    /// Regex patterns replaced with `[REDACTED]` in the sent cwd.
+    #[serde(default)]
+    pub redact_paths: Vec<String>,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            cwd: true,
+            git: true,
+            distro: true,
+            env_flags: true,
+            tools: true,
+            projects: true,
+            home_as_tilde: false,
+            redact_paths: Vec::new(),
+        }
+    }
+}
+
+/// `[context.infra]` section. Every source is off unless enabled; values
+/// are read from config files and environment variables, never by running
+/// the CLIs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InfraConfig {
+    /// Current kubeconfig context and its namespace.
+    #[serde(default)]
+    pub kubernetes: bool,
+    /// `AWS_PROFILE` and the region from the environment or `~/.aws/config`.
+    #[serde(default)]
+    pub aws: bool,
+    /// The active gcloud configuration's project.
+    #[serde(default)]
+    pub gcloud: bool,
+    /// The current docker context.
+    #[serde(default)]
+    pub docker: bool,
+}
+
+/// `[context.listing]` section: names, types, sizes, and ages of the
+/// entries in cwd. Off unless `enabled = true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingConfig {
+    /// Send the listing with each query.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Most entries to send, newest first (default: 30).
+    #[serde(default = "default_listing_limit")]
+    pub limit: usize,
+    /// Include dotfiles (default: false).
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            limit: default_listing_limit(),
+            hidden: false,
+        }
+    }
+}
+
+fn default_listing_limit() -> usize {
+    30
+}
+
+/// A `[[context.providers]]` entry: a program that prints a JSON object of
+/// key/value facts for the prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// Executable to run: a name on PATH or a path.
+    pub command: String,
+    /// Arguments passed to it.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Kill it after this many milliseconds (default: 50). The context
+    /// budget still applies.
+    #[serde(default = "default_provider_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_provider_timeout_ms() -> u64 {
+    50
+}
+
+/// `[context.aliases]` section. Only has an effect when the shell widget
+/// exports `INCANT_ALIASES`/`INCANT_FUNCTIONS` (`incant install --aliases`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasConfig {
+    /// Send exported aliases and function names (default: true).
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Most aliases, and separately most functions, to send (default: 50).
+    #[serde(default = "default_alias_limit")]
+    pub limit: usize,
+    /// Only send names matching one of these patterns (`*` wildcard).
+    /// Empty allows every name.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Never send names matching one of these patterns (`*` wildcard).
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl Default for AliasConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            limit: default_alias_limit(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+fn default_alias_limit() -> usize {
+    50
+}
+
+/// `[context.history]` section. Off unless `enabled = true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Send recent history with each query.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How many entries to send (default: 20).
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+    /// What to send: recent commands, or only alias definitions.
+    #[serde(default)]
+    pub mode: HistoryMode,
+    /// Extra regex patterns; matches are replaced with `[REDACTED]`.
+    #[serde(default)]
+    pub redact: Vec<String>,
+    /// Apply the built-in patterns for tokens, passwords, and URL
+    /// credentials (default: true).
+    #[serde(default = "default_true")]
+    pub default_redactions: bool,
+    /// History file to read instead of the shell's default.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            limit: default_history_limit(),
+            mode: HistoryMode::default(),
+            redact: Vec::new(),
+            default_redactions: true,
+            file: None,
+        }
+    }
+}
+
+fn default_history_limit() -> usize {
+    20
+}
+
+/// What history context is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryMode {
+    /// The last `limit` commands from the shell's history file.
+    #[default]
+    Commands,
+    /// Only alias definitions from the shell's startup files; no commands.
+    AliasesOnly,
+}
+
 /// Resolve the config directory from explicit inputs (pure, testable).
 ///
 /// `$XDG_CONFIG_HOME/incant` when set and non-empty, else
@@ -296,6 +754,12 @@ impl Config {
         Ok(Self::runtime_dir()?.join("incant.sock"))
     }
 
+    /// Get the default cassette path used by `record = true` and the
+    /// replay backend.
+    pub fn cassette_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("cassette.jsonl"))
+    }
+
     /// Get the PID file path for the daemon.
     pub fn pid_path() -> Result<PathBuf> {
         Ok(Self::runtime_dir()?.join("incant.pid"))
@@ -334,8 +798,12 @@ impl Config {
             }
             let contents = std::fs::read_to_string(&path)
                 .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-            toml::from_str(&contents)
-                .with_context(|| format!("Failed to parse config file: {}", path.display()))
+            let config: Self = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+            // Caught here, not when the daemon first assesses a command.
+            crate::safety::CustomRules::compile(&config.safety.rules)
+                .with_context(|| format!("Invalid config file: {}", path.display()))?;
+            Ok(config)
         } else {
             Ok(Self::default())
         }
@@ -387,6 +855,8 @@ impl Config {
             BackendConfig::Ollama { .. } => "ollama",
             BackendConfig::Anthropic { .. } => "anthropic",
             BackendConfig::OpenAI { .. } => "openai",
+            BackendConfig::Exec { .. } => "exec",
+            BackendConfig::Replay { .. } => "replay",
         }
     }
 
@@ -402,6 +872,12 @@ impl Config {
             BackendConfig::OpenAI {
                 default_profile, ..
             } => default_profile,
+            BackendConfig::Exec {
+                default_profile, ..
+            } => default_profile,
+            BackendConfig::Replay {
+                default_profile, ..
+            } => default_profile,
         }
     }
 
@@ -411,9 +887,30 @@ impl Config {
             BackendConfig::Ollama { .. } => "qwen2.5-coder:7b",
             BackendConfig::Anthropic { .. } => "claude-3-5-haiku-latest",
             BackendConfig::OpenAI { .. } => "gpt-4o-mini",
+            // Plugins and cassettes carry their own model names.
+            BackendConfig::Exec { .. } | BackendConfig::Replay { .. } => "default",
+        }
+    }
+
+    /// Whether responses from the real backend are recorded to a cassette.
+    pub fn record(&self) -> bool {
+        match &self.backend {
+            BackendConfig::Ollama { record, .. }
+            | BackendConfig::Anthropic { record, .. }
+            | BackendConfig::OpenAI { record, .. }
+            | BackendConfig::Exec { record, .. } => *record,
+            BackendConfig::Replay { .. } => false,
         }
     }
 
+    /// Every distinct model referenced by a profile, sorted.
+    pub fn profile_models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self.profiles.values().map(|p| p.model.clone()).collect();
+        models.sort_unstable();
+        models.dedup();
+        models
+    }
+
     /// Get the model name (from default profile or fallback).
     pub fn model_name(&self) -> String {
         let default_profile = self.default_profile();
@@ -424,20 +921,77 @@ impl Config {
         }
     }
 
-    /// Build the system prompt based on context and preferences.
+    /// Build the system prompt for a plain query to the default model.
     pub fn build_system_prompt(&self, context: &crate::protocol::Context) -> String {
-        if let Some(windows) = context.windows.as_ref() {
-            return build_windows_system_prompt(context, windows);
+        self.build_prompt(context, PromptMode::Generate, None, &self.model_name())
+    }
+
+    /// Build the system prompt for `mode`, with the input data sample if
+    /// there is one, fitted to `model`'s prompt budget (see
+    /// [`crate::prompt_budget`]).
+    pub fn build_prompt(
+        &self,
+        context: &crate::protocol::Context,
+        mode: PromptMode,
+        sample: Option<&crate::protocol::DataSample>,
+        model: &str,
+    ) -> String {
+        let mut sections = match context.windows.as_ref() {
+            Some(windows) => windows_sections(context, windows, &self.context),
+            None => self.posix_sections(context),
+        };
+        if let Some(sample) = sample {
+            let mut structure_only = sample.clone();
+            structure_only.head.clear();
+            sections.push(
+                Section::new("input sample", Priority::High, sample_section(sample))
+                    .or_shorter(sample_section(&structure_only)),
+            );
         }
+        let rules = match mode {
+            PromptMode::Generate => String::new(),
+            PromptMode::Fix => format!("\n\n{}", FIX_RULES),
+            PromptMode::Edit => format!("\n\n{}", EDIT_RULES),
+        };
+        sections.push(Section::new("mode rules", Priority::Required, rules));
+        crate::prompt_budget::fit(sections, self.prompt_budget(model))
+    }
+
+    /// Token budget for `model`'s system prompt: the smallest
+    /// `prompt_tokens` of the profiles using it, else a default for Ollama,
+    /// whose default context window is small. Other backends get none.
+    pub fn prompt_budget(&self, model: &str) -> Option<usize> {
+        self.profiles
+            .values()
+            .filter(|profile| profile.model == model)
+            .filter_map(|profile| profile.prompt_tokens)
+            .min()
+            .or_else(|| {
+                matches!(self.backend, BackendConfig::Ollama { .. })
+                    .then_some(DEFAULT_OLLAMA_PROMPT_TOKENS)
+            })
+    }
+
+    /// The POSIX prompt: rules and the basic facts, then the context
+    /// sections in prompt order.
+    fn posix_sections(&self, context: &crate::protocol::Context) -> Vec<Section> {
+        // Configured tools are not classic-tool replacements; they get
+        // their own line from push_tool_notes.
+        let modern_tools: Vec<&str> = context
+            .tools
+            .iter()
+            .map(String::as_str)
+            .filter(|tool| crate::context::is_modern_tool(tool))
+            .collect();
         let modern_tools_note = if !self.preferences.modern_tools {
             "- Use standard POSIX tools (grep, find, cat)".to_string()
-        } else if context.tools.is_empty() {
+        } else if modern_tools.is_empty() {
             "- Use modern tools when appropriate (ripgrep over grep, fd over find, bat over cat)"
                 .to_string()
         } else {
             format!(
                 "- Prefer these installed modern tools over classic equivalents: {}",
-                context.tools.join(", ")
+                modern_tools.join(", ")
             )
         };
 
@@ -453,18 +1007,7 @@ impl Config {
             .map(|d| format!("\nDistro: {}", d))
             .unwrap_or_default();
 
-        let mut extra = String::new();
-        if !context.projects.is_empty() {
-            extra.push_str(&format!("\nProject: {}", context.projects.join(", ")));
-        }
-        if let Some(git) = &context.git {
-            extra.push_str(&format!("\nGit: {}", git));
-        }
-        if !context.env_flags.is_empty() {
-            extra.push_str(&format!("\nEnvironment: {}", context.env_flags.join(", ")));
-        }
-
-        format!(
+        let head = format!(
             r#"You are a shell command generator. Your ONLY output is the exact command to run.
 
 Rules:
@@ -478,41 +1021,505 @@ Rules:
 
 Context:
 OS: {}{}
-Shell: {}
-CWD: {}{}"#,
+Shell: {}{}"#,
             modern_tools_note,
             flags_note,
             context.os,
             distro_info,
             context.shell,
-            context.cwd.display(),
-            extra
-        )
+            cwd_line(context),
+        );
+
+        let mut sections = vec![
+            Section::new("rules", Priority::Required, head),
+            section("userland", Priority::Medium, context, push_userland),
+            section("projects", Priority::High, context, push_projects),
+            project_tasks_section(context),
+            self.tool_notes_section(context),
+            section("infra", Priority::High, context, push_infra),
+            git_section(context),
+            section("environment", Priority::High, context, push_env_flags),
+            section("user", Priority::High, context, push_host),
+            listing_section(context),
+            section("site context", Priority::Medium, context, push_extra),
+        ];
+        sections.extend(user_sections(context));
+        sections
+    }
+
+    fn tool_notes_section(&self, context: &crate::protocol::Context) -> Section {
+        let mut text = String::new();
+        push_tool_notes(&mut text, context, &self.context);
+        Section::new("tool notes", Priority::Medium, text)
     }
 }
 
-fn build_windows_system_prompt(
+/// What a system prompt is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptMode {
+    /// A new command from a description.
+    Generate,
+    /// `incant fix`: correct a failed command.
+    Fix,
+    /// `--edit`: rewrite the command line.
+    Edit,
+}
+
+/// Prompt budget, in estimated tokens, for Ollama models whose profile sets
+/// none. Ollama's default context window is 2048 to 4096 tokens, and the
+/// query and the answer need room too.
+const DEFAULT_OLLAMA_PROMPT_TOKENS: usize = 1536;
+
+/// Most lines a shortened list section keeps.
+const SHORT_SECTION_LINES: usize = 5;
+
+/// A section rendered by one of the `push_*` helpers.
+fn section(
+    name: &'static str,
+    priority: Priority,
+    context: &crate::protocol::Context,
+    push: fn(&mut String, &crate::protocol::Context),
+) -> Section {
+    let mut text = String::new();
+    push(&mut text, context);
+    Section::new(name, priority, text)
+}
+
+/// `section`, offering the rendering of a pared-down copy of `context` as
+/// the shorter form.
+fn shortened_section(
+    name: &'static str,
+    priority: Priority,
     context: &crate::protocol::Context,
-    windows: &crate::protocol::WindowsContext,
-) -> String {
-    let mut extra = String::new();
-    if !windows.diagnostic_tools.is_empty() {
-        extra.push_str(&format!(
-            "\nInstalled diagnostic tools: {}",
-            windows.diagnostic_tools.join(", ")
-        ));
-    }
+    push: fn(&mut String, &crate::protocol::Context),
+    pare: impl FnOnce(&mut crate::protocol::Context),
+) -> Section {
+    let mut short = context.clone();
+    pare(&mut short);
+    let shorter = section(name, priority, &short, push).text;
+    section(name, priority, context, push).or_shorter(shorter)
+}
+
+fn project_tasks_section(context: &crate::protocol::Context) -> Section {
+    shortened_section(
+        "project tasks",
+        Priority::Medium,
+        context,
+        push_project_tasks,
+        |short| short.project_tasks.truncate(SHORT_SECTION_LINES),
+    )
+}
+
+/// Git state; the short form leaves out remotes and recent branches.
+fn git_section(context: &crate::protocol::Context) -> Section {
+    shortened_section("git", Priority::High, context, push_git, |short| {
+        if let Some(git) = &mut short.git_state {
+            git.remotes.clear();
+            git.recent_branches.clear();
+        }
+    })
+}
+
+fn listing_section(context: &crate::protocol::Context) -> Section {
+    shortened_section("listing", Priority::Low, context, push_listing, |short| {
+        if let Some(listing) = &mut short.listing {
+            let cut = listing.entries.len().saturating_sub(SHORT_SECTION_LINES);
+            listing.entries.truncate(SHORT_SECTION_LINES);
+            listing.omitted += cut;
+        }
+    })
+}
+
+/// The opt-in user context: aliases, function names, and recent history.
+/// The short history keeps the newest commands.
+fn user_sections(context: &crate::protocol::Context) -> Vec<Section> {
+    vec![
+        shortened_section("aliases", Priority::Low, context, push_aliases, |short| {
+            short.aliases.truncate(SHORT_SECTION_LINES)
+        }),
+        section("functions", Priority::Low, context, push_functions),
+        shortened_section("history", Priority::Low, context, push_history, |short| {
+            let cut = short.history.len().saturating_sub(SHORT_SECTION_LINES);
+            short.history.drain(..cut);
+        }),
+    ]
+}
+
+fn push_projects(extra: &mut String, context: &crate::protocol::Context) {
     if !context.projects.is_empty() {
         extra.push_str(&format!("\nProject: {}", context.projects.join(", ")));
     }
-    if let Some(git) = &context.git {
-        extra.push_str(&format!("\nGit: {git}"));
-    }
+}
+
+fn push_env_flags(extra: &mut String, context: &crate::protocol::Context) {
     if !context.env_flags.is_empty() {
         extra.push_str(&format!("\nEnvironment: {}", context.env_flags.join(", ")));
     }
+}
 
-    format!(
+/// Append who commands run as and which package manager installs packages.
+fn push_host(extra: &mut String, context: &crate::protocol::Context) {
+    let Some(host) = &context.host else {
+        return;
+    };
+    if host.root {
+        extra.push_str("\nUser: root - run admin commands without sudo");
+    } else if let Some(escalation) = host.escalation.first() {
+        extra.push_str(&format!(
+            "\nUser: regular user - prefix admin commands with {}",
+            escalation
+        ));
+    } else {
+        extra.push_str("\nUser: regular user, no sudo or doas - avoid commands that need root");
+    }
+    if let Some(first) = host.package_managers.first() {
+        extra.push_str(&format!(
+            "\nPackage managers: {} - install packages with {}",
+            host.package_managers.join(", "),
+            first
+        ));
+    }
+}
+
+/// Append the facts from context providers.
+fn push_extra(extra: &mut String, context: &crate::protocol::Context) {
+    if context.extra.is_empty() {
+        return;
+    }
+    extra.push_str("\nSite context:");
+    for (key, value) in &context.extra {
+        extra.push_str(&format!("\n  {}: {}", key, value));
+    }
+}
+
+/// Append the cwd listing, one entry per line: a trailing `/` marks a
+/// directory and `@` a symlink, then the size of files and the age.
+fn push_listing(extra: &mut String, context: &crate::protocol::Context) {
+    use crate::protocol::EntryKind;
+
+    let Some(listing) = &context.listing else {
+        return;
+    };
+    if listing.entries.is_empty() && listing.omitted == 0 {
+        extra.push_str("\nFiles in CWD: none");
+        return;
+    }
+    extra.push_str("\nFiles in CWD (newest first, with size and age):");
+    for entry in &listing.entries {
+        let mut line = entry.name.clone();
+        match entry.kind {
+            EntryKind::Dir => line.push('/'),
+            EntryKind::Symlink => line.push('@'),
+            EntryKind::File | EntryKind::Other => {}
+        }
+        if let Some(size) = entry.size {
+            line.push_str(&format!(" {}", human_size(size)));
+        }
+        if let Some(modified) = entry.modified {
+            line.push_str(&format!(
+                " {}",
+                human_age(listing.taken_at.saturating_sub(modified))
+            ));
+        }
+        extra.push_str(&format!("\n  {}", line));
+    }
+    if listing.omitted > 0 {
+        extra.push_str(&format!("\n  ... and {} more", listing.omitted));
+    }
+}
+
+/// `ls -h` style size: 512B, 1.2K, 34M.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["K", "M", "G", "T", "P"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// Coarse age: 45s, 12m, 5h, 3d, 6w, 2y.
+fn human_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3_599 => format!("{}m", secs / 60),
+        3_600..=86_399 => format!("{}h", secs / 3_600),
+        86_400..=1_209_599 => format!("{}d", secs / 86_400),
+        1_209_600..=31_535_999 => format!("{}w", secs / 604_800),
+        _ => format!("{}y", secs / 31_536_000),
+    }
+}
+
+/// Append the detected utility dialects, grouped by flavor, plus the
+/// portability traps that matter for non-GNU userlands.
+fn push_userland(extra: &mut String, context: &crate::protocol::Context) {
+    use crate::protocol::UtilityFlavor;
+
+    if context.userland.is_empty() {
+        return;
+    }
+    let mut by_flavor: BTreeMap<UtilityFlavor, Vec<&str>> = BTreeMap::new();
+    for (tool, flavor) in &context.userland {
+        by_flavor.entry(*flavor).or_default().push(tool);
+    }
+    let groups: Vec<String> = by_flavor
+        .iter()
+        .map(|(flavor, tools)| format!("{} ({})", flavor, tools.join(", ")))
+        .collect();
+    extra.push_str(&format!(
+        "\nUserland: {} - use flags these implementations support",
+        groups.join(", ")
+    ));
+    let is = |tool: &str, flavor: UtilityFlavor| context.userland.get(tool) == Some(&flavor);
+    if is("sed", UtilityFlavor::Bsd) {
+        extra.push_str("\n  BSD sed: in-place edits need `sed -i ''`");
+    }
+    if is("find", UtilityFlavor::Bsd) {
+        extra.push_str("\n  BSD find: no -printf; use -exec stat or -print0 | xargs -0");
+    }
+    if is("date", UtilityFlavor::Bsd) {
+        extra.push_str("\n  BSD date: no -d; use -v for offsets and -j -f to parse");
+    }
+    if is("stat", UtilityFlavor::Bsd) {
+        extra.push_str("\n  BSD stat: use -f with % formats, not -c");
+    }
+    if by_flavor.contains_key(&UtilityFlavor::Busybox) {
+        extra.push_str(
+            "\n  BusyBox applets: stick to common short flags; GNU long options are often missing",
+        );
+    }
+}
+
+/// Rules appended to the system prompt in fix mode. The base prompt still
+/// supplies the output rules and the context, so a fix follows the same
+/// shell policy as any other command.
+const FIX_RULES: &str = r#"Fix mode:
+- The user message is a command that just failed, with its exit status and stderr when available
+- Output ONLY the corrected command that does what the failed command was meant to do
+- Fix the actual cause: typos, wrong flags for this OS or userland, missing arguments, wrong paths or branch names
+- Keep everything that was not wrong, including quoting and pipes
+- If the failure is not caused by the command itself (network, permissions you cannot grant), output the original command unchanged"#;
+
+const EDIT_RULES: &str = r#"Edit mode:
+- The user message is a command the user already typed and a change to make to it
+- Output ONLY the rewritten command
+- Keep everything the change does not touch, including arguments, quoting, pipes and redirections
+- If the change asks for a different tool, translate the whole command to that tool"#;
+
+/// The user message for an edit request.
+pub fn edit_query(command: &str, change: &str) -> String {
+    format!("Command: {}\nChange: {}", command.trim(), change.trim())
+}
+
+/// The user message for a fix request.
+pub fn fix_query(failed: &crate::protocol::FailedCommand, hint: &str) -> String {
+    let mut query = format!("Failed command: {}", failed.command);
+    if let Some(code) = failed.exit_code {
+        query.push_str(&format!("\nExit status: {}", code));
+    }
+    if !failed.stderr.trim().is_empty() {
+        query.push_str(&format!("\nStderr:\n{}", failed.stderr.trim_end()));
+    }
+    if !hint.trim().is_empty() {
+        query.push_str(&format!("\nIntent: {}", hint.trim()));
+    }
+    query
+}
+
+/// The system prompt section describing piped or `--file` input.
+fn sample_section(sample: &crate::protocol::DataSample) -> String {
+    let mut section = match &sample.file {
+        Some(file) => format!(
+            "\n\nInput data: {} in the file {}\n\
+             - Pass that path to the command; do not read stdin",
+            sample.format, file
+        ),
+        None => format!(
+            "\n\nInput data: {} piped on stdin\n\
+             - The command receives this data on stdin; write a filter that reads stdin, with no file argument",
+            sample.format
+        ),
+    };
+    section.push_str("\n- Use the field names and columns exactly as they appear below");
+    if !sample.structure.is_empty() {
+        section.push_str("\nStructure:");
+        for line in &sample.structure {
+            section.push_str(&format!("\n  {}", line));
+        }
+    }
+    if !sample.head.is_empty() {
+        section.push_str(&format!("\nFirst lines:\n{}", sample.head));
+        if sample.truncated {
+            section.push_str("\n[...]");
+        }
+    }
+    section
+}
+
+/// Append the installed tools that are not built-in modern tools, and the
+/// `[context.tools]` descriptions of any tool the client found installed.
+fn push_tool_notes(extra: &mut String, context: &crate::protocol::Context, config: &ContextConfig) {
+    let notes: Vec<String> = context
+        .tools
+        .iter()
+        .filter_map(|tool| match config.tool_description(tool) {
+            Some(description) => Some(format!("\n  {}: {}", tool, description)),
+            None if !crate::context::is_modern_tool(tool) => Some(format!("\n  {}", tool)),
+            None => None,
+        })
+        .collect();
+    if !notes.is_empty() {
+        extra.push_str("\nInstalled tools:");
+        extra.push_str(&notes.concat());
+    }
+}
+
+/// Append the repository state, e.g. `Git: branch main (origin/main, ahead
+/// 2), 1 staged, rebase in progress`, plus remotes and recent branches.
+fn push_git(extra: &mut String, context: &crate::protocol::Context) {
+    let Some(git) = &context.git_state else {
+        // A client from before `git_state` sends only the summary.
+        if let Some(summary) = &context.git {
+            extra.push_str(&format!("\nGit: {}", summary));
+        }
+        return;
+    };
+    let mut head = match (&git.branch, &git.detached_at) {
+        (Some(branch), _) => format!("branch {}", branch),
+        (None, Some(commit)) => format!("detached HEAD at {}", commit),
+        (None, None) => "detached HEAD".to_string(),
+    };
+    if let Some(upstream) = &git.upstream {
+        let mut tracking = vec![upstream.clone()];
+        if git.ahead > 0 {
+            tracking.push(format!("ahead {}", git.ahead));
+        }
+        if git.behind > 0 {
+            tracking.push(format!("behind {}", git.behind));
+        }
+        head.push_str(&format!(" ({})", tracking.join(", ")));
+    }
+    let mut parts = vec![head];
+    if git.staged + git.unstaged + git.conflicted == 0 {
+        parts.push("clean".to_string());
+    }
+    for (count, label) in [
+        (git.staged, "staged"),
+        (git.unstaged, "unstaged"),
+        (git.conflicted, "conflicted"),
+    ] {
+        if count > 0 {
+            parts.push(format!("{} {}", count, label));
+        }
+    }
+    if let Some(operation) = &git.operation {
+        parts.push(format!("{} in progress", operation));
+    }
+    extra.push_str(&format!("\nGit: {}", parts.join(", ")));
+    if !git.remotes.is_empty() {
+        extra.push_str(&format!("\nGit remotes: {}", git.remotes.join(", ")));
+    }
+    if !git.recent_branches.is_empty() {
+        extra.push_str(&format!(
+            "\nRecent branches: {}",
+            git.recent_branches.join(", ")
+        ));
+    }
+}
+
+/// The `CWD:` line, absent when the client withheld the path.
+fn cwd_line(context: &crate::protocol::Context) -> String {
+    if context.cwd.as_os_str().is_empty() {
+        String::new()
+    } else {
+        format!("\nCWD: {}", context.cwd.display())
+    }
+}
+
+/// Append the cloud and cluster targets commands would act on.
+fn push_infra(extra: &mut String, context: &crate::protocol::Context) {
+    let infra = &context.infra;
+    let mut lines = Vec::new();
+    if let Some(kube) = &infra.kube_context {
+        let namespace = infra.kube_namespace.as_deref().unwrap_or("default");
+        lines.push(format!(
+            "kubectl context: {} (namespace {})",
+            kube, namespace
+        ));
+    }
+    match (&infra.aws_profile, &infra.aws_region) {
+        (Some(profile), Some(region)) => {
+            lines.push(format!("AWS profile: {} (region {})", profile, region))
+        }
+        (Some(profile), None) => lines.push(format!("AWS profile: {}", profile)),
+        (None, Some(region)) => lines.push(format!("AWS region: {}", region)),
+        (None, None) => {}
+    }
+    if let Some(project) = &infra.gcloud_project {
+        lines.push(format!("gcloud project: {}", project));
+    }
+    if let Some(docker) = &infra.docker_context {
+        lines.push(format!("docker context: {}", docker));
+    }
+    if !lines.is_empty() {
+        extra.push_str("\nInfra:");
+        for line in lines {
+            extra.push_str(&format!("\n  {}", line));
+        }
+    }
+}
+
+/// Append the project's runnable tasks so requests like "run the tests" map
+/// to real targets.
+fn push_project_tasks(extra: &mut String, context: &crate::protocol::Context) {
+    if !context.project_tasks.is_empty() {
+        extra.push_str("\nProject tasks (prefer these over generic commands):");
+        for line in &context.project_tasks {
+            extra.push_str(&format!("\n  {}", line));
+        }
+    }
+}
+
+fn push_aliases(extra: &mut String, context: &crate::protocol::Context) {
+    if !context.aliases.is_empty() {
+        extra.push_str(&format!("\nUser aliases: {}", context.aliases.join("; ")));
+    }
+}
+
+fn push_functions(extra: &mut String, context: &crate::protocol::Context) {
+    if !context.functions.is_empty() {
+        extra.push_str(&format!(
+            "\nUser shell functions: {}",
+            context.functions.join(", ")
+        ));
+    }
+}
+
+fn push_history(extra: &mut String, context: &crate::protocol::Context) {
+    if !context.history.is_empty() {
+        extra.push_str("\nRecent commands (oldest first):");
+        for command in &context.history {
+            extra.push_str(&format!("\n  {}", command));
+        }
+    }
+}
+
+/// The PowerShell prompt, in the same section layout as the POSIX one.
+fn windows_sections(
+    context: &crate::protocol::Context,
+    windows: &crate::protocol::WindowsContext,
+    config: &ContextConfig,
+) -> Vec<Section> {
+    let head = format!(
         r#"You are a Windows PowerShell command generator. Your ONLY output is one exact command to run.
 
 Rules:
@@ -534,17 +1541,40 @@ Context:
 OS: {} {} (build {})
 Shell: {}
 PowerShell: {}
-Elevated: {}
-CWD: {}{}"#,
+Elevated: {}{}"#,
         windows.caption,
         windows.version,
         windows.build,
         context.shell,
         windows.powershell_version,
         if windows.elevated { "yes" } else { "no" },
-        context.cwd.display(),
-        extra
-    )
+        cwd_line(context),
+    );
+    let diagnostic_tools = if windows.diagnostic_tools.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\nInstalled diagnostic tools: {}",
+            windows.diagnostic_tools.join(", ")
+        )
+    };
+    let mut tool_notes = String::new();
+    push_tool_notes(&mut tool_notes, context, config);
+
+    let mut sections = vec![
+        Section::new("rules", Priority::Required, head),
+        Section::new("diagnostic tools", Priority::High, diagnostic_tools),
+        section("projects", Priority::High, context, push_projects),
+        project_tasks_section(context),
+        Section::new("tool notes", Priority::Medium, tool_notes),
+        section("infra", Priority::High, context, push_infra),
+        git_section(context),
+        section("environment", Priority::High, context, push_env_flags),
+        listing_section(context),
+        section("site context", Priority::Medium, context, push_extra),
+    ];
+    sections.extend(user_sections(context));
+    sections
 }
 
 #[cfg(test)]
@@ -617,16 +1647,30 @@ mod tests {
         assert!(config.profiles.contains_key("fast"));
     }
 
-    fn ctx(projects: Vec<&str>, tools: Vec<&str>, git: Option<&str>) -> crate::protocol::Context {
+    fn ctx(
+        projects: Vec<&str>,
+        tools: Vec<&str>,
+        git: Option<crate::protocol::GitContext>,
+    ) -> crate::protocol::Context {
         crate::protocol::Context {
             cwd: "/work/demo".into(),
             shell: "/bin/zsh".to_string(),
             os: "Darwin 25.3.0".to_string(),
             distro: None,
             projects: projects.into_iter().map(String::from).collect(),
+            project_tasks: Vec::new(),
+            userland: BTreeMap::new(),
+            infra: Default::default(),
             tools: tools.into_iter().map(String::from).collect(),
-            git: git.map(String::from),
+            git: None,
+            git_state: git,
             env_flags: Vec::new(),
+            host: None,
+            listing: None,
+            extra: BTreeMap::new(),
+            history: Vec::new(),
+            aliases: Vec::new(),
+            functions: Vec::new(),
             windows: None,
         }
     }
@@ -637,13 +1681,26 @@ mod tests {
         let prompt = config.build_system_prompt(&ctx(
             vec!["rust"],
             vec!["rg", "fd"],
-            Some("branch main, dirty"),
+            Some(crate::protocol::GitContext {
+                branch: Some("main".to_string()),
+                unstaged: 2,
+                ..Default::default()
+            }),
         ));
         assert!(prompt.contains("Project: rust"));
-        assert!(prompt.contains("Git: branch main, dirty"));
+        assert!(!prompt.contains("Project tasks"));
+        assert!(prompt.contains("Git: branch main, 2 unstaged"));
         assert!(prompt.contains("installed modern tools over classic equivalents: rg, fd"));
     }
 
+    #[test]
+    fn prompt_renders_git_summary_from_older_clients() {
+        let mut context = ctx(vec![], vec![], None);
+        context.git = Some("branch main, dirty".to_string());
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(prompt.contains("\nGit: branch main, dirty"));
+    }
+
     #[test]
     fn prompt_omits_absent_context_sections() {
         let config = Config::default();
@@ -654,6 +1711,423 @@ mod tests {
         assert!(prompt.contains("Use modern tools when appropriate"));
     }
 
+    #[test]
+    fn prompt_lists_project_tasks() {
+        let mut context = ctx(vec!["node"], vec![], None);
+        context.project_tasks = vec!["npm scripts (npm run <name>): test:e2e".to_string()];
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(prompt.contains(
+            "Project tasks (prefer these over generic commands):\n  npm scripts (npm run <name>): test:e2e"
+        ));
+    }
+
+    #[test]
+    fn prompt_describes_configured_tools_that_are_installed() {
+        let mut config = Config::default();
+        config.context.tools.insert(
+            "deployctl".to_string(),
+            "Internal deploy CLI: deployctl <service> <env>".to_string(),
+        );
+        config
+            .context
+            .tools
+            .insert("helm".to_string(), "Kubernetes charts".to_string());
+        config
+            .context
+            .tools
+            .insert("aws".to_string(), String::new());
+        let prompt = config.build_system_prompt(&ctx(vec![], vec!["rg", "aws", "deployctl"], None));
+        assert!(prompt.contains(
+            "Installed tools:\n  aws\n  deployctl: Internal deploy CLI: deployctl <service> <env>"
+        ));
+        // Not installed, or installed without a description.
+        assert!(!prompt.contains("helm"));
+        assert!(!prompt.contains("aws:"));
+        // Configured tools are not classic-tool replacements.
+        assert!(prompt.contains("installed modern tools over classic equivalents: rg\n"));
+    }
+
+    #[test]
+    fn context_tables_are_validated() {
+        let mut context = ContextConfig::default();
+        context
+            .tools
+            .insert("terraform".to_string(), "IaC".to_string());
+        context
+            .markers
+            .insert("deploy/service.yaml".to_string(), "deploy".to_string());
+        assert!(context.validate().is_ok());
+
+        context.tools.insert("bin/tool".to_string(), String::new());
+        context
+            .tools
+            .insert("jq".to_string(), "line one\nline two".to_string());
+        context
+            .markers
+            .insert("../outside".to_string(), "x".to_string());
+        context
+            .markers
+            .insert("go.work".to_string(), "go workspace".to_string());
+        let error = context.validate().unwrap_err().to_string();
+        assert_eq!(error.lines().count(), 5, "{error}");
+        assert_eq!(context.valid_tools().count(), 1);
+        assert_eq!(context.valid_markers().count(), 1);
+    }
+
+    #[test]
+    fn prompt_renders_upstream_operation_remotes_and_branches() {
+        let git = crate::protocol::GitContext {
+            branch: Some("feat/login".to_string()),
+            upstream: Some("origin/feat/login".to_string()),
+            ahead: 2,
+            behind: 1,
+            staged: 1,
+            conflicted: 1,
+            operation: Some("rebase".to_string()),
+            remotes: vec!["origin".to_string(), "upstream".to_string()],
+            recent_branches: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let prompt = Config::default().build_system_prompt(&ctx(vec![], vec![], Some(git)));
+        assert!(prompt.contains(
+            "Git: branch feat/login (origin/feat/login, ahead 2, behind 1), 1 staged, 1 conflicted, rebase in progress\nGit remotes: origin, upstream\nRecent branches: main"
+        ), "{prompt}");
+
+        let detached = crate::protocol::GitContext {
+            detached_at: Some("1234567".to_string()),
+            ..Default::default()
+        };
+        let prompt = Config::default().build_system_prompt(&ctx(vec![], vec![], Some(detached)));
+        assert!(
+            prompt.ends_with("Git: detached HEAD at 1234567, clean"),
+            "{prompt}"
+        );
+    }
+
+    #[test]
+    fn fix_mode_extends_the_prompt_and_describes_the_failure() {
+        let context = ctx(vec![], vec![], None);
+        let config = Config::default();
+        let prompt = config.build_prompt(&context, PromptMode::Fix, None, &config.model_name());
+        assert!(prompt.starts_with(&config.build_system_prompt(&context)));
+        assert!(prompt.ends_with("output the original command unchanged"));
+
+        let failed = crate::protocol::FailedCommand {
+            command: "git pusj origin main".to_string(),
+            exit_code: Some(1),
+            stderr: "git: 'pusj' is not a git command.\n".to_string(),
+        };
+        assert_eq!(
+            fix_query(&failed, ""),
+            "Failed command: git pusj origin main\nExit status: 1\nStderr:\ngit: 'pusj' is not a git command."
+        );
+        let bare = crate::protocol::FailedCommand {
+            exit_code: None,
+            stderr: String::new(),
+            ..failed
+        };
+        assert_eq!(
+            fix_query(&bare, " push to the fork "),
+            "Failed command: git pusj origin main\nIntent: push to the fork"
+        );
+    }
+
+    #[test]
+    fn prompt_states_privileges_and_the_package_manager_to_use() {
+        let mut context = ctx(vec![], vec![], None);
+        context.host = Some(crate::protocol::HostContext {
+            root: true,
+            escalation: Vec::new(),
+            package_managers: vec!["apk".to_string()],
+        });
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(prompt.contains("\nUser: root - run admin commands without sudo"));
+        assert!(prompt.contains("\nPackage managers: apk - install packages with apk"));
+
+        context.host = Some(crate::protocol::HostContext {
+            root: false,
+            escalation: vec!["doas".to_string()],
+            package_managers: vec!["pacman".to_string(), "nix".to_string()],
+        });
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(prompt.contains("\nUser: regular user - prefix admin commands with doas"));
+        assert!(prompt.contains("\nPackage managers: pacman, nix - install packages with pacman"));
+
+        context.host = None;
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(!prompt.contains("User:") && !prompt.contains("Package managers:"));
+    }
+
+    #[test]
+    fn edit_mode_extends_the_prompt_and_pairs_command_with_change() {
+        let context = ctx(vec![], vec![], None);
+        let config = Config::default();
+        let prompt = config.build_prompt(&context, PromptMode::Edit, None, &config.model_name());
+        assert!(prompt.starts_with(&config.build_system_prompt(&context)));
+        assert!(prompt.contains("\n\nEdit mode:\n"));
+        assert_eq!(
+            edit_query("find . -name '*.log'\n", " use fd "),
+            "Command: find . -name '*.log'\nChange: use fd"
+        );
+    }
+
+    #[test]
+    fn sample_section_tells_stdin_from_a_file() {
+        let mut sample = crate::protocol::DataSample {
+            file: None,
+            format: crate::protocol::DataFormat::Json,
+            structure: vec![".items[].metadata.name (string)".to_string()],
+            head: "{\n  \"items\": [".to_string(),
+            truncated: true,
+        };
+        let section = sample_section(&sample);
+        assert!(section.starts_with("\n\nInput data: JSON piped on stdin\n"));
+        assert!(section.contains("\nStructure:\n  .items[].metadata.name (string)"));
+        assert!(section.ends_with("\nFirst lines:\n{\n  \"items\": [\n[...]"));
+
+        sample.file = Some("pods.json".to_string());
+        sample.truncated = false;
+        let section = sample_section(&sample);
+        assert!(section.starts_with("\n\nInput data: JSON in the file pods.json\n"));
+        assert!(!section.contains("[...]"));
+    }
+
+    #[test]
+    fn prompt_budget_comes_from_the_profile_or_the_backend() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.prompt_budget("qwen2.5-coder:1.5b"),
+            Some(DEFAULT_OLLAMA_PROMPT_TOKENS)
+        );
+        config.profiles.get_mut("fast").unwrap().prompt_tokens = Some(800);
+        assert_eq!(config.prompt_budget("qwen2.5-coder:1.5b"), Some(800));
+
+        config.backend = toml::from_str("type = \"openai\"").unwrap();
+        assert_eq!(config.prompt_budget("gpt-4o"), None);
+        assert_eq!(config.prompt_budget("qwen2.5-coder:1.5b"), Some(800));
+    }
+
+    #[test]
+    fn over_budget_prompts_shed_history_before_git() {
+        let mut context = ctx(
+            vec!["rust"],
+            vec![],
+            Some(crate::protocol::GitContext {
+                branch: Some("main".to_string()),
+                remotes: vec!["origin".to_string()],
+                ..Default::default()
+            }),
+        );
+        context.history = (0..200)
+            .map(|i| format!("cargo test --package crate-{} -- --nocapture", i))
+            .collect();
+        let mut config = Config::default();
+        let model = config.model_name();
+        let cloud = Config {
+            backend: toml::from_str("type = \"openai\"").unwrap(),
+            ..Config::default()
+        };
+        let full = cloud.build_prompt(&context, PromptMode::Fix, None, "gpt-4o");
+        assert!(full.contains("crate-0 "));
+
+        config.profiles.get_mut("default").unwrap().prompt_tokens = Some(1_000);
+        let prompt = config.build_prompt(&context, PromptMode::Fix, None, &model);
+        assert!(crate::prompt_budget::estimate_tokens(&prompt) <= 1_000);
+        assert!(prompt.contains("\nGit: branch main, clean\nGit remotes: origin"));
+        assert!(prompt.contains("\nProject: rust"));
+        assert!(prompt.contains("\n\nFix mode:\n"));
+        // The shortened history keeps the newest commands.
+        assert!(!prompt.contains("crate-0 "));
+        assert!(prompt.contains("crate-199 "));
+
+        // A budget below the rules alone still yields them.
+        config.profiles.get_mut("default").unwrap().prompt_tokens = Some(1);
+        let prompt = config.build_prompt(&context, PromptMode::Generate, None, &model);
+        assert!(prompt.starts_with("You are a shell command generator."));
+        assert!(!prompt.contains("Git:") && !prompt.contains("Recent commands"));
+    }
+
+    #[test]
+    fn providers_parse_validate_and_render() {
+        let config: Config = toml::from_str(
+            r#"
+[[context.providers]]
+command = "/usr/local/bin/which-cluster"
+args = ["--short"]
+
+[[context.providers]]
+command = " "
+timeout_ms = 200
+"#,
+        )
+        .unwrap();
+        let providers = &config.context.providers;
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[0].args, vec!["--short"]);
+        assert_eq!(providers[0].timeout_ms, 50);
+        assert_eq!(providers[1].timeout_ms, 200);
+        let error = config.context.validate().unwrap_err().to_string();
+        assert!(error.contains("providers: an entry has an empty command"));
+
+        let mut context = ctx(vec![], vec![], None);
+        assert!(!config
+            .build_system_prompt(&context)
+            .contains("Site context:"));
+        context.extra = BTreeMap::from([
+            ("cluster".to_string(), "prod-eu-2".to_string()),
+            ("vpn".to_string(), "corp".to_string()),
+        ]);
+        assert!(config
+            .build_system_prompt(&context)
+            .contains("\nSite context:\n  cluster: prod-eu-2\n  vpn: corp"));
+        on_windows(&mut context);
+        let prompt = config.build_system_prompt(&context);
+        assert!(prompt.starts_with("You are a Windows PowerShell command generator."));
+        assert!(
+            prompt.contains("\nSite context:\n  cluster: prod-eu-2\n  vpn: corp"),
+            "{prompt}"
+        );
+    }
+
+    #[test]
+    fn listing_renders_one_compact_line_per_entry() {
+        use crate::protocol::{DirListing, EntryKind, ListingEntry};
+
+        let mut context = ctx(vec![], vec![], None);
+        assert!(!Config::default()
+            .build_system_prompt(&context)
+            .contains("Files in CWD"));
+
+        let entry = |name: &str, kind, size, age: u64| ListingEntry {
+            name: name.to_string(),
+            kind,
+            size,
+            modified: Some(10_000_000 - age),
+        };
+        context.listing = Some(DirListing {
+            entries: vec![
+                entry("app.log", EntryKind::File, Some(1_300_000), 5 * 3_600),
+                entry("photos", EntryKind::Dir, None, 2 * 86_400),
+                entry("current", EntryKind::Symlink, None, 30),
+                entry("notes.txt", EntryKind::File, Some(512), 40 * 86_400),
+            ],
+            omitted: 12,
+            taken_at: 10_000_000,
+        });
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(
+            prompt.contains(
+                "\nFiles in CWD (newest first, with size and age):\n  app.log 1.2M 5h\n  photos/ 2d\n  current@ 30s\n  notes.txt 512B 5w\n  ... and 12 more"
+            ),
+            "{}",
+            prompt
+        );
+    }
+
+    #[test]
+    fn a_short_listing_that_costs_more_is_not_offered() {
+        use crate::protocol::{DirListing, EntryKind, ListingEntry};
+
+        let mut context = ctx(vec![], vec![], None);
+        context.listing = Some(DirListing {
+            entries: (0..6)
+                .map(|i| ListingEntry {
+                    name: format!("screenshot{:02}", i),
+                    kind: EntryKind::File,
+                    size: None,
+                    modified: None,
+                })
+                .collect(),
+            omitted: 0,
+            taken_at: 10_000_000,
+        });
+        let section = listing_section(&context);
+        assert!(section.short.is_none(), "{:?}", section);
+
+        let mut config = Config::default();
+        let model = config.model_name();
+        config.profiles.get_mut("default").unwrap().prompt_tokens = Some(1);
+        let prompt = config.build_prompt(&context, PromptMode::Generate, None, &model);
+        assert!(!prompt.contains("screenshot"), "{prompt}");
+    }
+
+    #[test]
+    fn prompt_omits_a_withheld_cwd() {
+        let mut context = ctx(vec![], vec![], None);
+        assert!(Config::default()
+            .build_system_prompt(&context)
+            .contains("\nCWD: /work/demo"));
+        context.cwd = PathBuf::new();
+        assert!(!Config::default()
+            .build_system_prompt(&context)
+            .contains("CWD:"));
+    }
+
+    #[test]
+    fn prompt_renders_infra_targets() {
+        let mut context = ctx(vec![], vec![], None);
+        assert!(!Config::default()
+            .build_system_prompt(&context)
+            .contains("Infra:"));
+        context.infra.kube_context = Some("prod-eu".to_string());
+        context.infra.aws_region = Some("eu-west-1".to_string());
+        context.infra.docker_context = Some("colima".to_string());
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(
+            prompt.contains(
+                "Infra:\n  kubectl context: prod-eu (namespace default)\n  AWS region: eu-west-1\n  docker context: colima"
+            ),
+            "{prompt}"
+        );
+    }
+
+    #[test]
+    fn prompt_targets_the_detected_userland() {
+        use crate::protocol::UtilityFlavor;
+
+        let mut context = ctx(vec![], vec![], None);
+        for tool in ["date", "sed"] {
+            context
+                .userland
+                .insert(tool.to_string(), UtilityFlavor::Bsd);
+        }
+        context
+            .userland
+            .insert("grep".to_string(), UtilityFlavor::Gnu);
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(
+            prompt.contains("Userland: GNU (grep), BSD (date, sed)"),
+            "{prompt}"
+        );
+        assert!(prompt.contains("`sed -i ''`"));
+        assert!(prompt.contains("BSD date: no -d"));
+        assert!(!prompt.contains("BSD find"));
+        assert!(!prompt.contains("BusyBox"));
+    }
+
+    #[test]
+    fn prompt_renders_opt_in_history_and_aliases() {
+        let mut context = ctx(vec![], vec![], None);
+        context.history = vec!["cargo build".to_string(), "git status".to_string()];
+        context.aliases = vec!["glog='git log --graph'".to_string()];
+        context.functions = vec!["mkcd".to_string()];
+        let prompt = Config::default().build_system_prompt(&context);
+        assert!(prompt.contains("User shell functions: mkcd"));
+        assert!(prompt.contains("User aliases: glog='git log --graph'"));
+        assert!(prompt.contains("Recent commands (oldest first):\n  cargo build\n  git status"));
+    }
+
+    #[test]
+    fn history_section_defaults_off() {
+        let config: Config = toml::from_str("[context.history]\nlimit = 5\n").unwrap();
+        assert!(!config.context.history.enabled);
+        assert_eq!(config.context.history.limit, 5);
+        assert!(config.context.history.default_redactions);
+        let config: Config =
+            toml::from_str("[context.history]\nenabled = true\nmode = \"aliases-only\"\n").unwrap();
+        assert_eq!(config.context.history.mode, HistoryMode::AliasesOnly);
+    }
+
     #[test]
     fn prompt_respects_posix_preference_over_probe() {
         let mut config = Config::default();
@@ -687,9 +2161,8 @@ CWD: /work/demo"#
         );
     }
 
-    #[test]
-    fn windows_context_selects_powershell_policy() {
-        let mut context = ctx(vec!["rust"], vec![], Some("branch main, clean"));
+    /// Turn `context` into one gathered by pwsh on Windows 11.
+    fn on_windows(context: &mut crate::protocol::Context) {
         context.shell = "pwsh".to_string();
         context.os = "Microsoft Windows 11 Pro 10.0.26100 (build 26100)".to_string();
         context.windows = Some(crate::protocol::WindowsContext {
@@ -704,6 +2177,29 @@ CWD: /work/demo"#
                 "wpr.exe".to_string(),
             ],
         });
+    }
+
+    #[test]
+    fn windows_context_selects_powershell_policy() {
+        let mut context = ctx(
+            vec!["rust"],
+            vec![],
+            Some(crate::protocol::GitContext {
+                branch: Some("main".to_string()),
+                ..Default::default()
+            }),
+        );
+        on_windows(&mut context);
+        context.listing = Some(crate::protocol::DirListing {
+            entries: vec![crate::protocol::ListingEntry {
+                name: "build.ps1".to_string(),
+                kind: crate::protocol::EntryKind::File,
+                size: Some(512),
+                modified: Some(10_000_000 - 30),
+            }],
+            omitted: 0,
+            taken_at: 10_000_000,
+        });
 
         let prompt = Config::default().build_system_prompt(&context);
         for required in [
@@ -722,6 +2218,7 @@ CWD: /work/demo"#
             "PowerShell: 7.4.6",
             "Elevated: no",
             "Installed diagnostic tools: pwsh.exe, pnputil.exe, wpr.exe",
+            "\nFiles in CWD (newest first, with size and age):\n  build.ps1 512B 30s",
         ] {
             assert!(
                 prompt.contains(required),
@@ -760,6 +2257,46 @@ verbose_flags = true
         assert!(!config.preferences.modern_tools);
     }
 
+    #[test]
+    fn exec_backend_deserializes_with_default_args() {
+        let toml = r#"
+[backend]
+type = "exec"
+command = "/opt/gateway/incant-plugin"
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let BackendConfig::Exec {
+            command,
+            args,
+            default_profile,
+            ..
+        } = &config.backend
+        else {
+            panic!("expected exec backend");
+        };
+        assert_eq!(command, "/opt/gateway/incant-plugin");
+        assert!(args.is_empty());
+        assert_eq!(default_profile, "default");
+        assert_eq!(config.backend_type(), "exec");
+        assert!(!config.record());
+    }
+
+    #[test]
+    fn record_flag_and_replay_backend_deserialize() {
+        let config: Config =
+            toml::from_str("[backend]\ntype = \"openai\"\nrecord = true\n").unwrap();
+        assert!(config.record());
+
+        let config: Config =
+            toml::from_str("[backend]\ntype = \"replay\"\ncassette = \"tests/demo.jsonl\"\n")
+                .unwrap();
+        assert!(matches!(
+            &config.backend,
+            BackendConfig::Replay { cassette: Some(path), .. } if path == std::path::Path::new("tests/demo.jsonl")
+        ));
+        assert!(!config.record());
+    }
+
     #[test]
     fn test_model_selection_explicit_model() {
         let config = Config::default();
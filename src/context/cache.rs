@@ -0,0 +1,227 @@
+//! Stable facts cached across invocations in the runtime dir.
+//!
+//! The OS string, the distro, the PATH probe, and the utility flavors cost
+//! a process spawn or a scan of every PATH directory, and they almost never
+//! change. Each is stored in its own small file in the per-user runtime dir
+//! next to the daemon socket, under a key that changes when the answer
+//! could. The PATH and userland keys cover PATH and the modification times
+//! of what it points at, so installing a tool invalidates them. The OS and
+//! distro are keyed by the boot (a kernel or OS upgrade means a reboot).
+//! A file keeps the last few keys, so switching between shells with
+//! different PATHs does not recompute on every query.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+#[cfg(not(windows))]
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Entries kept per cache file, most recently computed first.
+const MAX_ENTRIES: usize = 8;
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    value: T,
+}
+
+/// `compute()`, or its cached value if `name` holds one for `key`. An
+/// empty key means the answer cannot be validated, so it is computed and
+/// not stored. Any failure to read or write the cache falls back to
+/// computing.
+pub fn cached<T: Serialize + DeserializeOwned>(
+    name: &str,
+    key: &str,
+    compute: impl FnOnce() -> T,
+) -> T {
+    match crate::config::Config::runtime_dir() {
+        Ok(dir) if !key.is_empty() => cached_in(&dir, name, key, compute),
+        _ => compute(),
+    }
+}
+
+fn cached_in<T: Serialize + DeserializeOwned>(
+    dir: &Path,
+    name: &str,
+    key: &str,
+    compute: impl FnOnce() -> T,
+) -> T {
+    let path = dir.join(format!("incant.{}.json", name));
+    let mut entries: Vec<Entry<T>> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    if let Some(index) = entries.iter().position(|entry| entry.key == key) {
+        return entries.swap_remove(index).value;
+    }
+    entries.insert(
+        0,
+        Entry {
+            key: key.to_string(),
+            value: compute(),
+        },
+    );
+    entries.truncate(MAX_ENTRIES);
+    if let Ok(bytes) = serde_json::to_vec(&entries) {
+        let _ = crate::transport::write_private_file(&path, &bytes);
+    }
+    entries.swap_remove(0).value
+}
+
+/// Builds cache keys with 64-bit FNV-1a, like the replay cassettes. The
+/// std hasher is avoided because its output may change between Rust
+/// releases, and a key must mean the same thing to every build.
+#[derive(Clone, Copy)]
+pub struct KeyHasher(u64);
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        KeyHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl KeyHasher {
+    /// Add one field. A separator after it keeps `"ab", "c"` apart from
+    /// `"a", "bc"`.
+    pub fn field(mut self, bytes: impl AsRef<[u8]>) -> Self {
+        for byte in bytes.as_ref().iter().chain(&[0xff]) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        self
+    }
+
+    /// Add the modification time of `path`, or zero when it has none.
+    pub fn mtime(self, path: &Path) -> Self {
+        let nanos = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_nanos());
+        self.field(nanos.to_le_bytes())
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Cache key for facts that only change with a reboot or an OS upgrade:
+/// the Linux boot id, the modification time of the macOS version file, or
+/// elsewhere on Unix the kernel's name, release, and build. Empty when
+/// none of them is available, so nothing is cached.
+pub fn os_key() -> String {
+    if let Ok(boot_id) = std::fs::read_to_string("/proc/sys/kernel/random/boot_id") {
+        return boot_id.trim().to_string();
+    }
+    let version_file = Path::new("/System/Library/CoreServices/SystemVersion.plist");
+    if version_file.exists() {
+        return KeyHasher::default().mtime(version_file).finish();
+    }
+    kernel_build().unwrap_or_default()
+}
+
+/// `uname -srv`: changes with every kernel update on the BSDs, whose
+/// userland is upgraded together with the kernel.
+#[cfg(unix)]
+fn kernel_build() -> Option<String> {
+    // SAFETY: uname fills the zeroed struct with NUL-terminated strings.
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+    let field = |chars: &[libc::c_char]| {
+        // SAFETY: each field is NUL-terminated within its array.
+        unsafe { std::ffi::CStr::from_ptr(chars.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Some(format!(
+        "{} {} {}",
+        field(&name.sysname),
+        field(&name.release),
+        field(&name.version)
+    ))
+}
+
+#[cfg(not(unix))]
+fn kernel_build() -> Option<String> {
+    None
+}
+
+/// Cache key for a PATH probe: PATH itself, the names probed, and the
+/// modification time of every PATH directory.
+#[cfg(not(windows))]
+pub fn path_key(path: &OsStr, probes: &[String]) -> String {
+    let mut key = KeyHasher::default()
+        .field(path.as_encoded_bytes())
+        .field(probes.len().to_le_bytes());
+    for probe in probes {
+        key = key.field(probe);
+    }
+    for dir in std::env::split_paths(path) {
+        key = key.mtime(&dir);
+    }
+    key.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_a_matching_entry_and_recomputes_on_a_new_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let first: Vec<String> = cached_in(dir.path(), "probe", "a", || vec!["rg".to_string()]);
+        assert_eq!(first, vec!["rg"]);
+        let hit: Vec<String> = cached_in(dir.path(), "probe", "a", || unreachable!());
+        assert_eq!(hit, vec!["rg"]);
+        let miss: Vec<String> = cached_in(dir.path(), "probe", "b", Vec::new);
+        assert!(miss.is_empty());
+
+        // Older keys stay until MAX_ENTRIES newer ones push them out.
+        let hit: Vec<String> = cached_in(dir.path(), "probe", "a", || unreachable!());
+        assert_eq!(hit, vec!["rg"]);
+        for key in 0..MAX_ENTRIES {
+            let _: Vec<String> = cached_in(dir.path(), "probe", &key.to_string(), Vec::new);
+        }
+        let evicted: Vec<String> = cached_in(dir.path(), "probe", "a", || vec!["fd".to_string()]);
+        assert_eq!(evicted, vec!["fd"]);
+
+        // A corrupt file is recomputed, not an error.
+        std::fs::write(dir.path().join("incant.probe.json"), "{").unwrap();
+        let fresh: Vec<String> = cached_in(dir.path(), "probe", "b", || vec!["fd".to_string()]);
+        assert_eq!(fresh, vec!["fd"]);
+    }
+
+    #[test]
+    fn keys_are_stable_and_field_separated() {
+        // Pinned: a key must not change between builds.
+        assert_eq!(KeyHasher::default().field("a").finish(), "089bc907b544c769");
+        assert_ne!(
+            KeyHasher::default().field("ab").field("c").finish(),
+            KeyHasher::default().field("a").field("bc").finish()
+        );
+        assert!(!os_key().is_empty());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn path_key_changes_when_a_path_directory_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        std::fs::create_dir(&bin).unwrap();
+        let path = std::env::join_paths([&bin]).unwrap();
+        let probes = vec!["rg".to_string()];
+        let before = path_key(&path, &probes);
+        assert_eq!(before, path_key(&path, &probes));
+        assert_ne!(before, path_key(&path, &["fd".to_string()]));
+
+        // Directory mtimes can have coarse resolution; set one explicitly.
+        let file = std::fs::File::open(&bin).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_ne!(before, path_key(&path, &probes));
+    }
+}
@@ -0,0 +1,302 @@
+//! Git repository state.
+//!
+//! One `git status --porcelain=v2 --branch -uno` call gives the branch,
+//! upstream, ahead/behind counts, and staged/unstaged/conflicted counts.
+//! The rest comes from plain reads of the `.git` directory: the state files
+//! that mark a merge, rebase, cherry-pick, revert, am, or bisect in
+//! progress, the remotes in `config`, and recently checked-out branches
+//! from the HEAD reflog.
+
+use crate::protocol::GitContext;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Most recent branches listed.
+const MAX_RECENT_BRANCHES: usize = 5;
+/// Tail of the HEAD reflog read when looking for recent branches.
+const REFLOG_TAIL_BYTES: u64 = 64 * 1024;
+
+/// Git state of `dir`. Returns None outside a repository or when git is
+/// unavailable.
+pub fn git_context(dir: &Path) -> Option<GitContext> {
+    // A status that outlives the context budget is abandoned, so it must
+    // not take the index lock.
+    let output = std::process::Command::new("git")
+        .arg("--no-optional-locks")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "-uno",
+            "--no-renames",
+        ])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None; // not a repo
+    }
+
+    let mut git = parse_status(&String::from_utf8_lossy(&output.stdout))?;
+    if let Some(git_dir) = find_git_dir(dir) {
+        let common_dir = common_dir(&git_dir);
+        git.operation = operation(&git_dir).map(str::to_string);
+        git.remotes = remotes(&common_dir);
+        git.recent_branches = recent_branches(&git_dir, &common_dir, git.branch.as_deref());
+    }
+    Some(git)
+}
+
+/// Parse `git status --porcelain=v2 --branch` output.
+fn parse_status(text: &str) -> Option<GitContext> {
+    let mut git = GitContext::default();
+    let mut oid = None;
+    let mut detached = None;
+    for line in text.lines() {
+        if let Some(head) = line.strip_prefix("# branch.head ") {
+            detached = Some(head == "(detached)");
+            if head != "(detached)" {
+                git.branch = Some(head.to_string());
+            }
+        } else if let Some(value) = line.strip_prefix("# branch.oid ") {
+            oid = Some(value);
+        } else if let Some(upstream) = line.strip_prefix("# branch.upstream ") {
+            git.upstream = Some(upstream.to_string());
+        } else if let Some(counts) = line.strip_prefix("# branch.ab ") {
+            for count in counts.split_whitespace() {
+                if let Some(n) = count.strip_prefix('+') {
+                    git.ahead = n.parse().unwrap_or(0);
+                } else if let Some(n) = count.strip_prefix('-') {
+                    git.behind = n.parse().unwrap_or(0);
+                }
+            }
+        } else if line.starts_with("u ") {
+            git.conflicted += 1;
+        } else if let Some(rest) = line.strip_prefix("1 ").or_else(|| line.strip_prefix("2 ")) {
+            let mut xy = rest.chars();
+            if xy.next().is_some_and(|x| x != '.') {
+                git.staged += 1;
+            }
+            if xy.next().is_some_and(|y| y != '.') {
+                git.unstaged += 1;
+            }
+        }
+    }
+    if detached? {
+        git.detached_at = oid
+            .filter(|oid| *oid != "(initial)")
+            .map(|oid| oid.chars().take(7).collect());
+    }
+    Some(git)
+}
+
+/// The `.git` directory for `dir`, following the `gitdir:` file that
+/// worktrees and submodules use.
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let dot_git = ancestor.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if dot_git.is_file() {
+            let text = std::fs::read_to_string(&dot_git).ok()?;
+            let target = text.trim().strip_prefix("gitdir:")?.trim();
+            return Some(ancestor.join(target));
+        }
+    }
+    None
+}
+
+/// The directory holding shared state (config, refs) for a worktree.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    std::fs::read_to_string(git_dir.join("commondir"))
+        .map(|common| git_dir.join(common.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf())
+}
+
+/// The operation in progress, judged by the state files git leaves behind.
+fn operation(git_dir: &Path) -> Option<&'static str> {
+    let exists = |name: &str| git_dir.join(name).exists();
+    if exists("rebase-merge") {
+        Some("rebase")
+    } else if exists("rebase-apply") {
+        Some(if exists("rebase-apply/applying") {
+            "am"
+        } else {
+            "rebase"
+        })
+    } else if exists("MERGE_HEAD") {
+        Some("merge")
+    } else if exists("CHERRY_PICK_HEAD") {
+        Some("cherry-pick")
+    } else if exists("REVERT_HEAD") {
+        Some("revert")
+    } else if exists("BISECT_LOG") {
+        Some("bisect")
+    } else {
+        None
+    }
+}
+
+/// Remote names from `[remote "name"]` sections of the repo config.
+fn remotes(common_dir: &Path) -> Vec<String> {
+    let Ok(config) = std::fs::read_to_string(common_dir.join("config")) else {
+        return Vec::new();
+    };
+    let mut remotes = Vec::new();
+    for line in config.lines() {
+        let Some(name) = line
+            .trim()
+            .strip_prefix("[remote \"")
+            .and_then(|rest| rest.strip_suffix("\"]"))
+        else {
+            continue;
+        };
+        if !remotes.iter().any(|r| r == name) {
+            remotes.push(name.to_string());
+        }
+    }
+    remotes
+}
+
+/// Local branches from `checkout: moving from A to B` reflog entries, most
+/// recent first, skipping the current branch and deleted ones.
+fn recent_branches(git_dir: &Path, common_dir: &Path, current: Option<&str>) -> Vec<String> {
+    let Some(reflog) = read_tail(&git_dir.join("logs/HEAD"), REFLOG_TAIL_BYTES) else {
+        return Vec::new();
+    };
+    let packed = std::fs::read_to_string(common_dir.join("packed-refs")).unwrap_or_default();
+    let exists = |branch: &str| {
+        common_dir.join("refs/heads").join(branch).is_file()
+            || packed.lines().any(|line| {
+                line.strip_suffix(branch)
+                    .is_some_and(|l| l.ends_with(" refs/heads/"))
+            })
+    };
+
+    let mut branches: Vec<String> = Vec::new();
+    for line in reflog.lines().rev() {
+        let Some((_, message)) = line.split_once('\t') else {
+            continue;
+        };
+        let Some(moves) = message.strip_prefix("checkout: moving from ") else {
+            continue;
+        };
+        let Some((from, to)) = moves.split_once(" to ") else {
+            continue;
+        };
+        for name in [to, from] {
+            if Some(name) != current && !branches.iter().any(|b| b == name) && exists(name) {
+                branches.push(name.to_string());
+            }
+        }
+        if branches.len() >= MAX_RECENT_BRANCHES {
+            break;
+        }
+    }
+    branches.truncate(MAX_RECENT_BRANCHES);
+    branches
+}
+
+/// The last `max` bytes of `path`, starting at a line boundary.
+fn read_tail(path: &Path, max: u64) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max))).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    if len > max {
+        // Drop the partial first line.
+        return text.split_once('\n').map(|(_, rest)| rest.to_string());
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_branch_upstream_and_change_counts() {
+        let status = "\
+# branch.oid 1234567890abcdef1234567890abcdef12345678
+# branch.head feat/login
+# branch.upstream origin/feat/login
+# branch.ab +2 -1
+1 M. N... 100644 100644 100644 aaa bbb src/a.rs
+1 .M N... 100644 100644 100644 aaa bbb src/b.rs
+1 MM N... 100644 100644 100644 aaa bbb src/c.rs
+u UU N... 100644 100644 100644 100644 aaa bbb ccc src/d.rs
+";
+        let git = parse_status(status).unwrap();
+        assert_eq!(git.branch.as_deref(), Some("feat/login"));
+        assert_eq!(git.detached_at, None);
+        assert_eq!(git.upstream.as_deref(), Some("origin/feat/login"));
+        assert_eq!((git.ahead, git.behind), (2, 1));
+        assert_eq!((git.staged, git.unstaged, git.conflicted), (2, 2, 1));
+
+        let detached =
+            parse_status("# branch.oid 1234567890abcdef\n# branch.head (detached)\n").unwrap();
+        assert_eq!(detached.branch, None);
+        assert_eq!(detached.detached_at.as_deref(), Some("1234567"));
+        assert_eq!(parse_status("1 M. N... x\n"), None);
+    }
+
+    #[test]
+    fn git_context_outside_repo_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(git_context(dir.path()), None);
+    }
+
+    #[test]
+    fn git_context_reads_state_files_remotes_and_reflog() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.email=t@t", "-c", "user.name=t"])
+                .args(args)
+                .env("GIT_CONFIG_GLOBAL", "/dev/null")
+                .env("GIT_CONFIG_SYSTEM", "/dev/null")
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        run(&["init", "-q", "-b", "trunk"]);
+        run(&["commit", "-q", "--allow-empty", "-m", "init"]);
+        run(&["remote", "add", "origin", "https://example.com/repo.git"]);
+        run(&["remote", "add", "upstream", "https://example.com/up.git"]);
+        run(&["checkout", "-q", "-b", "feat/a"]);
+        run(&["checkout", "-q", "-b", "feat/b"]);
+        run(&["checkout", "-q", "trunk"]);
+        run(&["branch", "-q", "-D", "feat/a"]);
+
+        let git = git_context(dir.path()).unwrap();
+        assert_eq!(git.branch.as_deref(), Some("trunk"));
+        assert_eq!((git.staged, git.unstaged), (0, 0));
+        assert_eq!(git.operation, None);
+        assert_eq!(git.remotes, vec!["origin", "upstream"]);
+        assert_eq!(git.recent_branches, vec!["feat/b"]);
+
+        // Tracked-file modification counts as unstaged, then staged.
+        std::fs::write(dir.path().join("f.txt"), "one").unwrap();
+        run(&["add", "f.txt"]);
+        run(&["commit", "-q", "-m", "add f"]);
+        std::fs::write(dir.path().join("f.txt"), "two").unwrap();
+        assert_eq!(git_context(dir.path()).unwrap().unstaged, 1);
+        run(&["add", "f.txt"]);
+        assert_eq!(git_context(dir.path()).unwrap().staged, 1);
+
+        std::fs::write(dir.path().join(".git/MERGE_HEAD"), "").unwrap();
+        let nested = dir.path().join("src");
+        std::fs::create_dir(&nested).unwrap();
+        assert_eq!(
+            git_context(&nested).unwrap().operation.as_deref(),
+            Some("merge")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Cloud and cluster targets.
+//!
+//! "Scale the api deployment" means something different in the `prod`
+//! kube context than in `kind-dev`. This module reports which cluster,
+//! namespace, AWS profile and region, gcloud project, and docker context the
+//! user's CLIs would act on. It reads the same files and environment
+//! variables those CLIs read and never runs them: `kubectl config view`
+//! alone can take longer than a whole query.
+//!
+//! Each source is opt-in under `[context.infra]`.
+
+use crate::config::InfraConfig;
+use crate::protocol::InfraContext;
+use std::path::{Path, PathBuf};
+
+/// Largest config file read. Kubeconfigs with embedded certificates run to
+/// tens of KiB; anything past this is skipped.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Gather the enabled sources from the real environment.
+pub fn gather(config: &InfraConfig) -> InfraContext {
+    let Some(home) = dirs::home_dir() else {
+        return InfraContext::default();
+    };
+    gather_from(config, &|name| std::env::var(name).ok(), &home)
+}
+
+/// [`gather`] with the environment and home directory passed in.
+fn gather_from(
+    config: &InfraConfig,
+    env: &dyn Fn(&str) -> Option<String>,
+    home: &Path,
+) -> InfraContext {
+    let env = |name: &str| env(name).filter(|value| !value.trim().is_empty());
+    let mut infra = InfraContext::default();
+
+    if config.kubernetes {
+        let files = match env("KUBECONFIG") {
+            Some(list) => std::env::split_paths(&list).collect(),
+            None => vec![home.join(".kube/config")],
+        };
+        if let Some((context, namespace)) = kube_target(&files) {
+            infra.kube_context = Some(context);
+            infra.kube_namespace = namespace;
+        }
+    }
+
+    if config.aws {
+        let profile = env("AWS_PROFILE").or_else(|| env("AWS_DEFAULT_PROFILE"));
+        infra.aws_region = env("AWS_REGION")
+            .or_else(|| env("AWS_DEFAULT_REGION"))
+            .or_else(|| {
+                let file = env("AWS_CONFIG_FILE")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| home.join(".aws/config"));
+                let section = match profile.as_deref() {
+                    None | Some("default") => "default".to_string(),
+                    Some(name) => format!("profile {}", name),
+                };
+                ini_value(&read(&file)?, &section, "region")
+            });
+        infra.aws_profile = profile;
+    }
+
+    if config.gcloud {
+        infra.gcloud_project = env("CLOUDSDK_CORE_PROJECT").or_else(|| {
+            let dir = env("CLOUDSDK_CONFIG")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config/gcloud"));
+            let active = env("CLOUDSDK_ACTIVE_CONFIG_NAME")
+                .or_else(|| read(&dir.join("active_config")))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_else(|| "default".to_string());
+            let text = read(
+                &dir.join("configurations")
+                    .join(format!("config_{}", active)),
+            )?;
+            ini_value(&text, "core", "project")
+        });
+    }
+
+    if config.docker {
+        infra.docker_context = env("DOCKER_CONTEXT").or_else(|| {
+            let dir = env("DOCKER_CONFIG")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".docker"));
+            let config: serde_json::Value =
+                serde_json::from_str(&read(&dir.join("config.json"))?).ok()?;
+            config
+                .get("currentContext")?
+                .as_str()
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        });
+    }
+
+    infra
+}
+
+fn read(path: &Path) -> Option<String> {
+    if std::fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+/// The current context and its namespace across the kubeconfig files.
+/// Like kubectl, the first file that sets `current-context` wins, and the
+/// context is looked up in every file.
+fn kube_target(files: &[PathBuf]) -> Option<(String, Option<String>)> {
+    let texts: Vec<String> = files.iter().filter_map(|f| read(f)).collect();
+    let current = texts.iter().find_map(|text| {
+        text.lines()
+            .find_map(|line| line.strip_prefix("current-context:"))
+            .map(yaml_scalar)
+            .filter(|name| !name.is_empty())
+    })?;
+    let namespace = texts
+        .iter()
+        .find_map(|text| {
+            kube_contexts(text)
+                .into_iter()
+                .find(|(name, _)| *name == current)
+        })
+        .and_then(|(_, namespace)| namespace);
+    Some((current, namespace))
+}
+
+/// (name, namespace) of each entry in the top-level `contexts:` list.
+///
+/// A line-based reading: kubeconfig is machine-written YAML, and the only
+/// structure needed is "which list item does this key belong to".
+fn kube_contexts(text: &str) -> Vec<(String, Option<String>)> {
+    let mut contexts = Vec::new();
+    let mut in_contexts = false;
+    let mut current: Option<(Option<String>, Option<String>)> = None;
+    let mut flush = |item: Option<(Option<String>, Option<String>)>| {
+        if let Some((Some(name), namespace)) = item {
+            contexts.push((name, namespace));
+        }
+    };
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let top_level = line.len() == trimmed.len();
+        if top_level && !trimmed.starts_with('-') {
+            in_contexts = trimmed.trim_end() == "contexts:";
+            flush(current.take());
+            continue;
+        }
+        if !in_contexts {
+            continue;
+        }
+        let entry = match trimmed.strip_prefix('-') {
+            Some(rest) => {
+                flush(current.take());
+                rest.trim_start()
+            }
+            None => trimmed,
+        };
+        let item = current.get_or_insert((None, None));
+        if let Some(value) = entry.strip_prefix("name:") {
+            item.0 = Some(yaml_scalar(value));
+        } else if let Some(value) = entry.strip_prefix("namespace:") {
+            item.1 = Some(yaml_scalar(value)).filter(|ns| !ns.is_empty());
+        }
+    }
+    flush(current);
+    contexts
+}
+
+/// A plain or quoted YAML scalar, without a trailing comment.
+fn yaml_scalar(value: &str) -> String {
+    let value = value.trim();
+    if let Some(quoted) = value
+        .strip_prefix('"')
+        .and_then(|v| v.split('"').next())
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.split('\'').next()))
+    {
+        return quoted.to_string();
+    }
+    value
+        .split(" #")
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// `key` in INI `[section]`, as written by the AWS and gcloud CLIs.
+fn ini_value(text: &str, section: &str, key: &str) -> Option<String> {
+    let mut in_section = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_section = name.trim() == section;
+        } else if in_section {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key && !v.trim().is_empty() {
+                    return Some(v.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const KUBECONFIG: &str = "\
+apiVersion: v1
+clusters:
+- cluster:
+    server: https://prod.example:6443
+  name: prod
+contexts:
+- context:
+    cluster: kind
+    user: kind
+  name: kind-dev
+- context:
+    cluster: prod
+    namespace: payments
+    user: admin
+  name: \"prod-eu\"
+current-context: prod-eu # switched by kubectx
+kind: Config
+";
+
+    fn all() -> InfraConfig {
+        InfraConfig {
+            kubernetes: true,
+            aws: true,
+            gcloud: true,
+            docker: true,
+        }
+    }
+
+    #[test]
+    fn reads_every_source_from_files() {
+        let home = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = home.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(".kube/config", KUBECONFIG);
+        write(
+            ".aws/config",
+            "[default]\nregion = us-east-1\n\n[profile staging]\noutput = json\nregion = eu-west-1\n",
+        );
+        write(".config/gcloud/active_config", "work\n");
+        write(
+            ".config/gcloud/configurations/config_work",
+            "[core]\naccount = me@example.com\nproject = billing-prod\n",
+        );
+        write(
+            ".docker/config.json",
+            r#"{"auths":{},"currentContext":"colima"}"#,
+        );
+        let env: HashMap<&str, &str> = HashMap::from([("AWS_PROFILE", "staging")]);
+
+        let infra = gather_from(
+            &all(),
+            &|name| env.get(name).map(|v| v.to_string()),
+            home.path(),
+        );
+        assert_eq!(
+            infra,
+            InfraContext {
+                kube_context: Some("prod-eu".to_string()),
+                kube_namespace: Some("payments".to_string()),
+                aws_profile: Some("staging".to_string()),
+                aws_region: Some("eu-west-1".to_string()),
+                gcloud_project: Some("billing-prod".to_string()),
+                docker_context: Some("colima".to_string()),
+            }
+        );
+
+        // Nothing is read for sources left disabled.
+        let none = gather_from(&InfraConfig::default(), &|_| None, home.path());
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn environment_overrides_files() {
+        let home = tempfile::tempdir().unwrap();
+        let kube = home.path().join("merged.yaml");
+        std::fs::write(
+            &kube,
+            "contexts:\n  - name: ci\n    context:\n      namespace: jobs\ncurrent-context: ci\n",
+        )
+        .unwrap();
+        let env: HashMap<&str, String> = HashMap::from([
+            (
+                "KUBECONFIG",
+                std::env::join_paths([home.path().join("missing"), kube])
+                    .unwrap()
+                    .into_string()
+                    .unwrap(),
+            ),
+            ("AWS_REGION", "ap-south-1".to_string()),
+            ("CLOUDSDK_CORE_PROJECT", "sandbox".to_string()),
+            ("DOCKER_CONTEXT", "remote".to_string()),
+        ]);
+
+        let infra = gather_from(&all(), &|name| env.get(name).cloned(), home.path());
+        assert_eq!(infra.kube_context.as_deref(), Some("ci"));
+        assert_eq!(infra.kube_namespace.as_deref(), Some("jobs"));
+        assert_eq!(infra.aws_profile, None);
+        assert_eq!(infra.aws_region.as_deref(), Some("ap-south-1"));
+        assert_eq!(infra.gcloud_project.as_deref(), Some("sandbox"));
+        assert_eq!(infra.docker_context.as_deref(), Some("remote"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Top-level entries of the current directory.
+//!
+//! "Compress the logs from yesterday" needs the file names and their ages.
+//! The listing covers only cwd itself, never subdirectories: names, types,
+//! sizes of regular files, and modification times, newest first and cut at
+//! `[context.listing] limit`. Entries git ignores are left out (one
+//! `git check-ignore` call, so nested `.gitignore` files and global
+//! excludes count), and so are dotfiles unless `hidden = true`. Names
+//! matching a `[context.send] redact_paths` pattern are dropped, and with
+//! `cwd = false` there is no listing at all.
+
+use crate::config::ListingConfig;
+use crate::protocol::{DirListing, EntryKind, ListingEntry};
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most directory entries examined. A huge directory is summarized by its
+/// first entries rather than stat'ed in full.
+const SCAN_LIMIT: usize = 2000;
+
+/// The listing of `dir`, without names that match `redact`. None when it
+/// cannot be read.
+pub fn listing(dir: &Path, config: &ListingConfig, redact: &[Regex]) -> Option<DirListing> {
+    let mut entries = Vec::new();
+    let mut unscanned = 0;
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git"
+            || (!config.hidden && name.starts_with('.'))
+            || redact.iter().any(|regex| regex.is_match(&name))
+        {
+            continue;
+        }
+        if entries.len() == SCAN_LIMIT {
+            unscanned += 1;
+            continue;
+        }
+        let metadata = entry.metadata().ok();
+        let kind = match metadata.as_ref().map(|m| m.file_type()) {
+            Some(t) if t.is_symlink() => EntryKind::Symlink,
+            Some(t) if t.is_dir() => EntryKind::Dir,
+            Some(t) if t.is_file() => EntryKind::File,
+            _ => EntryKind::Other,
+        };
+        entries.push(ListingEntry {
+            name,
+            kind,
+            size: metadata.as_ref().filter(|m| m.is_file()).map(|m| m.len()),
+            modified: metadata
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        });
+    }
+
+    let ignored = ignored(dir, &entries);
+    entries.retain(|entry| !ignored.contains(&entry.name));
+    entries.sort_by(|a, b| b.modified.cmp(&a.modified).then(a.name.cmp(&b.name)));
+    let omitted = entries.len().saturating_sub(config.limit) + unscanned;
+    entries.truncate(config.limit);
+    Some(DirListing {
+        entries,
+        omitted,
+        taken_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+    })
+}
+
+/// Names among `entries` that git ignores. Empty outside a repository or
+/// without git.
+fn ignored(dir: &Path, entries: &[ListingEntry]) -> HashSet<String> {
+    if entries.is_empty() {
+        return HashSet::new();
+    }
+    // A trailing slash lets directory-only patterns such as `build/` match.
+    let paths: Vec<String> = entries
+        .iter()
+        .map(|entry| match entry.kind {
+            EntryKind::Dir => format!("{}/", entry.name),
+            _ => entry.name.clone(),
+        })
+        .collect();
+    // Exit status 1 means nothing is ignored and 128 means no repository;
+    // either way stdout is empty.
+    let Ok(mut child) = std::process::Command::new("git")
+        .arg("--no-optional-locks")
+        .arg("-C")
+        .arg(dir)
+        .args(["check-ignore", "--stdin", "-z"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return HashSet::new();
+    };
+    // Written from another thread so a full stdout pipe cannot deadlock.
+    let mut stdin = child.stdin.take();
+    let writer = std::thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(paths.join("\0").as_bytes());
+        }
+    });
+    let output = child.wait_with_output();
+    let _ = writer.join();
+    let Ok(output) = output else {
+        return HashSet::new();
+    };
+    output
+        .stdout
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            String::from_utf8_lossy(path)
+                .trim_end_matches('/')
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(limit: usize, hidden: bool) -> ListingConfig {
+        ListingConfig {
+            enabled: true,
+            limit,
+            hidden,
+        }
+    }
+
+    fn set_mtime(path: &Path, secs: u64) {
+        std::fs::File::open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn lists_newest_first_and_counts_what_is_cut() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.log"), "12345").unwrap();
+        std::fs::write(dir.path().join("new.log"), "1").unwrap();
+        std::fs::write(dir.path().join(".env"), "SECRET=1").unwrap();
+        std::fs::create_dir(dir.path().join("images")).unwrap();
+        set_mtime(&dir.path().join("old.log"), 1_000);
+        set_mtime(&dir.path().join("new.log"), 3_000);
+        set_mtime(&dir.path().join("images"), 2_000);
+
+        let listing = listing(dir.path(), &config(2, false), &[]).unwrap();
+        assert_eq!(
+            listing.entries,
+            vec![
+                ListingEntry {
+                    name: "new.log".to_string(),
+                    kind: EntryKind::File,
+                    size: Some(1),
+                    modified: Some(3_000),
+                },
+                ListingEntry {
+                    name: "images".to_string(),
+                    kind: EntryKind::Dir,
+                    size: None,
+                    modified: Some(2_000),
+                },
+            ]
+        );
+        assert_eq!(listing.omitted, 1);
+        assert!(listing.taken_at > 3_000);
+
+        let with_hidden = super::listing(dir.path(), &config(10, true), &[]).unwrap();
+        assert!(with_hidden.entries.iter().any(|e| e.name == ".env"));
+        assert!(super::listing(&dir.path().join("missing"), &config(10, false), &[]).is_none());
+
+        let redact = [Regex::new("^old").unwrap()];
+        let redacted = super::listing(dir.path(), &config(10, false), &redact).unwrap();
+        assert!(redacted.entries.iter().all(|e| e.name != "old.log"));
+        assert_eq!(redacted.entries.len(), 2);
+    }
+
+    #[test]
+    fn leaves_out_git_ignored_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = std::process::Command::new("git")
+            .args(["init", "-q"])
+            .arg(dir.path())
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_SYSTEM", "/dev/null")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::fs::write(dir.path().join(".gitignore"), "*.tmp\nbuild/\n").unwrap();
+        std::fs::write(dir.path().join("a.tmp"), "").unwrap();
+        std::fs::write(dir.path().join("main.c"), "").unwrap();
+        std::fs::create_dir(dir.path().join("build")).unwrap();
+
+        let names: Vec<String> = listing(dir.path(), &config(10, false), &[])
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, vec!["main.c"]);
+    }
+}
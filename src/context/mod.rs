@@ -0,0 +1,1181 @@
+//! System context gathering for better command generation.
+//!
+//! Collects information about the user's environment to help the LLM
+//! generate more appropriate commands. Everything gathered here is local
+//! and cheap: directory markers, one PATH scan, one `git status` call, and
+//! a few environment variables and marker files (see [`runtime`]), plus a
+//! budgeted read of well-known project files (see [`project`]) and a cached
+//! fingerprint of the core utilities (see [`userland`]). Cloud and cluster
+//! targets are read from CLI config files when enabled (see [`infra`]), and
+//! an opt-in listing of cwd is read from the directory itself (see
+//! [`listing`]). Site-specific facts come from
+//! user-configured provider programs (see [`providers`]).
+//! Shell history is only read when the user opts in (see
+//! [`crate::history`]).
+//!
+//! The probes run concurrently under one deadline (`[context] budget_ms`),
+//! and the OS, distro, and PATH probe are cached in the runtime dir (see
+//! [`cache`]), so a slow `git status` in a large repo costs at most the
+//! budget.
+
+mod cache;
+mod git;
+mod infra;
+mod listing;
+mod project;
+mod providers;
+mod runtime;
+#[cfg(not(windows))]
+mod userland;
+
+use crate::config::{Config, ContextConfig, SendConfig, TableMode};
+use crate::protocol::{Context, GitContext, WindowsContext};
+#[cfg(any(windows, test))]
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Project types detected from well-known marker files. `[context.markers]`
+/// extends or replaces this table (see [`marker_table`]).
+const PROJECT_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("package.json", "node"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("go.mod", "go"),
+    ("pom.xml", "java"),
+    ("build.gradle", "java"),
+    ("build.gradle.kts", "java"),
+    ("Gemfile", "ruby"),
+    ("composer.json", "php"),
+    ("mix.exs", "elixir"),
+    ("CMakeLists.txt", "cmake"),
+    ("Dockerfile", "docker"),
+    ("docker-compose.yml", "docker-compose"),
+    ("compose.yaml", "docker-compose"),
+];
+
+/// Modern CLI tools worth telling the model about. The system prompt asks
+/// the model to prefer these over classic equivalents, so it must only
+/// mention tools that are actually installed. `[context.tools]` extends or
+/// replaces this table (see [`tool_table`]).
+const PROBED_TOOLS: &[&str] = &[
+    "rg", "fd", "bat", "eza", "jq", "yq", "fzf", "gh", "docker", "podman", "kubectl", "tmux",
+];
+
+#[cfg(any(windows, test))]
+const WINDOWS_DIAGNOSTIC_TOOLS: &[&str] = &[
+    "pwsh.exe",
+    "wpr.exe",
+    "wpa.exe",
+    "procmon.exe",
+    "procexp.exe",
+    "handle.exe",
+    "tcpview.exe",
+    "rammap.exe",
+    "autoruns.exe",
+    "sigcheck.exe",
+    "diskspd.exe",
+    "pktmon.exe",
+    "netsh.exe",
+    "wevtutil.exe",
+    "pnputil.exe",
+    "dism.exe",
+    "sfc.exe",
+    "driverquery.exe",
+    "systeminfo.exe",
+    "wsl.exe",
+];
+
+/// Whether `tool` is one of the built-in modern tools, as opposed to one
+/// only `[context.tools]` names.
+pub fn is_modern_tool(tool: &str) -> bool {
+    PROBED_TOOLS.contains(&tool)
+}
+
+/// The effective tool probe list: the built-ins unless replaced, then the
+/// configured tools.
+pub fn tool_table(config: &ContextConfig) -> Vec<String> {
+    let mut tools: Vec<String> = match config.tools_mode {
+        TableMode::Extend => PROBED_TOOLS.iter().map(|t| (*t).to_string()).collect(),
+        TableMode::Replace => Vec::new(),
+    };
+    for (name, _) in config.valid_tools() {
+        if !tools.iter().any(|t| t == name) {
+            tools.push(name.to_string());
+        }
+    }
+    tools
+}
+
+/// The effective marker table as (file, project type). In extend mode a
+/// configured file overrides the built-in project type for that file.
+pub fn marker_table(config: &ContextConfig) -> Vec<(String, String)> {
+    let mut markers: Vec<(String, String)> = match config.markers_mode {
+        TableMode::Extend => PROJECT_MARKERS
+            .iter()
+            .map(|(file, project)| ((*file).to_string(), (*project).to_string()))
+            .collect(),
+        TableMode::Replace => Vec::new(),
+    };
+    for (file, project) in config.valid_markers() {
+        match markers.iter_mut().find(|(f, _)| f == file) {
+            Some(entry) => entry.1 = project.to_string(),
+            None => markers.push((file.to_string(), project.to_string())),
+        }
+    }
+    markers
+}
+
+/// One-line summary of the configured tables for status output, e.g.
+/// `tools extend (+aws, terraform), markers extend (built-in only)`.
+pub fn table_summary(config: &ContextConfig) -> String {
+    let describe = |mode: TableMode, names: Vec<&str>| match (mode, names.is_empty()) {
+        (TableMode::Extend, true) => "extend (built-in only)".to_string(),
+        (TableMode::Extend, false) => format!("extend (+{})", names.join(", ")),
+        (TableMode::Replace, true) => "replace (none)".to_string(),
+        (TableMode::Replace, false) => format!("replace ({})", names.join(", ")),
+    };
+    format!(
+        "tools {}, markers {}",
+        describe(
+            config.tools_mode,
+            config.valid_tools().map(|(name, _)| name).collect()
+        ),
+        describe(
+            config.markers_mode,
+            config.valid_markers().map(|(file, _)| file).collect()
+        )
+    )
+}
+
+/// Gather system context for the LLM.
+///
+/// Probes that spawn a process or walk directories run concurrently, each
+/// on its own thread, and the ones that have not answered when
+/// `budget_ms` runs out are left out of the context.
+pub fn gather_context(config: &Config) -> Result<Context> {
+    let send = &config.context.send;
+    let budget = Duration::from_millis(config.context.budget_ms);
+    let deadline = Instant::now() + budget;
+    let cwd = get_cwd();
+    let probe_list = if send.tools {
+        tool_table(&config.context)
+    } else {
+        Vec::new()
+    };
+
+    let git = send.git.then(|| {
+        let cwd = cwd.clone();
+        spawn_probe(move || git::git_context(&cwd))
+    });
+    let projects = send.projects.then(|| {
+        let (cwd, markers) = (cwd.clone(), marker_table(&config.context));
+        spawn_probe(move || {
+            (
+                detect_projects(&cwd, &markers),
+                project::project_tasks(&cwd, project::TIME_BUDGET.min(budget)),
+            )
+        })
+    });
+    let infra = {
+        let infra = config.context.infra.clone();
+        spawn_probe(move || infra::gather(&infra))
+    };
+    // The names in cwd say as much as its path, so the listing follows
+    // the same `[context.send]` rules.
+    let listing = (config.context.listing.enabled && send.cwd)
+        .then(|| -> Result<_> {
+            let (cwd, listing) = (cwd.clone(), config.context.listing.clone());
+            let redact = redact_patterns(send)?;
+            Ok(spawn_probe(move || {
+                listing::listing(&cwd, &listing, &redact)
+            }))
+        })
+        .transpose()?;
+    let provided: Vec<_> = config
+        .context
+        .providers
+        .iter()
+        .filter(|provider| !provider.command.trim().is_empty())
+        .map(|provider| {
+            let (cwd, provider) = (cwd.clone(), provider.clone());
+            spawn_probe(move || providers::run(&provider, &cwd, budget))
+        })
+        .collect();
+    let distro = send
+        .distro
+        .then(|| spawn_probe(|| cache::cached("distro", &cache::os_key(), get_distro_info)));
+
+    #[cfg(windows)]
+    let (shell, windows, tools, host, userland, os) = {
+        // The PATH scan also finds pwsh, so it runs even with tools off.
+        let path_tools = probe_windows_path(&probe_list);
+        let (shell, windows) =
+            gather_windows_context(path_tools.pwsh, path_tools.diagnostic_tools)?;
+        let os = windows.as_ref().map_or_else(get_os_info, |details| {
+            format!(
+                "{} {} (build {})",
+                details.caption, details.version, details.build
+            )
+        });
+        (
+            shell,
+            windows,
+            path_tools.modern_tools,
+            None,
+            std::collections::BTreeMap::new(),
+            os,
+        )
+    };
+    #[cfg(not(windows))]
+    let (shell, windows, tools, host, userland, os) = {
+        // One PATH scan answers for the tool table and the host probes.
+        let mut probes = probe_list.clone();
+        if send.env_flags {
+            for name in runtime::ESCALATION_TOOLS
+                .iter()
+                .chain(runtime::PACKAGE_MANAGERS)
+            {
+                if !probes.iter().any(|p| p == name) {
+                    probes.push((*name).to_string());
+                }
+            }
+        }
+        let path_probe = spawn_probe(move || {
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            cache::cached("path", &cache::path_key(&path, &probes), || {
+                probe_tools(&probes)
+            })
+        });
+        let userland = spawn_probe(userland::detect);
+        let os = spawn_probe(|| cache::cached("os", &cache::os_key(), get_os_info));
+
+        let found = path_probe.wait(deadline);
+        let tools = found
+            .iter()
+            .flatten()
+            .filter(|name| probe_list.contains(name))
+            .cloned()
+            .collect();
+        // Without the scan, nothing is known about sudo or package managers.
+        let host = found
+            .filter(|_| send.env_flags)
+            .map(|found| runtime::host(&found));
+        (
+            get_shell(),
+            None::<WindowsContext>,
+            tools,
+            host,
+            userland.wait(deadline).unwrap_or_default(),
+            os.wait(deadline).unwrap_or_else(fallback_os_info),
+        )
+    };
+
+    // History and aliases are read here while the probes run.
+    let (history, mut aliases) = gather_history(config, &shell);
+    let (widget_aliases, functions) = gather_widget_aliases(config);
+    merge_aliases(&mut aliases, widget_aliases);
+    let env_flags = if send.env_flags {
+        runtime::env_flags()
+    } else {
+        Vec::new()
+    };
+
+    // Earlier providers win a key both report.
+    let mut extra = std::collections::BTreeMap::new();
+    for probe in provided {
+        for (key, value) in probe.wait(deadline).flatten().unwrap_or_default() {
+            extra.entry(key).or_insert(value);
+        }
+    }
+    let (projects, project_tasks) = projects
+        .and_then(|probe| probe.wait(deadline))
+        .unwrap_or_default();
+    let git_state = git.and_then(|probe| probe.wait(deadline)).flatten();
+    Ok(Context {
+        projects,
+        project_tasks,
+        userland,
+        infra: infra.wait(deadline).unwrap_or_default(),
+        tools,
+        git: git_state.as_ref().map(GitContext::summary),
+        git_state,
+        env_flags,
+        host,
+        listing: listing.and_then(|probe| probe.wait(deadline)).flatten(),
+        history,
+        aliases,
+        functions,
+        extra,
+        cwd: shared_cwd(&cwd, dirs::home_dir().as_deref(), send)?,
+        shell,
+        os,
+        distro: distro.and_then(|probe| probe.wait(deadline)).flatten(),
+        windows,
+    })
+}
+
+/// A probe running on its own thread.
+struct Probe<T>(mpsc::Receiver<T>);
+
+/// Start `probe` on a detached thread. A probe that misses its deadline
+/// keeps running until the client exits, and its answer is dropped.
+fn spawn_probe<T: Send + 'static>(probe: impl FnOnce() -> T + Send + 'static) -> Probe<T> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(probe());
+    });
+    Probe(receiver)
+}
+
+impl<T> Probe<T> {
+    /// The probe's answer, or None if it is not ready by `deadline`.
+    fn wait(self, deadline: Instant) -> Option<T> {
+        self.0
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .ok()
+    }
+}
+
+/// The cwd as sent: empty when withheld, otherwise with the home directory
+/// shown as `~` if configured and `redact_paths` matches replaced.
+fn shared_cwd(cwd: &Path, home: Option<&Path>, send: &SendConfig) -> Result<PathBuf> {
+    if !send.cwd {
+        return Ok(PathBuf::new());
+    }
+    let mut shown = cwd.to_path_buf();
+    if send.home_as_tilde {
+        if let Some(rest) = home.and_then(|home| cwd.strip_prefix(home).ok()) {
+            shown = Path::new("~").join(rest);
+        }
+    }
+    if send.redact_paths.is_empty() {
+        return Ok(shown);
+    }
+    let mut text = shown.to_string_lossy().into_owned();
+    for regex in redact_patterns(send)? {
+        text = regex.replace_all(&text, "[REDACTED]").into_owned();
+    }
+    Ok(PathBuf::from(text))
+}
+
+/// The compiled `[context.send] redact_paths` patterns.
+fn redact_patterns(send: &SendConfig) -> Result<Vec<regex::Regex>> {
+    send.redact_paths
+        .iter()
+        .map(|pattern| {
+            regex::Regex::new(pattern).with_context(|| {
+                format!("Invalid [context.send] redact_paths pattern {:?}", pattern)
+            })
+        })
+        .collect()
+}
+
+/// Opt-in history context as (commands, aliases). A failure (e.g. an invalid
+/// redaction pattern) sends nothing rather than unredacted history.
+fn gather_history(config: &Config, shell: &str) -> (Vec<String>, Vec<String>) {
+    if !config.context.history.enabled {
+        return (Vec::new(), Vec::new());
+    }
+    match crate::history::collect(&config.context.history, shell) {
+        Ok(history) => (history.commands, history.aliases),
+        Err(e) => {
+            warn!("History context skipped: {:#}", e);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+/// Environment variables the alias-aware shell widget sets for one
+/// invocation: the shell's `alias` output and its function names.
+const ALIASES_ENV: &str = "INCANT_ALIASES";
+const FUNCTIONS_ENV: &str = "INCANT_FUNCTIONS";
+
+/// Longest exported value read, in bytes; the rest is ignored.
+const MAX_WIDGET_EXPORT: usize = 64 * 1024;
+
+/// Longest alias definition sent, in characters.
+const MAX_ALIAS_CHARS: usize = 120;
+
+/// Aliases and function names exported by the shell widget, filtered by
+/// `[context.aliases]` and redacted like history.
+fn gather_widget_aliases(config: &Config) -> (Vec<String>, Vec<String>) {
+    let settings = &config.context.aliases;
+    if !settings.enabled {
+        return (Vec::new(), Vec::new());
+    }
+    let read = |name: &str| {
+        std::env::var(name).ok().map(|mut value| {
+            if value.len() > MAX_WIDGET_EXPORT {
+                let mut end = MAX_WIDGET_EXPORT;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+            }
+            value
+        })
+    };
+    let aliases = read(ALIASES_ENV).unwrap_or_default();
+    let functions = read(FUNCTIONS_ENV).unwrap_or_default();
+    if aliases.is_empty() && functions.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let redactor = match crate::history::Redactor::new(&config.context.history) {
+        Ok(redactor) => redactor,
+        Err(e) => {
+            warn!("Alias context skipped: {:#}", e);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    let aliases = parse_alias_output(&aliases)
+        .into_iter()
+        .filter(|(name, _)| name_allowed(name, &settings.allow, &settings.deny))
+        .take(settings.limit)
+        .map(|(name, value)| {
+            let definition = redactor.redact(&format!("{}={}", name, value));
+            match definition.char_indices().nth(MAX_ALIAS_CHARS) {
+                Some((index, _)) => format!("{}...", &definition[..index]),
+                None => definition,
+            }
+        })
+        .collect();
+    let functions = parse_function_names(&functions)
+        .into_iter()
+        .filter(|name| name_allowed(name, &settings.allow, &settings.deny))
+        .take(settings.limit)
+        .collect();
+    (aliases, functions)
+}
+
+/// Parse `alias` output from zsh (`name=value`), bash (`alias name=value`),
+/// or fish (`alias name 'value'`) into (name, value) pairs.
+fn parse_alias_output(text: &str) -> Vec<(String, String)> {
+    let mut seen = HashSet::new();
+    let mut aliases = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("alias ").unwrap_or(line).trim_start();
+        let Some(split) = line.find(|c: char| c == '=' || c.is_whitespace()) else {
+            continue;
+        };
+        let (name, value) = (&line[..split], line[split + 1..].trim());
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "_-.:+@".contains(c));
+        if valid_name && !value.is_empty() && seen.insert(name.to_string()) {
+            aliases.push((name.to_string(), value.to_string()));
+        }
+    }
+    aliases
+}
+
+/// Function names, whitespace-separated. Private helpers (leading `_`) and
+/// fish's own `fish_*` functions are never user vocabulary.
+fn parse_function_names(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|name| !name.is_empty() && !name.starts_with('_') && !name.starts_with("fish_"))
+        .filter(|name| seen.insert(*name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `name` passes the allow list (empty allows all) and misses the
+/// deny list.
+fn name_allowed(name: &str, allow: &[String], deny: &[String]) -> bool {
+    (allow.is_empty() || allow.iter().any(|p| wildcard_match(p, name)))
+        && !deny.iter().any(|p| wildcard_match(p, name))
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty(); // no wildcard: exact match
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Add widget-exported aliases to any found by history's aliases-only mode;
+/// the live shell's definition wins over the startup file's.
+fn merge_aliases(aliases: &mut Vec<String>, widget: Vec<String>) {
+    if widget.is_empty() {
+        return;
+    }
+    let name = |definition: &str| definition.split('=').next().unwrap_or_default().to_string();
+    let live: HashSet<String> = widget.iter().map(|d| name(d)).collect();
+    aliases.retain(|definition| !live.contains(&name(definition)));
+    aliases.extend(widget);
+}
+
+/// Get the current working directory.
+fn get_cwd() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
+}
+
+/// Get the user's shell from `$SHELL` on POSIX hosts.
+#[cfg(not(windows))]
+fn get_shell() -> String {
+    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+}
+
+/// Detect project types from marker files in `dir`.
+fn detect_projects(dir: &Path, markers: &[(String, String)]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut projects = Vec::new();
+    for (marker, project) in markers {
+        if dir.join(marker).exists() && seen.insert(project.as_str()) {
+            projects.push(project.clone());
+        }
+    }
+    projects
+}
+
+/// Probe PATH once and report which of `probe_list` are installed.
+#[cfg(not(windows))]
+fn probe_tools(probe_list: &[String]) -> Vec<String> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    let mut available: HashSet<&str> = HashSet::new();
+    for dir in std::env::split_paths(&path) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                if let Some(tool) = probe_list.iter().find(|t| *t == name) {
+                    available.insert(tool);
+                }
+            }
+        }
+    }
+
+    // Preserve the table ordering rather than hash order.
+    probe_list
+        .iter()
+        .filter(|t| available.contains(t.as_str()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(any(windows, test))]
+#[derive(Debug, Default, PartialEq, Eq)]
+struct WindowsPathTools {
+    modern_tools: Vec<String>,
+    diagnostic_tools: Vec<String>,
+    pwsh: Option<&'static str>,
+}
+
+#[cfg(windows)]
+fn probe_windows_path(probe_list: &[String]) -> WindowsPathTools {
+    std::env::var_os("PATH")
+        .as_deref()
+        .map(|path| probe_windows_path_in(path, probe_list))
+        .unwrap_or_default()
+}
+
+#[cfg(any(windows, test))]
+fn probe_windows_path_in(path: &std::ffi::OsStr, probe_list: &[String]) -> WindowsPathTools {
+    let mut modern = HashSet::new();
+    let mut diagnostic = HashSet::new();
+    for dir in std::env::split_paths(path) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+            let bare_name = name.strip_suffix(".exe").unwrap_or(&name);
+            if let Some(tool) = probe_list
+                .iter()
+                .find(|tool| tool.eq_ignore_ascii_case(bare_name))
+            {
+                modern.insert(tool.as_str());
+            }
+            if WINDOWS_DIAGNOSTIC_TOOLS.contains(&name.as_str()) {
+                diagnostic.insert(name);
+            }
+        }
+    }
+
+    let diagnostic_tools: Vec<String> = WINDOWS_DIAGNOSTIC_TOOLS
+        .iter()
+        .filter(|tool| diagnostic.contains(**tool))
+        .map(|tool| (*tool).to_string())
+        .collect();
+    let pwsh = diagnostic_tools
+        .iter()
+        .any(|tool| tool == "pwsh.exe")
+        .then_some("pwsh.exe");
+    WindowsPathTools {
+        modern_tools: probe_list
+            .iter()
+            .filter(|tool| modern.contains(tool.as_str()))
+            .cloned()
+            .collect(),
+        diagnostic_tools,
+        pwsh,
+    }
+}
+#[cfg(any(windows, test))]
+fn require_pwsh(pwsh: Option<&'static str>) -> Result<&'static str> {
+    let Some(pwsh) = pwsh else {
+        bail!("pwsh 7.4+ is required on Windows; pwsh.exe was not found on PATH");
+    };
+    Ok(pwsh)
+}
+
+#[cfg(windows)]
+fn gather_windows_context(
+    pwsh: Option<&'static str>,
+    diagnostic_tools: Vec<String>,
+) -> Result<(String, Option<WindowsContext>)> {
+    let pwsh = require_pwsh(pwsh)?;
+    let script = r#"
+[Console]::OutputEncoding = [Text.UTF8Encoding]::new($false)
+if ([Version]$PSVersionTable.PSVersion.ToString() -lt [Version]'7.4') {
+    throw "pwsh 7.4+ is required"
+}
+$targetPid = [uint32]$args[0]
+$os = Get-CimInstance -ClassName Win32_OperatingSystem
+$target = Get-CimInstance -ClassName Win32_Process -Filter "ProcessId = $targetPid"
+$parentName = (Get-Process -Id $target.ParentProcessId -ErrorAction Stop).ProcessName
+$identity = [Security.Principal.WindowsIdentity]::GetCurrent()
+$principal = [Security.Principal.WindowsPrincipal]::new($identity)
+$elevated = $principal.IsInRole([Security.Principal.WindowsBuiltInRole]::Administrator)
+[Console]::Out.Write(("{0}`t{1}`t{2}`t{3}`t{4}`t{5}" -f $os.Caption, $os.Version, $os.BuildNumber, $PSVersionTable.PSVersion, $elevated, $parentName))
+"#;
+    let output = std::process::Command::new(pwsh)
+        .args([
+            "-NoLogo",
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            script,
+        ])
+        .arg(std::process::id().to_string())
+        .output()
+        .with_context(|| format!("failed to collect Windows context with {pwsh}"))?;
+    if !output.status.success() {
+        bail!(
+            "{pwsh} failed to collect Windows context: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let text = String::from_utf8(output.stdout).context("PowerShell returned non-UTF-8 context")?;
+    let parsed = parse_windows_context(&text, diagnostic_tools)?;
+    let shell = normalize_windows_shell(&parsed.0)?;
+    Ok((shell, Some(parsed.1)))
+}
+
+#[cfg(any(windows, test))]
+fn validate_pwsh_version(version: &str) -> Result<()> {
+    let version = version.trim();
+    let mut components = version.split('.');
+    let parse_component = |component: Option<&str>| {
+        component
+            .filter(|value| !value.is_empty())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let (Some(major), Some(minor)) = (
+        parse_component(components.next()),
+        parse_component(components.next()),
+    ) else {
+        bail!("pwsh 7.4+ is required; reported version {version:?} is malformed");
+    };
+
+    let mut extra_components = 0;
+    for component in components {
+        extra_components += 1;
+        if extra_components > 2 || component.parse::<u64>().is_err() {
+            bail!("pwsh 7.4+ is required; reported version {version:?} is malformed");
+        }
+    }
+    if major < 7 || (major == 7 && minor < 4) {
+        bail!("pwsh 7.4+ is required; found {version}");
+    }
+    Ok(())
+}
+
+#[cfg(any(windows, test))]
+fn parse_windows_context(
+    text: &str,
+    diagnostic_tools: Vec<String>,
+) -> Result<(String, WindowsContext)> {
+    let fields: Vec<&str> = text.trim().split('\t').collect();
+    if fields.len() != 6 || fields[..5].iter().any(|value| value.trim().is_empty()) {
+        bail!("PowerShell returned incomplete Windows context");
+    }
+    let powershell_version = fields[3].trim();
+    validate_pwsh_version(powershell_version)?;
+    let elevated = match fields[4].trim() {
+        value if value.eq_ignore_ascii_case("true") => true,
+        value if value.eq_ignore_ascii_case("false") => false,
+        _ => bail!("PowerShell returned an invalid elevation state"),
+    };
+    Ok((
+        fields[5].trim().to_string(),
+        WindowsContext {
+            caption: fields[0].trim().to_string(),
+            version: fields[1].trim().to_string(),
+            build: fields[2].trim().to_string(),
+            powershell_version: powershell_version.to_string(),
+            elevated,
+            diagnostic_tools,
+        },
+    ))
+}
+
+#[cfg(any(windows, test))]
+fn windows_process_stem(name: &str) -> &str {
+    let basename = name
+        .trim()
+        .rsplit(['\\', '/'])
+        .next()
+        .unwrap_or(name.trim());
+    let extension_start = basename.len().saturating_sub(4);
+    if basename
+        .get(extension_start..)
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(".exe"))
+    {
+        &basename[..extension_start]
+    } else {
+        basename
+    }
+}
+
+#[cfg(any(windows, test))]
+fn normalize_windows_shell(parent_name: &str) -> Result<String> {
+    let parent = windows_process_stem(parent_name);
+    if parent.is_empty() {
+        bail!("PowerShell returned an empty parent process name; active Windows shell is unknown");
+    }
+    if parent.eq_ignore_ascii_case("pwsh") {
+        return Ok("pwsh".to_string());
+    }
+    if parent.eq_ignore_ascii_case("powershell") {
+        return Ok("PowerShell".to_string());
+    }
+    if parent.eq_ignore_ascii_case("cmd") {
+        return Ok("cmd".to_string());
+    }
+    Ok(parent.to_string())
+}
+
+/// Get OS information from uname.
+fn get_os_info() -> String {
+    // Try to get uname info
+    #[cfg(unix)]
+    {
+        if let Ok(output) = std::process::Command::new("uname").arg("-sr").output() {
+            if output.status.success() {
+                if let Ok(info) = String::from_utf8(output.stdout) {
+                    return info.trim().to_string();
+                }
+            }
+        }
+    }
+
+    fallback_os_info()
+}
+
+/// Basic OS info when uname is unavailable or too slow.
+fn fallback_os_info() -> String {
+    format!("{} {}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Get Linux distribution info from /etc/os-release.
+fn get_distro_info() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(contents) = std::fs::read_to_string("/etc/os-release") {
+            for line in contents.lines() {
+                if let Some(value) = line.strip_prefix("PRETTY_NAME=") {
+                    return Some(value.trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if let Ok(output) = std::process::Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+        {
+            if output.status.success() {
+                if let Ok(version) = String::from_utf8(output.stdout) {
+                    return Some(format!("macOS {}", version.trim()));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_that_miss_the_deadline_are_omitted() {
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(50);
+        let fast = spawn_probe(|| "fast");
+        let slow = spawn_probe(|| {
+            std::thread::sleep(Duration::from_secs(5));
+            "slow"
+        });
+        assert_eq!(fast.wait(deadline), Some("fast"));
+        assert_eq!(slow.wait(deadline), None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn zero_budget_still_yields_a_usable_context() {
+        let mut config = Config::default();
+        config.context.budget_ms = 0;
+        let ctx = gather_context(&config).unwrap();
+        assert!(!ctx.shell.is_empty() && !ctx.os.is_empty());
+    }
+
+    #[test]
+    fn listing_follows_the_cwd_send_rules() {
+        let mut config = Config::default();
+        config.context.listing.enabled = true;
+        config.context.budget_ms = 2_000;
+        config.context.send.cwd = false;
+        assert_eq!(gather_context(&config).unwrap().listing, None);
+
+        config.context.send.cwd = true;
+        config.context.send.redact_paths = vec!["(".to_string()];
+        assert!(gather_context(&config).is_err());
+    }
+
+    #[test]
+    fn test_gather_context() {
+        let ctx = gather_context(&Config::default()).unwrap();
+        assert!(!ctx.shell.is_empty());
+        assert!(!ctx.os.is_empty());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn parses_alias_output_from_each_shell() {
+        let zsh = "glog='git log --oneline --graph'\ng=git\n";
+        let bash = "alias glog='git log --oneline --graph'\nalias ll='ls -l'\n";
+        let fish = "alias glog 'git log --oneline --graph'\n";
+        assert_eq!(
+            parse_alias_output(zsh),
+            vec![
+                (
+                    "glog".to_string(),
+                    "'git log --oneline --graph'".to_string()
+                ),
+                ("g".to_string(), "git".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_alias_output(bash)[1],
+            ("ll".to_string(), "'ls -l'".to_string())
+        );
+        assert_eq!(
+            parse_alias_output(fish),
+            vec![(
+                "glog".to_string(),
+                "'git log --oneline --graph'".to_string()
+            )]
+        );
+        assert!(parse_alias_output("  continuation of a multi-line value\n=oops\n").len() <= 1);
+    }
+
+    #[test]
+    fn function_names_skip_private_helpers() {
+        assert_eq!(
+            parse_function_names("mkcd _git fish_prompt mkcd\nextract"),
+            vec!["mkcd", "extract"]
+        );
+    }
+
+    #[test]
+    fn allow_and_deny_lists_use_wildcards() {
+        assert!(wildcard_match("g*", "glog"));
+        assert!(wildcard_match("*log", "glog"));
+        assert!(wildcard_match("g*o*g", "glog"));
+        assert!(wildcard_match("ll", "ll"));
+        assert!(!wildcard_match("ll", "lll"));
+        assert!(!wildcard_match("g*x", "glog"));
+        assert!(!wildcard_match("ab*ba", "aba"));
+
+        let allow = vec!["g*".to_string()];
+        let deny = vec!["gpush*".to_string()];
+        assert!(name_allowed("glog", &allow, &deny));
+        assert!(!name_allowed("gpushf", &allow, &deny));
+        assert!(!name_allowed("ll", &allow, &deny));
+        assert!(name_allowed("ll", &[], &[]));
+    }
+
+    #[test]
+    fn live_aliases_replace_startup_file_definitions() {
+        let mut aliases = vec!["ll='ls -l'".to_string(), "g=git".to_string()];
+        merge_aliases(&mut aliases, vec!["ll='eza -l'".to_string()]);
+        assert_eq!(aliases, vec!["g=git", "ll='eza -l'"]);
+    }
+
+    #[test]
+    fn test_get_shell() {
+        let shell = get_shell();
+        assert!(!shell.is_empty());
+    }
+
+    #[test]
+    fn test_get_os_info() {
+        let os = get_os_info();
+        assert!(!os.is_empty());
+    }
+
+    fn builtin_markers() -> Vec<(String, String)> {
+        marker_table(&ContextConfig::default())
+    }
+
+    #[test]
+    fn detect_projects_from_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]").unwrap();
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        let projects = detect_projects(dir.path(), &builtin_markers());
+        assert_eq!(projects, vec!["rust".to_string(), "node".to_string()]);
+    }
+
+    #[test]
+    fn detect_projects_dedupes_same_type() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pyproject.toml"), "").unwrap();
+        std::fs::write(dir.path().join("requirements.txt"), "").unwrap();
+        assert_eq!(
+            detect_projects(dir.path(), &builtin_markers()),
+            vec!["python".to_string()]
+        );
+    }
+
+    #[test]
+    fn detect_projects_empty_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(detect_projects(dir.path(), &builtin_markers()).is_empty());
+    }
+
+    #[test]
+    fn probe_tools_reports_only_present_tools() {
+        // `probe_tools` reads the real PATH; whatever it reports must
+        // genuinely resolve, and the list must be a subset of the probe set.
+        for tool in probe_tools(&tool_table(&ContextConfig::default())) {
+            assert!(PROBED_TOOLS.contains(&tool.as_str()));
+        }
+    }
+
+    #[test]
+    fn shared_cwd_hides_home_and_redacts_paths() {
+        let home = Path::new("/home/alice");
+        let cwd = Path::new("/home/alice/clients/acme-merger/api");
+        let mut send = SendConfig::default();
+        assert_eq!(shared_cwd(cwd, Some(home), &send).unwrap(), cwd);
+
+        send.home_as_tilde = true;
+        send.redact_paths = vec!["acme-[a-z]+".to_string()];
+        assert_eq!(
+            shared_cwd(cwd, Some(home), &send).unwrap(),
+            PathBuf::from("~/clients/[REDACTED]/api")
+        );
+        // Outside home the path is kept, apart from redaction.
+        assert_eq!(
+            shared_cwd(Path::new("/srv/acme-web"), Some(home), &send).unwrap(),
+            PathBuf::from("/srv/[REDACTED]")
+        );
+
+        send.redact_paths = vec!["(".to_string()];
+        assert!(shared_cwd(cwd, Some(home), &send).is_err());
+        send.cwd = false;
+        assert_eq!(shared_cwd(cwd, Some(home), &send).unwrap(), PathBuf::new());
+    }
+
+    #[test]
+    fn configured_tables_extend_override_or_replace_the_built_ins() {
+        let mut config = ContextConfig::default();
+        config
+            .tools
+            .insert("terraform".to_string(), "IaC".to_string());
+        config.tools.insert("rg".to_string(), String::new());
+        config.tools.insert("../evil".to_string(), String::new());
+        config
+            .markers
+            .insert("main.tf".to_string(), "terraform".to_string());
+        config
+            .markers
+            .insert("Dockerfile".to_string(), "container".to_string());
+
+        let tools = tool_table(&config);
+        assert_eq!(tools.len(), PROBED_TOOLS.len() + 1);
+        assert_eq!(tools.last().map(String::as_str), Some("terraform"));
+        let markers = marker_table(&config);
+        assert!(markers.contains(&("Dockerfile".to_string(), "container".to_string())));
+        assert_eq!(
+            markers.last(),
+            Some(&("main.tf".to_string(), "terraform".to_string()))
+        );
+        assert_eq!(
+            table_summary(&config),
+            "tools extend (+rg, terraform), markers extend (+Dockerfile, main.tf)"
+        );
+
+        config.tools_mode = TableMode::Replace;
+        config.markers_mode = TableMode::Replace;
+        assert_eq!(tool_table(&config), vec!["rg", "terraform"]);
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.path().join("main.tf"), "").unwrap();
+        assert_eq!(
+            detect_projects(dir.path(), &marker_table(&config)),
+            vec!["terraform".to_string()]
+        );
+    }
+
+    #[test]
+    fn parses_explicit_windows_context_fields() {
+        let (parent, details) = parse_windows_context(
+            "Microsoft Windows 11 Pro\t10.0.26100\t26100\t7.4.6\tTrue\tPwSh.EXE",
+            vec!["pwsh.exe".to_string(), "pnputil.exe".to_string()],
+        )
+        .unwrap();
+        assert_eq!(parent, "PwSh.EXE");
+        assert_eq!(details.caption, "Microsoft Windows 11 Pro");
+        assert_eq!(details.version, "10.0.26100");
+        assert_eq!(details.build, "26100");
+        assert_eq!(details.powershell_version, "7.4.6");
+        assert!(details.elevated);
+        assert_eq!(
+            details.diagnostic_tools,
+            vec!["pwsh.exe".to_string(), "pnputil.exe".to_string()]
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_or_ambiguous_windows_security_context() {
+        assert!(parse_windows_context("Windows\t10.0\t26100\t7.4\tmaybe\tpwsh", vec![]).is_err());
+        assert!(parse_windows_context("Windows\t10.0\t26100\t7.4\ttrue", vec![]).is_err());
+    }
+
+    #[test]
+    fn normalizes_observed_windows_shells_and_rejects_an_empty_parent() {
+        let cases = [
+            ("pwsh", "pwsh"),
+            ("PwSh.EXE", "pwsh"),
+            ("powershell.exe", "PowerShell"),
+            ("CMD.EXE", "cmd"),
+            ("WindowsTerminal", "WindowsTerminal"),
+            ("nu.exe", "nu"),
+            (r"C:\Tools\nu.exe", "nu"),
+        ];
+        for (parent, expected) in cases {
+            assert_eq!(normalize_windows_shell(parent).unwrap(), expected);
+        }
+
+        for parent in ["", "  "] {
+            let error = normalize_windows_shell(parent).unwrap_err().to_string();
+            assert_eq!(
+                error,
+                "PowerShell returned an empty parent process name; active Windows shell is unknown"
+            );
+        }
+    }
+
+    #[test]
+    fn windows_tool_probe_is_curated_case_insensitive_and_ordered() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        std::fs::write(first.path().join("PNPUTIL.EXE"), "").unwrap();
+        std::fs::write(first.path().join("not-a-diagnostic.exe"), "").unwrap();
+        std::fs::write(second.path().join("PwSh.ExE"), "").unwrap();
+        std::fs::write(second.path().join("WPR.exe"), "").unwrap();
+        std::fs::write(second.path().join("RG.EXE"), "").unwrap();
+        let path = std::env::join_paths([first.path(), second.path()]).unwrap();
+        let tools = probe_windows_path_in(&path, &tool_table(&ContextConfig::default()));
+        assert_eq!(tools.modern_tools, vec!["rg".to_string()]);
+        assert_eq!(
+            tools.diagnostic_tools,
+            vec![
+                "pwsh.exe".to_string(),
+                "wpr.exe".to_string(),
+                "pnputil.exe".to_string()
+            ]
+        );
+        assert_eq!(tools.pwsh, Some("pwsh.exe"));
+    }
+
+    #[test]
+    fn windows_path_probe_never_selects_legacy_windows_powershell() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("powershell.exe"), "").unwrap();
+        let path = std::env::join_paths([dir.path()]).unwrap();
+        let tools = probe_windows_path_in(&path, &tool_table(&ContextConfig::default()));
+        assert_eq!(tools.pwsh, None);
+        assert!(tools.diagnostic_tools.is_empty());
+    }
+
+    #[test]
+    fn executable_selection_requires_pwsh() {
+        assert_eq!(require_pwsh(Some("pwsh.exe")).unwrap(), "pwsh.exe");
+        let error = require_pwsh(None).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "pwsh 7.4+ is required on Windows; pwsh.exe was not found on PATH"
+        );
+    }
+
+    #[test]
+    fn pwsh_version_enforces_7_4_boundary_numerically() {
+        for accepted in ["7.4", "7.4.0", "7.10.2", "8.0", "10.1.2.3"] {
+            assert!(
+                validate_pwsh_version(accepted).is_ok(),
+                "{accepted:?} should be accepted"
+            );
+        }
+        for rejected in [
+            "5.1",
+            "7.3",
+            "7.3.99",
+            "",
+            "7",
+            "seven.four",
+            "7.4-preview",
+            "7.4.1.2.3",
+        ] {
+            let error = validate_pwsh_version(rejected).unwrap_err().to_string();
+            assert!(
+                error.contains("pwsh 7.4+ is required"),
+                "unexpected error for {rejected:?}: {error}"
+            );
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Project task discovery.
+//!
+//! Marker files say *what* a project is; this module reads a few of them to
+//! say what can be *run*: package.json scripts, Makefile and justfile
+//! targets, Cargo workspace members and aliases, pyproject tool sections,
+//! and compose services. "Run the integration tests" can then map to the
+//! repo's real target instead of a guess.
+//!
+//! Everything here is bounded: files over [`MAX_FILE_BYTES`] are skipped,
+//! total reads stop at [`MAX_TOTAL_BYTES`], and parsing stops at the
+//! deadline. A partial summary is better than a slow keystroke.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Largest single file parsed.
+const MAX_FILE_BYTES: u64 = 64 * 1024;
+/// Total bytes read across all project files.
+const MAX_TOTAL_BYTES: u64 = 256 * 1024;
+/// Wall-clock budget for the whole scan.
+pub const TIME_BUDGET: Duration = Duration::from_millis(50);
+/// Most names listed per source.
+const MAX_NAMES: usize = 15;
+
+const COMPOSE_FILES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yml",
+    "docker-compose.yaml",
+];
+
+/// Summarize runnable project tasks in `dir`, one line per source, e.g.
+/// `npm scripts (npm run <name>): build, test, lint`.
+pub fn project_tasks(dir: &Path, budget: Duration) -> Vec<String> {
+    let mut reader = BudgetReader {
+        deadline: Instant::now() + budget,
+        remaining: MAX_TOTAL_BYTES,
+    };
+    let mut lines = Vec::new();
+
+    if let Some(text) = reader.read(&dir.join("package.json")) {
+        if let Some(line) = npm_scripts(&text, &package_runner(dir)) {
+            lines.push(line);
+        }
+    }
+    for name in ["Makefile", "makefile", "GNUmakefile"] {
+        if let Some(text) = reader.read(&dir.join(name)) {
+            lines.extend(summarize("make targets", make_targets(&text)));
+            break;
+        }
+    }
+    for name in ["justfile", "Justfile", ".justfile"] {
+        if let Some(text) = reader.read(&dir.join(name)) {
+            lines.extend(summarize("just recipes", just_recipes(&text)));
+            break;
+        }
+    }
+    if let Some(text) = reader.read(&dir.join("Cargo.toml")) {
+        lines.extend(summarize("cargo workspace members", cargo_members(&text)));
+    }
+    for name in [".cargo/config.toml", ".cargo/config"] {
+        if let Some(text) = reader.read(&dir.join(name)) {
+            lines.extend(summarize("cargo aliases", cargo_aliases(&text)));
+            break;
+        }
+    }
+    if let Some(text) = reader.read(&dir.join("pyproject.toml")) {
+        let (tools, scripts) = pyproject_sections(&text);
+        lines.extend(summarize("python tools", tools));
+        lines.extend(summarize("python scripts", scripts));
+    }
+    for name in COMPOSE_FILES {
+        if let Some(text) = reader.read(&dir.join(name)) {
+            lines.extend(summarize("compose services", compose_services(&text)));
+            break;
+        }
+    }
+    lines
+}
+
+/// Reads files while both the byte and time budgets last.
+struct BudgetReader {
+    deadline: Instant,
+    remaining: u64,
+}
+
+impl BudgetReader {
+    fn read(&mut self, path: &Path) -> Option<String> {
+        if Instant::now() >= self.deadline {
+            return None;
+        }
+        let len = std::fs::metadata(path).ok()?.len();
+        if len > MAX_FILE_BYTES || len > self.remaining {
+            return None;
+        }
+        self.remaining -= len;
+        std::fs::read_to_string(path).ok()
+    }
+}
+
+/// `label: a, b, c` with at most [`MAX_NAMES`] names, noting the rest.
+fn summarize(label: &str, names: Vec<String>) -> Option<String> {
+    if names.is_empty() {
+        return None;
+    }
+    let extra = names.len().saturating_sub(MAX_NAMES);
+    let mut line = format!(
+        "{}: {}",
+        label,
+        names
+            .into_iter()
+            .take(MAX_NAMES)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if extra > 0 {
+        line.push_str(&format!(" (+{} more)", extra));
+    }
+    Some(line)
+}
+
+/// The command that runs package.json scripts, judged by lockfile.
+fn package_runner(dir: &Path) -> String {
+    let runner = if dir.join("pnpm-lock.yaml").exists() {
+        "pnpm run"
+    } else if dir.join("yarn.lock").exists() {
+        "yarn run"
+    } else if dir.join("bun.lockb").exists() || dir.join("bun.lock").exists() {
+        "bun run"
+    } else {
+        "npm run"
+    };
+    runner.to_string()
+}
+
+fn npm_scripts(text: &str, runner: &str) -> Option<String> {
+    let package: serde_json::Value = serde_json::from_str(text).ok()?;
+    let names = package
+        .get("scripts")?
+        .as_object()?
+        .keys()
+        .cloned()
+        .collect();
+    summarize(&format!("npm scripts ({} <name>)", runner), names)
+}
+
+/// Explicit Makefile targets: `name:` at column 0, excluding special
+/// targets (`.PHONY`), pattern rules, and `:=`/`::=` assignments.
+fn make_targets(text: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    for line in text.lines() {
+        if line.starts_with(['\t', ' ', '#', '.']) {
+            continue;
+        }
+        let Some(colon) = line.find(':') else {
+            continue;
+        };
+        if line[colon..].starts_with(":=") || line[colon..].starts_with("::=") {
+            continue;
+        }
+        let head = &line[..colon];
+        if head.contains(['=', '%', '$', '(']) {
+            continue;
+        }
+        for target in head.split_whitespace() {
+            if !targets.iter().any(|t| t == target) {
+                targets.push(target.to_string());
+            }
+        }
+    }
+    targets
+}
+
+/// justfile recipes: `name params*:` at column 0. Settings, aliases,
+/// variables, and private `_` recipes are skipped.
+fn just_recipes(text: &str) -> Vec<String> {
+    const KEYWORDS: &[&str] = &["set", "alias", "export", "import", "mod"];
+    let mut recipes = Vec::new();
+    for line in text.lines() {
+        if line.starts_with([' ', '\t', '#', '[']) || line.contains(":=") {
+            continue;
+        }
+        let Some((head, _)) = line.split_once(':') else {
+            continue;
+        };
+        let mut words = head.trim_start_matches('@').split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if valid && !name.starts_with('_') && !KEYWORDS.contains(&name) {
+            recipes.push(name.to_string());
+        }
+    }
+    recipes
+}
+
+fn cargo_members(text: &str) -> Vec<String> {
+    let Ok(manifest) = toml::from_str::<toml::Value>(text) else {
+        return Vec::new();
+    };
+    manifest
+        .get("workspace")
+        .and_then(|w| w.get("members"))
+        .and_then(|m| m.as_array())
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|m| m.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn cargo_aliases(text: &str) -> Vec<String> {
+    let Ok(config) = toml::from_str::<toml::Value>(text) else {
+        return Vec::new();
+    };
+    config
+        .get("alias")
+        .and_then(|a| a.as_table())
+        .map(|aliases| aliases.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// (`[tool.*]` names, `[project.scripts]` + poetry script names).
+fn pyproject_sections(text: &str) -> (Vec<String>, Vec<String>) {
+    let Ok(project) = toml::from_str::<toml::Value>(text) else {
+        return (Vec::new(), Vec::new());
+    };
+    let keys = |value: Option<&toml::Value>| -> Vec<String> {
+        value
+            .and_then(|v| v.as_table())
+            .map(|t| t.keys().cloned().collect())
+            .unwrap_or_default()
+    };
+    let tool = project.get("tool");
+    let mut scripts = keys(project.get("project").and_then(|p| p.get("scripts")));
+    for script in keys(
+        tool.and_then(|t| t.get("poetry"))
+            .and_then(|p| p.get("scripts")),
+    ) {
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+    (keys(tool), scripts)
+}
+
+/// Service names: the keys one level under a top-level `services:`.
+/// A line-based reading of the YAML, which is all compose files need.
+fn compose_services(text: &str) -> Vec<String> {
+    let mut services = Vec::new();
+    let mut in_services = false;
+    let mut indent = None;
+    for line in text.lines() {
+        let trimmed = line.trim_end();
+        if trimmed.trim_start().is_empty() || trimmed.trim_start().starts_with('#') {
+            continue;
+        }
+        let depth = trimmed.len() - trimmed.trim_start().len();
+        if depth == 0 {
+            in_services = trimmed == "services:";
+            indent = None;
+            continue;
+        }
+        if !in_services {
+            continue;
+        }
+        let level = *indent.get_or_insert(depth);
+        if depth == level {
+            if let Some(name) = trimmed.trim_start().strip_suffix(':') {
+                services.push(name.trim_matches(['"', '\'']).to_string());
+            }
+        }
+    }
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_every_supported_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(
+            "package.json",
+            r#"{"scripts":{"build":"tsc","test:e2e":"playwright test"}}"#,
+        );
+        write("pnpm-lock.yaml", "");
+        write(
+            "Makefile",
+            ".PHONY: test\nCC := gcc\nall: build\n\tcc main.c\nbuild test: deps\n%.o: %.c\n",
+        );
+        write(
+            "justfile",
+            "set shell := [\"bash\"]\nalias t := test\n# comment\ntest filter='': build\n    cargo test\n_hidden:\n@lint:\n",
+        );
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/core\", \"crates/cli\"]\n",
+        );
+        write(
+            ".cargo/config.toml",
+            "[alias]\nxtask = \"run -p xtask --\"\n",
+        );
+        write(
+            "pyproject.toml",
+            "[project.scripts]\nserve = \"app:main\"\n[tool.pytest.ini_options]\n[tool.ruff]\n",
+        );
+        write(
+            "compose.yaml",
+            "version: '3'\nservices:\n  db:\n    image: postgres\n    ports:\n      - 5432\n  web:\n    build: .\nvolumes:\n  data:\n",
+        );
+
+        assert_eq!(
+            project_tasks(dir.path(), Duration::from_secs(5)),
+            vec![
+                "npm scripts (pnpm run <name>): build, test:e2e",
+                "make targets: all, build, test",
+                "just recipes: test, lint",
+                "cargo workspace members: crates/core, crates/cli",
+                "cargo aliases: xtask",
+                "python tools: pytest, ruff",
+                "python scripts: serve",
+                "compose services: db, web",
+            ]
+        );
+    }
+
+    #[test]
+    fn budgets_skip_large_files_and_expired_deadlines() {
+        let dir = tempfile::tempdir().unwrap();
+        let huge = format!("big:\n{}", "# padding\n".repeat(10_000));
+        std::fs::write(dir.path().join("Makefile"), huge).unwrap();
+        assert!(project_tasks(dir.path(), Duration::from_secs(5)).is_empty());
+
+        std::fs::write(dir.path().join("Makefile"), "all:\n").unwrap();
+        assert!(project_tasks(dir.path(), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn long_lists_are_capped() {
+        let names = (0..20).map(|i| format!("t{i}")).collect();
+        let line = summarize("make targets", names).unwrap();
+        assert!(line.ends_with("t14 (+5 more)"), "{line}");
+        assert_eq!(summarize("x", Vec::new()), None);
+    }
+}
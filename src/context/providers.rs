@@ -0,0 +1,217 @@
+//! Context provider plugins: site-specific facts from external programs.
+//!
+//! Each `[[context.providers]]` entry names an executable that prints one
+//! JSON object of key/value pairs on stdout, e.g.
+//! `{"cluster": "prod-eu-2", "vpn": "corp"}`. It runs in cwd with stdin
+//! closed and is killed at its `timeout_ms` or at the context deadline,
+//! whichever comes first. A provider that fails, times out, or prints
+//! something else contributes nothing; the reason is logged at warn level
+//! (visible with `RUST_LOG=incant=warn`) and the query goes ahead.
+
+use crate::config::ProviderConfig;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Most stdout read from a provider.
+const MAX_OUTPUT_BYTES: u64 = 16 * 1024;
+/// Most pairs taken from one provider.
+const MAX_PAIRS: usize = 32;
+/// Longest key kept.
+const MAX_KEY_CHARS: usize = 64;
+/// Longer values are cut to this many characters.
+const MAX_VALUE_CHARS: usize = 200;
+/// How often a running provider is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Run `provider` in `cwd` for at most `limit` (or its own, shorter,
+/// timeout). None on any failure.
+pub fn run(
+    provider: &ProviderConfig,
+    cwd: &Path,
+    limit: Duration,
+) -> Option<BTreeMap<String, String>> {
+    let timeout = Duration::from_millis(provider.timeout_ms).min(limit);
+    match run_inner(provider, cwd, timeout) {
+        Ok(pairs) => Some(pairs),
+        Err(reason) => {
+            warn!(
+                "Context provider {:?} skipped: {}",
+                provider.command, reason
+            );
+            None
+        }
+    }
+}
+
+fn run_inner(
+    provider: &ProviderConfig,
+    cwd: &Path,
+    timeout: Duration,
+) -> Result<BTreeMap<String, String>, String> {
+    let started = Instant::now();
+    let mut child = Command::new(&provider.command)
+        .args(&provider.args)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run it: {}", e))?;
+
+    // Read on another thread so a chatty provider cannot block on a full
+    // pipe while this one waits for it to exit.
+    let stdout = child.stdout.take();
+    let (sender, output) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(stdout) = stdout {
+            let _ = stdout.take(MAX_OUTPUT_BYTES).read_to_end(&mut bytes);
+        }
+        let _ = sender.send(bytes);
+    });
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < timeout => std::thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("no answer within {} ms", timeout.as_millis()));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    if !status.success() {
+        return Err(format!("exited with {}", status));
+    }
+    // A background process the provider started may still hold stdout.
+    let bytes = output
+        .recv_timeout(
+            timeout
+                .saturating_sub(started.elapsed())
+                .max(POLL_INTERVAL * 4),
+        )
+        .map_err(|_| "stdout left open after exit".to_string())?;
+    parse(&bytes)
+}
+
+/// Key/value pairs from a provider's stdout. Strings, numbers, and
+/// booleans are kept; nulls, arrays, and objects are skipped.
+fn parse(stdout: &[u8]) -> Result<BTreeMap<String, String>, String> {
+    let value: serde_json::Value =
+        serde_json::from_slice(stdout).map_err(|e| format!("invalid JSON: {}", e))?;
+    let serde_json::Value::Object(map) = value else {
+        return Err("output is not a JSON object".to_string());
+    };
+    let mut pairs = BTreeMap::new();
+    for (key, value) in map {
+        let key = single_line(&key);
+        if key.is_empty() || key.chars().count() > MAX_KEY_CHARS {
+            continue;
+        }
+        let value = match value {
+            serde_json::Value::String(s) => single_line(&s),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            _ => continue,
+        };
+        let value = match value.char_indices().nth(MAX_VALUE_CHARS) {
+            Some((end, _)) => format!("{}...", &value[..end]),
+            None => value,
+        };
+        pairs.insert(key, value);
+        if pairs.len() == MAX_PAIRS {
+            break;
+        }
+    }
+    Ok(pairs)
+}
+
+/// Collapse whitespace, including newlines, so every pair stays one
+/// prompt line.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_scalar_pairs_on_one_line() {
+        let long = "x".repeat(MAX_VALUE_CHARS + 5);
+        let output = format!(
+            r#"{{"cluster": "prod-eu-2", "replicas": 3, "vpn": true, "note": "line one\nline two",
+               "nested": {{"a": 1}}, "list": [1], "none": null, "": "x", "long": "{}"}}"#,
+            long
+        );
+        let pairs = parse(output.as_bytes()).unwrap();
+        assert_eq!(pairs["cluster"], "prod-eu-2");
+        assert_eq!(pairs["replicas"], "3");
+        assert_eq!(pairs["vpn"], "true");
+        assert_eq!(pairs["note"], "line one line two");
+        assert_eq!(pairs["long"].chars().count(), MAX_VALUE_CHARS + 3);
+        assert_eq!(pairs.len(), 5);
+
+        assert!(parse(b"[1, 2]").is_err());
+        assert!(parse(b"cluster=prod").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_and_slow_providers_contribute_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let sh = |script: &str, timeout_ms: u64| ProviderConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            timeout_ms,
+        };
+        let limit = Duration::from_secs(5);
+
+        let pairs = run(
+            &sh(r#"printf '{"dir": "%s"}' "$(basename "$PWD")""#, 2_000),
+            dir.path(),
+            limit,
+        )
+        .unwrap();
+        let name = dir.path().file_name().unwrap().to_string_lossy();
+        assert_eq!(pairs["dir"], name);
+
+        assert_eq!(
+            run(&sh("echo '{}'; exit 3", 2_000), dir.path(), limit),
+            None
+        );
+        assert_eq!(
+            run(
+                &ProviderConfig {
+                    command: dir.path().join("missing").display().to_string(),
+                    args: Vec::new(),
+                    timeout_ms: 2_000,
+                },
+                dir.path(),
+                limit
+            ),
+            None
+        );
+
+        let started = Instant::now();
+        assert_eq!(run(&sh("sleep 5", 50), dir.path(), limit), None);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // The context deadline caps a generous per-provider timeout.
+        let started = Instant::now();
+        assert_eq!(
+            run(
+                &sh("sleep 5", 10_000),
+                dir.path(),
+                Duration::from_millis(50)
+            ),
+            None
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,182 @@
+//! Runtime environment: where the shell runs and who it runs as.
+//!
+//! Environment flags come from environment variables and a few marker
+//! files: remote and multiplexed sessions, containers and pods, WSL, and
+//! activated Python, conda, nix, or direnv environments. On POSIX hosts the
+//! effective user, the privilege escalation commands, and the package
+//! managers on PATH let "install htop" come back as `apk add htop` in an
+//! Alpine container running as root rather than `sudo apt install htop`.
+
+#[cfg(not(windows))]
+use crate::protocol::HostContext;
+use std::path::Path;
+
+/// Privilege escalation commands, preferred first.
+pub const ESCALATION_TOOLS: &[&str] = &["sudo", "doas"];
+
+/// Package managers, system package managers before user-level ones so the
+/// first one found is the one to install system packages with.
+pub const PACKAGE_MANAGERS: &[&str] = &["apt", "dnf", "pacman", "apk", "brew", "nix"];
+
+/// Environment flags for the current process.
+pub fn env_flags() -> Vec<String> {
+    env_flags_from(&|name| std::env::var(name).ok(), Path::new("/"))
+}
+
+/// [`env_flags`] with the environment and filesystem root passed in.
+fn env_flags_from(env: &dyn Fn(&str) -> Option<String>, root: &Path) -> Vec<String> {
+    let env = |name: &str| env(name).filter(|value| !value.trim().is_empty());
+    let mut flags = Vec::new();
+
+    if env("SSH_CONNECTION").is_some() || env("SSH_TTY").is_some() {
+        flags.push("ssh".to_string());
+    }
+    if env("TMUX").is_some() {
+        flags.push("tmux".to_string());
+    }
+    if root.join(".dockerenv").exists() {
+        flags.push("docker".to_string());
+    }
+    if root.join("run/.containerenv").exists() {
+        flags.push("podman".to_string());
+    }
+    if env("KUBERNETES_SERVICE_HOST").is_some() {
+        flags.push("kubernetes pod".to_string());
+    }
+    if env("WSL_DISTRO_NAME").is_some()
+        || env("WSL_INTEROP").is_some()
+        || std::fs::read_to_string(root.join("proc/sys/kernel/osrelease"))
+            .is_ok_and(|release| release.to_ascii_lowercase().contains("microsoft"))
+    {
+        flags.push("wsl".to_string());
+    }
+
+    if let Some(venv) = env("VIRTUAL_ENV") {
+        // Newer venvs export the prompt name; older ones only the path.
+        let name = env("VIRTUAL_ENV_PROMPT")
+            .map(|prompt| {
+                prompt
+                    .trim()
+                    .trim_start_matches('(')
+                    .trim_end_matches(')')
+                    .trim()
+                    .to_string()
+            })
+            .filter(|name| !name.is_empty())
+            .or_else(|| {
+                Path::new(&venv)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+        flags.push(match name {
+            Some(name) => format!("python venv ({})", name),
+            None => "python venv".to_string(),
+        });
+    }
+    if let Some(conda) = env("CONDA_DEFAULT_ENV") {
+        flags.push(format!("conda env ({})", conda.trim()));
+    }
+    if env("IN_NIX_SHELL").is_some() {
+        flags.push("nix-shell".to_string());
+    }
+    if env("DIRENV_DIR").is_some() {
+        flags.push("direnv".to_string());
+    }
+    flags
+}
+
+/// Privileges and package managers, given the names found on PATH.
+#[cfg(not(windows))]
+pub fn host(installed: &[String]) -> HostContext {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let root = unsafe { libc::geteuid() } == 0;
+    host_from(root, installed)
+}
+
+#[cfg(not(windows))]
+fn host_from(root: bool, installed: &[String]) -> HostContext {
+    let found = |table: &[&str]| {
+        table
+            .iter()
+            .filter(|name| installed.iter().any(|i| i == *name))
+            .map(|name| (*name).to_string())
+            .collect()
+    };
+    HostContext {
+        root,
+        escalation: found(ESCALATION_TOOLS),
+        package_managers: found(PACKAGE_MANAGERS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn detects_sessions_containers_and_activated_environments() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("run")).unwrap();
+        std::fs::write(root.path().join("run/.containerenv"), "").unwrap();
+        std::fs::create_dir_all(root.path().join("proc/sys/kernel")).unwrap();
+        std::fs::write(
+            root.path().join("proc/sys/kernel/osrelease"),
+            "5.15.153.1-microsoft-standard-WSL2\n",
+        )
+        .unwrap();
+        let env: HashMap<&str, &str> = HashMap::from([
+            ("SSH_TTY", "/dev/pts/3"),
+            ("TMUX", ""),
+            ("KUBERNETES_SERVICE_HOST", "10.96.0.1"),
+            ("VIRTUAL_ENV", "/home/me/src/api/.venv"),
+            ("VIRTUAL_ENV_PROMPT", "(api) "),
+            ("CONDA_DEFAULT_ENV", "base"),
+            ("IN_NIX_SHELL", "impure"),
+            ("DIRENV_DIR", "-/home/me/src/api"),
+        ]);
+
+        let flags = env_flags_from(&|name| env.get(name).map(|v| v.to_string()), root.path());
+        assert_eq!(
+            flags,
+            vec![
+                "ssh",
+                "podman",
+                "kubernetes pod",
+                "wsl",
+                "python venv (api)",
+                "conda env (base)",
+                "nix-shell",
+                "direnv",
+            ]
+        );
+
+        let venv_only: HashMap<&str, &str> = HashMap::from([("VIRTUAL_ENV", "/srv/app/venv")]);
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(
+            env_flags_from(
+                &|name| venv_only.get(name).map(|v| v.to_string()),
+                empty.path()
+            ),
+            vec!["python venv (venv)"]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn host_lists_escalation_and_package_managers_in_preference_order() {
+        let installed: Vec<String> = ["brew", "rg", "doas", "apt", "sudo"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            host_from(false, &installed),
+            HostContext {
+                root: false,
+                escalation: vec!["sudo".to_string(), "doas".to_string()],
+                package_managers: vec!["apt".to_string(), "brew".to_string()],
+            }
+        );
+        assert!(host_from(true, &[]).root);
+    }
+}
@@ -0,0 +1,246 @@
+//! Core utility dialect detection.
+//!
+//! The classic wrong answer is GNU `sed -i` or `find -printf` on macOS or in
+//! an Alpine container. This module works out which implementation of each
+//! key utility is first on PATH: GNU, BSD, BusyBox, or uutils.
+//!
+//! It stays cheap in three ways. BusyBox applets are recognised from their
+//! symlink, with no process at all. Tools from one package in one directory
+//! (coreutils' `date`/`stat`, findutils' `find`/`xargs`) share a single
+//! `--version` probe. Results are cached per PATH and invalidated when a
+//! resolved binary changes. A probe that hangs is killed after
+//! [`PROBE_TIMEOUT`] and tells nothing.
+
+use super::cache::{self, KeyHasher};
+use crate::protocol::UtilityFlavor;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Utilities worth fingerprinting, grouped by the package that ships them.
+/// One probe answers for every tool in a group that lives in the same
+/// directory.
+const PROBE_GROUPS: &[&[&str]] = &[
+    &["sed"],
+    &["grep"],
+    &["tar"],
+    &["find", "xargs"],
+    &["date", "stat"],
+];
+
+/// Longest a single `--version` probe may run.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often a running probe is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Detect each utility's flavor, using the on-disk cache when the resolved
+/// binaries are unchanged.
+pub fn detect() -> BTreeMap<String, UtilityFlavor> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return BTreeMap::new();
+    };
+    let resolved = resolve_tools(&path);
+    cache::cached("userland", &cache_key(&resolved), || probe(&resolved))
+}
+
+/// First match on PATH for every probed tool.
+fn resolve_tools(path: &std::ffi::OsStr) -> BTreeMap<&'static str, PathBuf> {
+    let mut resolved = BTreeMap::new();
+    for dir in std::env::split_paths(path) {
+        for tool in PROBE_GROUPS.iter().flat_map(|group| group.iter()) {
+            if resolved.contains_key(tool) {
+                continue;
+            }
+            let candidate = dir.join(tool);
+            if candidate.is_file() {
+                resolved.insert(*tool, candidate);
+            }
+        }
+    }
+    resolved
+}
+
+/// Cache key over the resolved paths and their modification times, so
+/// installing GNU sed or switching PATH invalidates the entry.
+fn cache_key(resolved: &BTreeMap<&'static str, PathBuf>) -> String {
+    let mut key = KeyHasher::default();
+    for (tool, path) in resolved {
+        key = key
+            .field(tool)
+            .field(path.as_os_str().as_encoded_bytes())
+            .mtime(path);
+    }
+    key.finish()
+}
+
+/// Fingerprint every resolved tool, spawning at most one process per group.
+fn probe(resolved: &BTreeMap<&'static str, PathBuf>) -> BTreeMap<String, UtilityFlavor> {
+    let mut flavors = BTreeMap::new();
+    // Memoized by (group, directory): one --version answers for the rest.
+    let mut probed: HashMap<(usize, PathBuf), Option<UtilityFlavor>> = HashMap::new();
+    for (index, group) in PROBE_GROUPS.iter().enumerate() {
+        for tool in group.iter() {
+            let Some(path) = resolved.get(tool) else {
+                continue;
+            };
+            let flavor = if is_busybox_applet(path) {
+                Some(UtilityFlavor::Busybox)
+            } else {
+                let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                *probed
+                    .entry((index, dir))
+                    .or_insert_with(|| version_flavor(path))
+            };
+            if let Some(flavor) = flavor {
+                flavors.insert((*tool).to_string(), flavor);
+            }
+        }
+    }
+    flavors
+}
+
+/// BusyBox installs each applet as a link to the `busybox` binary.
+fn is_busybox_applet(path: &Path) -> bool {
+    std::fs::canonicalize(path)
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_os_string()))
+        .is_some_and(|name| name == "busybox")
+}
+
+/// Run `<tool> --version` and classify what comes back. A tool still
+/// running after [`PROBE_TIMEOUT`] is killed and left unclassified.
+fn version_flavor(path: &Path) -> Option<UtilityFlavor> {
+    let started = Instant::now();
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    // A version banner fits in the pipe buffer, so reading after exit
+    // cannot deadlock.
+    let status = loop {
+        match child.try_wait().ok()? {
+            Some(status) => break status,
+            None if started.elapsed() < PROBE_TIMEOUT => std::thread::sleep(POLL_INTERVAL),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let mut bytes = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        let _ = stdout.take(4096).read_to_end(&mut bytes);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let _ = stderr.take(4096).read_to_end(&mut bytes);
+    }
+    classify(&String::from_utf8_lossy(&bytes), status.success())
+}
+
+/// Classify `--version` output. BSD tools mostly reject the flag outright,
+/// so a failure on a BSD-family host counts as BSD.
+fn classify(text: &str, succeeded: bool) -> Option<UtilityFlavor> {
+    if text.contains("BusyBox") {
+        Some(UtilityFlavor::Busybox)
+    } else if text.contains("uutils") {
+        Some(UtilityFlavor::Uutils)
+    } else if text.contains("BSD") || text.contains("bsdtar") {
+        // Checked before GNU: macOS grep says "BSD grep, GNU compatible".
+        Some(UtilityFlavor::Bsd)
+    } else if text.contains("GNU") || text.contains("Free Software Foundation") {
+        Some(UtilityFlavor::Gnu)
+    } else if !succeeded
+        && cfg!(any(
+            target_os = "macos",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        ))
+    {
+        Some(UtilityFlavor::Bsd)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_version_banners() {
+        assert_eq!(
+            classify("sed (GNU sed) 4.9\nCopyright", true),
+            Some(UtilityFlavor::Gnu)
+        );
+        assert_eq!(
+            classify("grep (BSD grep, GNU compatible) 2.6.0-FreeBSD", true),
+            Some(UtilityFlavor::Bsd)
+        );
+        assert_eq!(
+            classify("bsdtar 3.5.3 - libarchive 3.5.3", true),
+            Some(UtilityFlavor::Bsd)
+        );
+        assert_eq!(
+            classify("BusyBox v1.36.1 (2023-07-27) multi-call binary.", false),
+            Some(UtilityFlavor::Busybox)
+        );
+        assert_eq!(
+            classify("date (uutils coreutils) 0.0.27", true),
+            Some(UtilityFlavor::Uutils)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probes_once_per_group_directory_and_spots_busybox_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("probes.log");
+        let script = format!(
+            "#!/bin/sh\necho \"$0\" >> {}\necho 'find (GNU findutils) 4.9.0'\n",
+            log.display()
+        );
+        for tool in ["find", "xargs"] {
+            let path = dir.path().join(tool);
+            std::fs::write(&path, &script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let busybox = dir.path().join("busybox");
+        std::fs::write(&busybox, "").unwrap();
+        std::os::unix::fs::symlink(&busybox, dir.path().join("sed")).unwrap();
+
+        let resolved = resolve_tools(dir.path().as_os_str());
+        let flavors = probe(&resolved);
+        assert_eq!(flavors.get("find"), Some(&UtilityFlavor::Gnu));
+        assert_eq!(flavors.get("xargs"), Some(&UtilityFlavor::Gnu));
+        assert_eq!(flavors.get("sed"), Some(&UtilityFlavor::Busybox));
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+
+        // Touching a binary changes the cache key.
+        let before = cache_key(&resolved);
+        std::fs::remove_file(dir.path().join("xargs")).unwrap();
+        assert_ne!(before, cache_key(&resolve_tools(dir.path().as_os_str())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_hanging_probe_is_killed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let sed = dir.path().join("sed");
+        std::fs::write(&sed, "#!/bin/sh\nexec sleep 10\n").unwrap();
+        std::fs::set_permissions(&sed, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let started = Instant::now();
+        assert_eq!(version_flavor(&sed), None);
+        assert!(started.elapsed() < PROBE_TIMEOUT * 4);
+    }
+}
@@ -2,11 +2,13 @@
 //!
 //! Uses the Anthropic API for command generation with Claude models.
 
+use super::ModelInfo;
 use anyhow::{anyhow, Context, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_MODELS_URL: &str = "https://api.anthropic.com/v1/models";
 const ANTHROPIC_VERSION: &str = "2023-06-01";
 
 /// Anthropic backend for Claude API.
@@ -113,6 +115,60 @@ impl AnthropicBackend {
         self.get_api_key()?;
         Ok(())
     }
+
+    /// List the models this API key can use, following pagination.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let api_key = self.get_api_key()?;
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+
+        loop {
+            // Model IDs are URL-safe, so the cursor needs no encoding.
+            let url = match &after_id {
+                Some(after) => format!("{}?limit=1000&after_id={}", ANTHROPIC_MODELS_URL, after),
+                None => format!("{}?limit=1000", ANTHROPIC_MODELS_URL),
+            };
+            let response = self
+                .client
+                .get(&url)
+                .header("x-api-key", &api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .send()
+                .await
+                .context("Failed to connect to Anthropic API")?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body: Result<AnthropicError, _> = response.json().await;
+                let message = body
+                    .map(|e| e.error.message)
+                    .unwrap_or_else(|_| "Unknown error".to_string());
+                return Err(anyhow!(
+                    "Anthropic model listing failed with status {}: {}",
+                    status,
+                    message
+                ));
+            }
+
+            let page: AnthropicModelPage = response
+                .json()
+                .await
+                .context("Failed to parse Anthropic model list")?;
+            models.extend(page.data.into_iter().map(|m| {
+                ModelInfo {
+                    name: m.id,
+                    size: None,
+                    modified: m
+                        .created_at
+                        .map(|d| d.split('T').next().unwrap_or(&d).to_string()),
+                }
+            }));
+            match (page.has_more, page.last_id) {
+                (true, Some(last)) => after_id = Some(last),
+                _ => return Ok(models),
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -145,6 +201,22 @@ struct AnthropicContent {
     text: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct AnthropicModelPage {
+    data: Vec<AnthropicModel>,
+    #[serde(default)]
+    has_more: bool,
+    #[serde(default)]
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModel {
+    id: String,
+    #[serde(default)]
+    created_at: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct AnthropicError {
     error: AnthropicErrorDetail,
@@ -0,0 +1,214 @@
+//! External process backend implementation.
+//!
+//! The daemon spawns a user-specified program once and talks to it over
+//! stdin/stdout, one JSON object per line in each direction. This lets
+//! internal gateways or experimental models plug in without forking incant.
+//!
+//! Requests carry a `type` of `generate`, `health` or `list_models`. Every
+//! reply is a single line with `response`, `models`, or `error` set.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+/// How long a plugin may take to answer a generate request.
+const GENERATE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a plugin may take to answer health and model-listing requests.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Backend that delegates to an external plugin process.
+pub struct ExecBackend {
+    pub model: String,
+    program: String,
+    args: Vec<String>,
+    process: Mutex<Option<PluginProcess>>,
+}
+
+/// A running plugin and its line-oriented pipes.
+struct PluginProcess {
+    // Held so the child is killed when the process is dropped.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExecBackend {
+    /// Create a new exec backend. The plugin is spawned lazily on first use.
+    pub fn new(model: String, program: String, args: Vec<String>) -> Self {
+        Self {
+            model,
+            program,
+            args,
+            process: Mutex::new(None),
+        }
+    }
+
+    /// Generate a command from a query and system prompt.
+    /// Optionally override the model and temperature for this request.
+    pub async fn generate(
+        &self,
+        system_prompt: &str,
+        user_query: &str,
+        model_override: Option<&str>,
+        temperature_override: Option<f32>,
+    ) -> Result<String> {
+        let request = PluginRequest::Generate {
+            model: model_override.unwrap_or(&self.model),
+            system: system_prompt,
+            prompt: user_query,
+            temperature: temperature_override.unwrap_or(0.1),
+        };
+
+        let response = self.exchange(&request, GENERATE_TIMEOUT).await?;
+        response
+            .response
+            .map(|command| command.trim().to_string())
+            .ok_or_else(|| anyhow!("Exec plugin reply is missing \"response\""))
+    }
+
+    /// Check that the plugin starts and answers a health request.
+    pub async fn health_check(&self) -> Result<()> {
+        self.exchange(&PluginRequest::Health, CONTROL_TIMEOUT)
+            .await
+            .map(|_| ())
+    }
+
+    /// Ask the plugin which models it can serve.
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let response = self
+            .exchange(&PluginRequest::ListModels, CONTROL_TIMEOUT)
+            .await?;
+        response
+            .models
+            .ok_or_else(|| anyhow!("Exec plugin reply is missing \"models\""))
+    }
+
+    /// Send one request line and read one reply line.
+    ///
+    /// Requests are serialized through the lock, so concurrent clients never
+    /// interleave lines. Any transport failure discards the process; the next
+    /// request respawns it.
+    async fn exchange(
+        &self,
+        request: &PluginRequest<'_>,
+        timeout: Duration,
+    ) -> Result<PluginResponse> {
+        let mut guard = self.process.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.spawn()?);
+        }
+        let process = guard.as_mut().expect("plugin process was just spawned");
+
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+
+        let result = tokio::time::timeout(timeout, async {
+            process.stdin.write_all(line.as_bytes()).await?;
+            process.stdin.flush().await?;
+            let mut reply = String::new();
+            if process.stdout.read_line(&mut reply).await? == 0 {
+                return Err(anyhow!("Exec plugin '{}' exited", self.program));
+            }
+            Ok(reply)
+        })
+        .await
+        .map_err(|_| anyhow!("Exec plugin '{}' timed out", self.program))
+        .and_then(|reply| reply);
+
+        let reply = match result {
+            Ok(reply) => reply,
+            Err(e) => {
+                *guard = None;
+                return Err(e);
+            }
+        };
+
+        let response: PluginResponse = match serde_json::from_str(&reply) {
+            Ok(response) => response,
+            Err(e) => {
+                *guard = None;
+                return Err(e).context("Failed to parse exec plugin reply");
+            }
+        };
+        if let Some(error) = response.error {
+            return Err(anyhow!("Exec plugin error: {}", error));
+        }
+        Ok(response)
+    }
+
+    /// Start the plugin with piped stdin/stdout; stderr goes to the daemon log.
+    fn spawn(&self) -> Result<PluginProcess> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start exec plugin '{}'", self.program))?;
+
+        let stdin = child.stdin.take().expect("stdin was piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout was piped"));
+        Ok(PluginProcess {
+            _child: child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PluginRequest<'a> {
+    Generate {
+        model: &'a str,
+        system: &'a str,
+        prompt: &'a str,
+        temperature: f32,
+    },
+    Health,
+    ListModels,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginResponse {
+    #[serde(default)]
+    response: Option<String>,
+    #[serde(default)]
+    models: Option<Vec<String>>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_tagged_json_lines() {
+        let generate = PluginRequest::Generate {
+            model: "m",
+            system: "sys",
+            prompt: "list files",
+            temperature: 0.1,
+        };
+        let json: serde_json::Value = serde_json::to_value(&generate).unwrap();
+        assert_eq!(json["type"], "generate");
+        assert_eq!(json["prompt"], "list files");
+        assert_eq!(
+            serde_json::to_string(&PluginRequest::ListModels).unwrap(),
+            r#"{"type":"list_models"}"#
+        );
+    }
+
+    #[test]
+    fn replies_tolerate_missing_fields() {
+        let reply: PluginResponse = serde_json::from_str(r#"{"ok":true}"#).unwrap();
+        assert!(reply.response.is_none());
+        assert!(reply.error.is_none());
+    }
+}
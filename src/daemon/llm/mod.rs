@@ -1,20 +1,71 @@
 //! LLM backend implementations.
 //!
 //! This module provides a unified interface for different LLM backends
-//! including Ollama (local) and cloud providers (Anthropic, OpenAI).
+//! including Ollama (local), cloud providers (Anthropic, OpenAI), external
+//! plugin processes, and cassette record/replay.
 
 pub mod anthropic;
+pub mod exec;
 pub mod ollama;
 pub mod openai;
+pub mod replay;
 
 use anyhow::Result;
 
+/// A model as reported by a backend's listing endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    /// Model identifier, as accepted in a profile's `model` field.
+    pub name: String,
+    /// Size on disk in bytes (local backends only).
+    pub size: Option<u64>,
+    /// Last-modified or creation date (YYYY-MM-DD) when the backend reports one.
+    pub modified: Option<String>,
+}
+
+impl ModelInfo {
+    /// A listing entry that carries only a name.
+    fn named(name: String) -> Self {
+        Self {
+            name,
+            size: None,
+            modified: None,
+        }
+    }
+}
+
+/// Whether the listed model `available` satisfies a profile's `wanted` model.
+///
+/// Beyond exact matches this accepts Ollama's implicit `:latest` tag
+/// (`llama3` is `llama3:latest`) and provider `-latest` aliases, which
+/// resolve to a dated snapshot (`claude-3-5-haiku-latest` is served as
+/// `claude-3-5-haiku-20241022`).
+pub fn model_matches(available: &str, wanted: &str) -> bool {
+    if available == wanted {
+        return true;
+    }
+    if !wanted.contains(':') && available.strip_suffix(":latest") == Some(wanted) {
+        return true;
+    }
+    wanted
+        .strip_suffix("latest")
+        .filter(|stem| stem.ends_with('-'))
+        .is_some_and(|stem| {
+            available
+                .strip_prefix(stem)
+                .is_some_and(|rest| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
 /// Enum-based backend for LLM providers.
 /// Using an enum instead of trait objects for better performance and simplicity.
 pub enum Backend {
     Ollama(ollama::OllamaBackend),
     Anthropic(anthropic::AnthropicBackend),
     OpenAI(openai::OpenAIBackend),
+    Exec(Box<exec::ExecBackend>),
+    Replay(replay::ReplayBackend),
+    Record(Box<replay::RecordingBackend>),
 }
 
 impl Backend {
@@ -55,6 +106,33 @@ impl Backend {
                 )
                 .await
             }
+            Backend::Exec(b) => {
+                b.generate(
+                    system_prompt,
+                    user_query,
+                    model_override,
+                    temperature_override,
+                )
+                .await
+            }
+            Backend::Replay(b) => {
+                b.generate(
+                    system_prompt,
+                    user_query,
+                    model_override,
+                    temperature_override,
+                )
+                .await
+            }
+            Backend::Record(b) => {
+                b.generate(
+                    system_prompt,
+                    user_query,
+                    model_override,
+                    temperature_override,
+                )
+                .await
+            }
         }
     }
 
@@ -64,6 +142,9 @@ impl Backend {
             Backend::Ollama(_) => "ollama",
             Backend::Anthropic(_) => "anthropic",
             Backend::OpenAI(_) => "openai",
+            Backend::Exec(_) => "exec",
+            Backend::Replay(_) => "replay",
+            Backend::Record(b) => b.inner.name(),
         }
     }
 
@@ -73,6 +154,37 @@ impl Backend {
             Backend::Ollama(b) => &b.model,
             Backend::Anthropic(b) => &b.model,
             Backend::OpenAI(b) => &b.model,
+            Backend::Exec(b) => &b.model,
+            Backend::Replay(b) => &b.model,
+            Backend::Record(b) => b.inner.model(),
+        }
+    }
+
+    /// List the models this backend can serve.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        match self {
+            Backend::Ollama(b) => b.list_models().await,
+            Backend::Anthropic(b) => b.list_models().await,
+            Backend::OpenAI(b) => b.list_models().await,
+            Backend::Exec(b) => Ok(b
+                .list_models()
+                .await?
+                .into_iter()
+                .map(ModelInfo::named)
+                .collect()),
+            Backend::Replay(b) => Ok(b.list_models().into_iter().map(ModelInfo::named).collect()),
+            // Boxed: the wrapped backend's listing recurses into this fn.
+            Backend::Record(b) => Box::pin(b.inner.list_models()).await,
+        }
+    }
+
+    /// The Ollama backend underneath this one, if any, seeing through
+    /// recording.
+    pub fn as_ollama(&self) -> Option<&ollama::OllamaBackend> {
+        match self {
+            Backend::Ollama(b) => Some(b),
+            Backend::Record(b) => b.inner.as_ollama(),
+            _ => None,
         }
     }
 
@@ -82,24 +194,77 @@ impl Backend {
             Backend::Ollama(b) => b.health_check().await,
             Backend::Anthropic(b) => b.health_check().await,
             Backend::OpenAI(b) => b.health_check().await,
+            Backend::Exec(b) => b.health_check().await,
+            Backend::Replay(b) => b.health_check().await,
+            // Boxed: the wrapped backend's health check recurses into this fn.
+            Backend::Record(b) => Box::pin(b.inner.health_check()).await,
         }
     }
 }
 
 /// Create a backend from configuration.
-/// The model is resolved from the default profile in the config.
-pub fn create_backend(config: &crate::config::Config) -> Backend {
+/// The model is resolved from the default profile in the config. With
+/// `record = true`, the backend is wrapped so its responses go to a cassette.
+pub fn create_backend(config: &crate::config::Config) -> Result<Backend> {
     let model = config.model_name();
 
-    match &config.backend {
-        crate::config::BackendConfig::Ollama { host, .. } => {
-            Backend::Ollama(ollama::OllamaBackend::new(model, host.clone()))
-        }
+    let backend = match &config.backend {
+        crate::config::BackendConfig::Ollama {
+            host, auto_pull, ..
+        } => Backend::Ollama(ollama::OllamaBackend::new(model, host.clone(), *auto_pull)),
         crate::config::BackendConfig::Anthropic { api_key, .. } => {
             Backend::Anthropic(anthropic::AnthropicBackend::new(model, api_key.clone()))
         }
-        crate::config::BackendConfig::OpenAI { api_key, .. } => {
-            Backend::OpenAI(openai::OpenAIBackend::new(model, api_key.clone()))
+        crate::config::BackendConfig::OpenAI {
+            api_key, base_url, ..
+        } => Backend::OpenAI(openai::OpenAIBackend::new(
+            model,
+            api_key.clone(),
+            base_url.clone(),
+        )),
+        crate::config::BackendConfig::Exec { command, args, .. } => Backend::Exec(Box::new(
+            exec::ExecBackend::new(model, command.clone(), args.clone()),
+        )),
+        crate::config::BackendConfig::Replay { cassette, .. } => {
+            let path = match cassette {
+                Some(path) => path.clone(),
+                None => crate::config::Config::cassette_path()?,
+            };
+            return Ok(Backend::Replay(replay::ReplayBackend::new(
+                model,
+                replay::Cassette::load(&path)?,
+            )));
         }
+    };
+
+    if config.record() {
+        let cassette = replay::Cassette::load_or_create(&crate::config::Config::cassette_path()?)?;
+        return Ok(Backend::Record(Box::new(replay::RecordingBackend::new(
+            backend, cassette,
+        ))));
+    }
+    Ok(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_matching_accepts_implicit_tags_and_latest_aliases() {
+        assert!(model_matches("qwen2.5-coder:7b", "qwen2.5-coder:7b"));
+        assert!(model_matches("llama3:latest", "llama3"));
+        assert!(model_matches(
+            "claude-3-5-haiku-20241022",
+            "claude-3-5-haiku-latest"
+        ));
+
+        assert!(!model_matches("qwen2.5-coder:1.5b", "qwen2.5-coder:7b"));
+        assert!(!model_matches("llama3:8b", "llama3"));
+        assert!(!model_matches(
+            "claude-3-5-sonnet-20241022",
+            "claude-3-5-haiku-latest"
+        ));
+        assert!(!model_matches("gpt-4o-mini-audio", "gpt-4o-mini"));
     }
 }
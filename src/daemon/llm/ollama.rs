@@ -2,20 +2,71 @@
 //!
 //! Ollama is a local LLM server that provides fast inference without API costs.
 
+use super::ModelInfo;
 use anyhow::{anyhow, Context, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Errors callers are expected to handle rather than just report.
+#[derive(Debug, thiserror::Error)]
+pub enum OllamaError {
+    /// The requested model has not been pulled into this Ollama instance.
+    #[error(
+        "Model '{0}' is not installed in Ollama. Pull it with: incant models pull {0} \
+         (or set auto_pull = true under [backend])"
+    )]
+    ModelNotInstalled(String),
+}
+
+/// Progress of a model pull, as streamed by `/api/pull`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PullProgress {
+    /// Ollama's current phase, e.g. "pulling manifest" or "verifying sha256 digest".
+    pub status: String,
+    /// Bytes downloaded of the current layer, when downloading.
+    pub completed: Option<u64>,
+    /// Size of the current layer, when downloading.
+    pub total: Option<u64>,
+    /// Set once the pull ends: Ok on success, the error message otherwise.
+    pub finished: Option<Result<(), String>>,
+}
+
+impl std::fmt::Display for PullProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => write!(
+                f,
+                "{}: {}% ({}/{})",
+                self.status,
+                (completed as f64 / total as f64 * 100.0) as u32,
+                format_size(completed),
+                format_size(total)
+            ),
+            _ => write!(f, "{}", self.status),
+        }
+    }
+}
+
+/// In-flight background pulls, keyed by model name.
+type PullRegistry = Arc<Mutex<HashMap<String, watch::Receiver<PullProgress>>>>;
 
 /// Ollama backend for local LLM inference.
 pub struct OllamaBackend {
     pub model: String,
+    /// Pull missing models in the background instead of failing the query.
+    pub auto_pull: bool,
     host: String,
     client: Client,
+    pulls: PullRegistry,
 }
 
 impl OllamaBackend {
     /// Create a new Ollama backend.
-    pub fn new(model: String, host: String) -> Self {
+    pub fn new(model: String, host: String, auto_pull: bool) -> Self {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(60))
             .build()
@@ -23,8 +74,10 @@ impl OllamaBackend {
 
         Self {
             model,
+            auto_pull,
             host,
             client,
+            pulls: Arc::default(),
         }
     }
 
@@ -61,6 +114,9 @@ impl OllamaBackend {
             .await
             .context("Failed to connect to Ollama")?;
 
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(OllamaError::ModelNotInstalled(model.to_string()).into());
+        }
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
@@ -98,6 +154,172 @@ impl OllamaBackend {
             Err(anyhow!("Ollama health check failed: {}", response.status()))
         }
     }
+
+    /// List locally installed models.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let url = format!("{}/api/tags", self.host);
+        let response = self
+            .client
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await
+            .context("Failed to connect to Ollama. Is it running?")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Failed to list models: {}", response.status()));
+        }
+
+        let tags: OllamaTags = response
+            .json()
+            .await
+            .context("Failed to parse Ollama model list")?;
+        Ok(tags
+            .models
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.name,
+                size: m.size,
+                modified: m
+                    .modified_at
+                    .map(|d| d.split('T').next().unwrap_or(&d).to_string()),
+            })
+            .collect())
+    }
+
+    /// Report which of `models` are not installed.
+    pub async fn missing_models(&self, models: &[String]) -> Result<Vec<String>> {
+        let installed = self.list_models().await?;
+        Ok(models
+            .iter()
+            .filter(|wanted| {
+                !installed
+                    .iter()
+                    .any(|m| super::model_matches(&m.name, wanted))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Start pulling `model` in the background, or join a pull already in
+    /// flight. The receiver sees every progress update and, last, a
+    /// `finished` result.
+    pub fn start_pull(&self, model: &str) -> watch::Receiver<PullProgress> {
+        let mut pulls = self
+            .pulls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(receiver) = pulls.get(model) {
+            return receiver.clone();
+        }
+
+        let (sender, receiver) = watch::channel(PullProgress {
+            status: "starting pull".to_string(),
+            ..PullProgress::default()
+        });
+        pulls.insert(model.to_string(), receiver.clone());
+
+        let client = self.client.clone();
+        let host = self.host.clone();
+        let model = model.to_string();
+        let registry = Arc::clone(&self.pulls);
+        tokio::spawn(async move {
+            info!("Pulling model {}", model);
+            let result = pull(&client, &host, &model, |progress| {
+                sender.send_replace(progress.clone());
+            })
+            .await;
+            match &result {
+                Ok(()) => info!("Pulled model {}", model),
+                Err(e) => warn!("Pulling model {} failed: {:#}", model, e),
+            }
+            registry
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .remove(&model);
+            sender.send_modify(|progress| {
+                progress.finished = Some(result.map_err(|e| format!("{:#}", e)));
+            });
+        });
+        receiver
+    }
+}
+
+/// Pull `model` through `/api/pull`, calling `on_progress` for every
+/// streamed status update. Shared by `incant models pull` and the daemon's
+/// background auto-pull.
+pub async fn pull(
+    client: &Client,
+    host: &str,
+    model: &str,
+    mut on_progress: impl FnMut(&PullProgress),
+) -> Result<()> {
+    let url = format!("{}/api/pull", host);
+
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({ "name": model, "stream": true }))
+        .timeout(std::time::Duration::from_secs(3600)) // 1 hour timeout for large models
+        .send()
+        .await
+        .context("Failed to connect to Ollama. Is it running?")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Failed to pull model: {} - {}", status, body));
+    }
+
+    // Each line is a JSON object; a line may straddle two chunks.
+    use futures::StreamExt;
+    let mut stream = response.bytes_stream();
+    let mut pending = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        pending.extend_from_slice(&chunk?);
+        while let Some(newline) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=newline).collect();
+            handle_pull_line(&line, &mut on_progress)?;
+        }
+    }
+    handle_pull_line(&pending, &mut on_progress)
+}
+
+/// Decode one streamed `/api/pull` line.
+fn handle_pull_line(line: &[u8], on_progress: &mut impl FnMut(&PullProgress)) -> Result<()> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(());
+    }
+    let Ok(update) = serde_json::from_str::<PullUpdate>(line) else {
+        return Ok(());
+    };
+    if let Some(error) = update.error {
+        return Err(anyhow!("Failed to pull model: {}", error));
+    }
+    if let Some(status) = update.status {
+        on_progress(&PullProgress {
+            status,
+            completed: update.completed,
+            total: update.total,
+            finished: None,
+        });
+    }
+    Ok(())
+}
+
+/// Format bytes to human-readable size.
+pub fn format_size(bytes: u64) -> String {
+    const GB: u64 = 1024 * 1024 * 1024;
+    const MB: u64 = 1024 * 1024;
+
+    if bytes >= GB {
+        format!("{:.1}GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.0}MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{}B", bytes)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -122,6 +344,33 @@ struct OllamaResponse {
     done: bool,
 }
 
+#[derive(Debug, Deserialize)]
+struct OllamaTags {
+    #[serde(default)]
+    models: Vec<OllamaTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaTag {
+    name: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    modified_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullUpdate {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
 /// Clean up the generated command.
 fn clean_command(response: &str) -> String {
     let mut command = response.trim().to_string();
@@ -178,6 +427,26 @@ mod tests {
         assert_eq!(clean_command("```bash\nls -la\n```"), "ls -la");
     }
 
+    #[test]
+    fn pull_lines_report_progress_and_errors() {
+        let mut seen = Vec::new();
+        handle_pull_line(
+            br#"{"status":"pulling abc","completed":512,"total":1024}"#,
+            &mut |p: &PullProgress| seen.push(p.to_string()),
+        )
+        .unwrap();
+        handle_pull_line(b"\n", &mut |p: &PullProgress| seen.push(p.to_string())).unwrap();
+        assert_eq!(seen, vec!["pulling abc: 50% (512B/1024B)".to_string()]);
+
+        let error = handle_pull_line(
+            br#"{"error":"pull model manifest: file does not exist"}"#,
+            &mut |_: &PullProgress| {},
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("file does not exist"), "{error}");
+    }
+
     #[test]
     fn test_clean_command_with_preamble() {
         assert_eq!(clean_command("Here's the command: ls -la"), "ls -la");
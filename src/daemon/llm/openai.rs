@@ -1,23 +1,27 @@
 //! OpenAI backend implementation.
 //!
-//! Uses the OpenAI API for command generation with GPT models.
+//! Uses the OpenAI API for command generation with GPT models. Any
+//! OpenAI-compatible server works by pointing `base_url` at it.
 
+use super::ModelInfo;
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 
-const OPENAI_API_URL: &str = "https://api.openai.com/v1/chat/completions";
+/// The official OpenAI API root.
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
 
-/// OpenAI backend for GPT API.
+/// OpenAI backend for GPT API and compatible servers.
 pub struct OpenAIBackend {
     pub model: String,
     api_key: Option<String>,
+    base_url: String,
     client: Client,
 }
 
 impl OpenAIBackend {
     /// Create a new OpenAI backend.
-    pub fn new(model: String, api_key: Option<String>) -> Self {
+    pub fn new(model: String, api_key: Option<String>, base_url: String) -> Self {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(30))
             .build()
@@ -26,21 +30,35 @@ impl OpenAIBackend {
         Self {
             model,
             api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
             client,
         }
     }
 
     /// Get the API key from config or environment.
-    fn get_api_key(&self) -> Result<String> {
-        self.api_key
+    ///
+    /// The official API always needs one; a compatible server (vLLM,
+    /// llama.cpp, LM Studio, ...) may not, so its key is optional.
+    fn get_api_key(&self) -> Result<Option<String>> {
+        let key = self
+            .api_key
             .clone()
-            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
-            .ok_or_else(|| {
-                anyhow!(
-                    "OpenAI API key not found. Set OPENAI_API_KEY environment variable \
-                     or add api_key to config file."
-                )
-            })
+            .or_else(|| std::env::var("OPENAI_API_KEY").ok());
+        if key.is_none() && self.base_url == OPENAI_BASE_URL {
+            return Err(anyhow!(
+                "OpenAI API key not found. Set OPENAI_API_KEY environment variable \
+                 or add api_key to config file."
+            ));
+        }
+        Ok(key)
+    }
+
+    /// Attach bearer auth when a key is configured.
+    fn authorize(&self, request: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+        match api_key {
+            Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+            None => request,
+        }
     }
 
     /// Generate a command from a query and system prompt.
@@ -74,9 +92,11 @@ impl OpenAIBackend {
         };
 
         let response = self
-            .client
-            .post(OPENAI_API_URL)
-            .header("Authorization", format!("Bearer {}", api_key))
+            .authorize(
+                self.client
+                    .post(format!("{}/chat/completions", self.base_url)),
+                api_key.as_deref(),
+            )
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
@@ -116,6 +136,46 @@ impl OpenAIBackend {
         self.get_api_key()?;
         Ok(())
     }
+
+    /// List the models served at `base_url`.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let api_key = self.get_api_key()?;
+        let response = self
+            .authorize(
+                self.client.get(format!("{}/models", self.base_url)),
+                api_key.as_deref(),
+            )
+            .send()
+            .await
+            .context("Failed to connect to OpenAI API")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body: Result<OpenAIError, _> = response.json().await;
+            let message = body
+                .map(|e| e.error.message)
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow!(
+                "OpenAI model listing failed with status {}: {}",
+                status,
+                message
+            ));
+        }
+
+        let list: OpenAIModelList = response
+            .json()
+            .await
+            .context("Failed to parse OpenAI model list")?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.id,
+                size: None,
+                modified: None,
+            })
+            .collect())
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -147,6 +207,16 @@ struct OpenAIMessageResponse {
     content: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct OpenAIModelList {
+    data: Vec<OpenAIModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModel {
+    id: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct OpenAIError {
     error: OpenAIErrorDetail,
@@ -0,0 +1,268 @@
+//! Record/replay backend implementation.
+//!
+//! A cassette is a JSON-lines file of model responses keyed by the system
+//! prompt hash, the user query, and the model. Recording wraps any real
+//! backend and appends what it returns; replaying serves those responses
+//! back without touching the network, for deterministic demos and
+//! regression suites on real model output.
+
+use super::Backend;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+/// One recorded exchange.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    /// Hash of the system prompt (see [`prompt_hash`]).
+    system_hash: String,
+    model: String,
+    query: String,
+    response: String,
+}
+
+/// An in-memory cassette backed by a JSON-lines file.
+pub struct Cassette {
+    path: PathBuf,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Cassette {
+    /// Load an existing cassette. A missing file is an error: replaying
+    /// from nothing would fail every query.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette: {}", path.display()))?;
+        let entries = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("Invalid cassette entry at {}:{}", path.display(), index + 1)
+                })
+            })
+            .collect::<Result<Vec<Entry>>>()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Load a cassette for recording, starting empty when it does not exist.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self {
+                path: path.to_path_buf(),
+                entries: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    /// Distinct models with recorded responses, in first-recorded order.
+    fn models(&self) -> Vec<String> {
+        let mut models: Vec<String> = Vec::new();
+        for entry in self
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+        {
+            if !models.contains(&entry.model) {
+                models.push(entry.model.clone());
+            }
+        }
+        models
+    }
+
+    /// Find the recorded response for an exchange.
+    fn lookup(&self, system_prompt: &str, query: &str, model: &str) -> Option<String> {
+        let system_hash = prompt_hash(system_prompt);
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .find(|e| e.system_hash == system_hash && e.query == query && e.model == model)
+            .map(|e| e.response.clone())
+    }
+
+    /// Record an exchange, replacing any earlier entry with the same key,
+    /// and rewrite the file. Cassettes embed prompts, which carry the user's
+    /// context, so they are written owner-only.
+    fn record(&self, system_prompt: &str, query: &str, model: &str, response: &str) -> Result<()> {
+        let entry = Entry {
+            system_hash: prompt_hash(system_prompt),
+            model: model.to_string(),
+            query: query.to_string(),
+            response: response.to_string(),
+        };
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.retain(|e| {
+            !(e.system_hash == entry.system_hash
+                && e.query == entry.query
+                && e.model == entry.model)
+        });
+        entries.push(entry);
+
+        let mut contents = String::new();
+        for entry in entries.iter() {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        crate::transport::write_private_file(&self.path, contents.as_bytes())
+            .with_context(|| format!("Failed to write cassette: {}", self.path.display()))
+    }
+}
+
+/// Backend that answers only from a cassette.
+pub struct ReplayBackend {
+    pub model: String,
+    cassette: Cassette,
+}
+
+impl ReplayBackend {
+    /// Create a replay backend over a loaded cassette.
+    pub fn new(model: String, cassette: Cassette) -> Self {
+        Self { model, cassette }
+    }
+
+    /// Serve the recorded response for this exchange.
+    pub async fn generate(
+        &self,
+        system_prompt: &str,
+        user_query: &str,
+        model_override: Option<&str>,
+        _temperature_override: Option<f32>,
+    ) -> Result<String> {
+        let model = model_override.unwrap_or(&self.model);
+        self.cassette
+            .lookup(system_prompt, user_query, model)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No recorded response for query {:?} with model {} in {}",
+                    user_query,
+                    model,
+                    self.cassette.path.display()
+                )
+            })
+    }
+
+    /// The cassette was loaded at construction, so replay is always ready.
+    pub async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The models the cassette has responses for.
+    pub fn list_models(&self) -> Vec<String> {
+        self.cassette.models()
+    }
+}
+
+/// A real backend whose successful responses are written to a cassette.
+pub struct RecordingBackend {
+    pub inner: Backend,
+    cassette: Cassette,
+}
+
+impl RecordingBackend {
+    /// Wrap `inner` so every response it produces is recorded.
+    pub fn new(inner: Backend, cassette: Cassette) -> Self {
+        Self { inner, cassette }
+    }
+
+    /// Generate with the wrapped backend and record the result.
+    ///
+    /// A cassette write failure is logged, never surfaced: the user still
+    /// gets their command.
+    pub async fn generate(
+        &self,
+        system_prompt: &str,
+        user_query: &str,
+        model_override: Option<&str>,
+        temperature_override: Option<f32>,
+    ) -> Result<String> {
+        // Boxed: the wrapped backend's generate recurses into Backend::generate.
+        let response = Box::pin(self.inner.generate(
+            system_prompt,
+            user_query,
+            model_override,
+            temperature_override,
+        ))
+        .await?;
+        let model = model_override.unwrap_or(self.inner.model());
+        match self
+            .cassette
+            .record(system_prompt, user_query, model, &response)
+        {
+            Ok(()) => debug!("Recorded response to {}", self.cassette.path.display()),
+            Err(e) => warn!("Recording failed: {:#}", e),
+        }
+        Ok(response)
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of a system prompt, as hex.
+///
+/// The std hasher is deliberately avoided: its output may change between
+/// Rust releases, which would orphan every committed cassette.
+fn prompt_hash(prompt: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in prompt.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_hash_is_stable() {
+        // Pinned: changing the hash would invalidate existing cassettes.
+        assert_eq!(prompt_hash(""), "cbf29ce484222325");
+        assert_eq!(prompt_hash("a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn recorded_entries_replay_by_prompt_query_and_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.jsonl");
+        let cassette = Cassette::load_or_create(&path).unwrap();
+        cassette.record("sys", "list files", "m1", "ls").unwrap();
+        cassette
+            .record("sys", "list files", "m1", "ls -la")
+            .unwrap();
+        cassette.record("sys", "list files", "m2", "eza").unwrap();
+
+        let replay = Cassette::load(&path).unwrap();
+        assert_eq!(
+            replay.lookup("sys", "list files", "m1").as_deref(),
+            Some("ls -la")
+        );
+        assert_eq!(
+            replay.lookup("sys", "list files", "m2").as_deref(),
+            Some("eza")
+        );
+        assert_eq!(replay.lookup("other system", "list files", "m1"), None);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn missing_or_corrupt_cassettes_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.jsonl");
+        assert!(Cassette::load(&path).is_err());
+        std::fs::write(&path, "not json\n").unwrap();
+        let error = Cassette::load(&path).err().unwrap().to_string();
+        assert!(error.contains("cassette.jsonl:1"), "{error}");
+    }
+}
@@ -2,31 +2,37 @@
 //!
 //! Handles client connections and routes requests to the LLM backend.
 
-use crate::config::Config;
+use crate::config::{Config, PromptMode};
+use crate::daemon::llm::ollama::OllamaError;
 use crate::daemon::llm::{create_backend, Backend};
-use crate::protocol::{framing, Message, Response};
+use crate::privacy::Locality;
+use crate::protocol::{framing, Message, Request, Response};
+use crate::safety::CustomRules;
 use crate::transport::{self, Endpoint, Listener, ServerStream};
 use anyhow::{Context, Result};
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// The daemon server that listens for client connections.
 pub struct DaemonServer {
     config: Config,
     endpoint: Endpoint,
     backend: Arc<Backend>,
+    safety: Arc<CustomRules>,
 }
 
 impl DaemonServer {
     /// Create a new daemon server.
     pub fn new(config: Config) -> Result<Self> {
         let endpoint = transport::endpoint()?;
-        let backend = create_backend(&config);
+        let backend = create_backend(&config)?;
+        let safety = CustomRules::compile(&config.safety.rules)?;
 
         Ok(Self {
             config,
             endpoint,
             backend: Arc::new(backend),
+            safety: Arc::new(safety),
         })
     }
 
@@ -45,6 +51,21 @@ impl DaemonServer {
 
     /// Inner run logic that can fail during startup.
     async fn run_inner(&self) -> Result<()> {
+        // Enforce the network policy before the backend is contacted at all
+        let enforcement = crate::privacy::enforcement(&self.config)?;
+        let backend_config = self.config.backend.clone();
+        let locality = tokio::task::spawn_blocking(move || Locality::of(&backend_config))
+            .await
+            .context("Backend locality check panicked")?;
+        locality.check(&enforcement)?;
+        let locality = Arc::new(locality);
+        info!("Network policy: {}", enforcement);
+        self.config.context.validate()?;
+        info!(
+            "Context tables: {}",
+            crate::context::table_summary(&self.config.context)
+        );
+
         // Perform health check
         info!("Checking backend health...");
         self.backend.health_check().await.with_context(|| {
@@ -60,6 +81,8 @@ impl DaemonServer {
             self.backend.model()
         );
 
+        self.check_profile_models().await;
+
         let mut listener = Listener::bind(&self.endpoint)?;
 
         info!("Daemon listening on {}", self.endpoint);
@@ -75,9 +98,13 @@ impl DaemonServer {
             match listener.accept().await {
                 Ok(stream) => {
                     let backend = Arc::clone(&self.backend);
+                    let safety = Arc::clone(&self.safety);
+                    let locality = Arc::clone(&locality);
                     let config = self.config.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_client(stream, backend, config).await {
+                        if let Err(e) =
+                            handle_client(stream, backend, safety, locality, config).await
+                        {
                             error!("Error handling client: {}", e);
                         }
                     });
@@ -89,6 +116,33 @@ impl DaemonServer {
         }
     }
 
+    /// Warn about profile models Ollama does not have, pulling them in the
+    /// background when `auto_pull` is set. Never fatal: queries against the
+    /// installed models still work.
+    async fn check_profile_models(&self) {
+        let Some(ollama) = self.backend.as_ollama() else {
+            return;
+        };
+        let missing = match ollama.missing_models(&self.config.profile_models()).await {
+            Ok(missing) => missing,
+            Err(e) => {
+                warn!("Could not check installed models: {:#}", e);
+                return;
+            }
+        };
+        for model in missing {
+            if ollama.auto_pull {
+                info!(
+                    "Model {} is not installed; pulling in the background",
+                    model
+                );
+                ollama.start_pull(&model);
+            } else {
+                warn!("{}", OllamaError::ModelNotInstalled(model));
+            }
+        }
+    }
+
     /// Write the PID file.
     async fn write_pid_file(&self) -> Result<()> {
         let pid_path = Config::pid_path()?;
@@ -115,6 +169,8 @@ impl DaemonServer {
 async fn handle_client(
     mut stream: ServerStream,
     backend: Arc<Backend>,
+    safety: Arc<CustomRules>,
+    locality: Arc<Locality>,
     config: Config,
 ) -> Result<()> {
     debug!("Client connected");
@@ -126,8 +182,36 @@ async fn handle_client(
         Message::Query(request) => {
             debug!("Received query: {}", request.query);
 
-            // Build the system prompt
-            let system_prompt = config.build_system_prompt(&request.context);
+            // The policy is re-read per request so a tightened system
+            // policy applies without restarting the daemon; the backend's
+            // locality was resolved at startup.
+            if let Err(e) = crate::privacy::enforcement(&config)
+                .and_then(|enforcement| locality.check(&enforcement))
+            {
+                error!("Query refused: {:#}", e);
+                framing::write_message(&mut stream, &Response::error(format!("{:#}", e))).await?;
+                return Ok(());
+            }
+
+            // Build the system prompt; fix and edit modes have their own
+            let (mode, query) = match (&request.failed, &request.edit) {
+                (Some(failed), _) => (
+                    PromptMode::Fix,
+                    crate::config::fix_query(failed, &request.query),
+                ),
+                (None, Some(command)) => (
+                    PromptMode::Edit,
+                    crate::config::edit_query(command, &request.query),
+                ),
+                (None, None) => (PromptMode::Generate, request.query.clone()),
+            };
+            // Sized for the model that will read it.
+            let system_prompt = config.build_prompt(
+                &request.context,
+                mode,
+                request.sample.as_ref(),
+                request.model.as_deref().unwrap_or(backend.model()),
+            );
 
             // Extract model and temperature overrides from request
             let model_override = request.model.as_deref();
@@ -138,18 +222,19 @@ async fn handle_client(
             }
 
             // Generate the command
-            match backend
-                .generate(
-                    &system_prompt,
-                    &request.query,
-                    model_override,
-                    temperature_override,
-                )
-                .await
+            match generate_command(
+                &mut stream,
+                &backend,
+                &request,
+                &system_prompt,
+                &query,
+                model_override,
+            )
+            .await
             {
                 Ok(command) => {
                     debug!("Generated command: {}", command);
-                    let risk = crate::safety::assess(&command);
+                    let risk = crate::safety::assess_with(&command, &safety);
                     if !risk.is_safe() {
                         debug!("Safety findings: {:?}", risk.findings);
                     }
@@ -180,9 +265,23 @@ async fn handle_client(
                 }
             }
         }
+        Message::Assess { command } => {
+            let risk = crate::safety::assess_with(&command, &safety);
+            Response::success(command, risk)
+        }
         Message::Status => {
             // Return status information
-            Response::plain(format!("Backend: {} ({})", backend.name(), backend.model()))
+            let network = match crate::privacy::enforcement(&config) {
+                Ok(enforcement) => enforcement.to_string(),
+                Err(e) => format!("invalid policy ({:#})", e),
+            };
+            Response::plain(format!(
+                "Backend: {} ({})\nNetwork: {}\nContext: {}",
+                backend.name(),
+                backend.model(),
+                network,
+                crate::context::table_summary(&config.context)
+            ))
         }
         Message::Shutdown => {
             info!("Received shutdown request");
@@ -204,6 +303,54 @@ async fn handle_client(
     Ok(())
 }
 
+/// Generate a command, waiting out a background pull when the model is
+/// missing and `auto_pull` is on. Pull progress is streamed to clients that
+/// asked for it.
+async fn generate_command(
+    stream: &mut ServerStream,
+    backend: &Backend,
+    request: &Request,
+    system_prompt: &str,
+    query: &str,
+    model_override: Option<&str>,
+) -> Result<String> {
+    let generate = || backend.generate(system_prompt, query, model_override, request.temperature);
+
+    let error = match generate().await {
+        Ok(command) => return Ok(command),
+        Err(error) => error,
+    };
+    let (Some(OllamaError::ModelNotInstalled(model)), Some(ollama)) =
+        (error.downcast_ref::<OllamaError>(), backend.as_ollama())
+    else {
+        return Err(error);
+    };
+    if !ollama.auto_pull {
+        return Err(error);
+    }
+
+    let mut pull = ollama.start_pull(model);
+    loop {
+        let progress = pull.borrow_and_update().clone();
+        match progress.finished {
+            Some(Ok(())) => break,
+            Some(Err(e)) => anyhow::bail!("Pulling model '{}' failed: {}", model, e),
+            None if request.progress => {
+                let status = format!("Pulling {}: {}", model, progress);
+                framing::write_message(stream, &Response::progress(status)).await?;
+            }
+            None => {}
+        }
+        if pull.changed().await.is_err() {
+            // The pull task ended; its last update is still readable.
+            if pull.borrow().finished.is_none() {
+                anyhow::bail!("Pulling model '{}' was interrupted", model);
+            }
+        }
+    }
+    generate().await
+}
+
 /// System prompt for the explanation pass. Kept separate from command
 /// generation so each call does exactly one job.
 const EXPLAIN_SYSTEM_PROMPT: &str = "You explain shell commands to someone learning the terminal.\n\nRules:\n- Reply in 1-3 short plain-text lines\n- Describe what the command does and what each notable flag means\n- No markdown, no code fences, no preamble";
@@ -263,6 +410,24 @@ fn endpoint_is_absent(error: &anyhow::Error) -> bool {
     })
 }
 
+/// Ask the running daemon for its status text.
+pub async fn request_status() -> Result<String> {
+    let endpoint = transport::endpoint()?;
+    let mut stream = transport::connect(&endpoint)
+        .await
+        .context("Failed to connect to daemon")?;
+    framing::write_message(&mut stream, &Message::Status).await?;
+    let response: Response = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        framing::read_message(&mut stream),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("daemon status request timed out"))??;
+    response
+        .command
+        .ok_or_else(|| anyhow::anyhow!("Invalid status response from daemon"))
+}
+
 /// Stop the running daemon.
 pub async fn stop_daemon() -> Result<()> {
     let endpoint = transport::endpoint()?;
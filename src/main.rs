@@ -7,6 +7,9 @@ mod client;
 mod config;
 mod context;
 mod daemon;
+mod history;
+mod privacy;
+mod prompt_budget;
 mod protocol;
 mod safety;
 mod transport;
@@ -53,6 +56,19 @@ struct Cli {
     #[arg(short = 'e', long)]
     explain: bool,
 
+    /// Rewrite this command as the query describes (the shell widgets pass
+    /// the current command line)
+    #[arg(long, value_name = "COMMAND", allow_hyphen_values = true)]
+    edit: Option<String>,
+
+    /// Show the model a sample of the data piped on stdin
+    #[arg(long, conflicts_with_all = ["pipe", "file"])]
+    stdin: bool,
+
+    /// Show the model a sample of this file, as with --stdin
+    #[arg(long, value_name = "PATH")]
+    file: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -64,23 +80,79 @@ enum Commands {
         #[command(subcommand)]
         action: DaemonAction,
     },
-    /// Manage Ollama models (pull, list, remove)
+    /// Manage models (list for any backend; pull, remove, ensure for Ollama)
     Models {
         #[command(subcommand)]
         action: ModelsAction,
     },
+    /// Preview the optional context sent with queries
+    Context {
+        #[command(subcommand)]
+        action: ContextAction,
+    },
     /// Open configuration file in $EDITOR
     Config,
     /// Install shell integration
-    Install,
+    Install {
+        /// Also pass your aliases and function names to incant
+        #[arg(long)]
+        aliases: bool,
+        /// Also install the Esc Esc binding that fixes the last failed command
+        #[arg(long)]
+        fix: bool,
+        /// Also warn about risky commands you type, before they run
+        #[arg(long)]
+        guard: bool,
+    },
+    /// Suggest a corrected version of a command that failed (run by the
+    /// shell hooks from `incant install --fix`)
+    Fix {
+        /// The command line that failed
+        #[arg(long, allow_hyphen_values = true)]
+        command: String,
+        /// Its exit status
+        #[arg(long, allow_hyphen_values = true)]
+        status: Option<i32>,
+        /// File holding the command's captured stderr
+        #[arg(long, value_name = "PATH")]
+        stderr_file: Option<std::path::PathBuf>,
+        /// What you meant to do, if the error alone is not enough
+        hint: Option<String>,
+    },
     /// List available profiles
     Profiles,
+    /// Warn about a command line before the shell runs it (run by the shell
+    /// hooks from `incant install --guard`)
+    Guard {
+        /// Ask before running a destructive command; exits 1 unless the
+        /// answer is yes
+        #[arg(long)]
+        confirm: bool,
+        /// The command line about to run, after `--`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Check commands against the safety rules, including your
+    /// [[safety.rules]], without running them or starting the daemon.
+    /// Exits 0 when all are safe, 3 on caution, 4 on destructive
+    Check {
+        /// Print one JSON object per command instead of text
+        #[arg(long)]
+        json: bool,
+        /// The command to check, after `--`: one argument is taken as a
+        /// command line, several as the words of one (quoted as needed).
+        /// Without it, commands are read from stdin, one per line
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
 enum ModelsAction {
-    /// List locally available models
+    /// List the backend's models and check them against your profiles
     List,
+    /// Pull every Ollama model referenced by a profile that is not installed
+    Ensure,
     /// Pull/download a model from Ollama registry
     Pull {
         /// Model name (e.g., qwen2.5-coder:7b, llama3.2:3b)
@@ -93,6 +165,14 @@ enum ModelsAction {
     },
 }
 
+#[derive(Subcommand)]
+enum ContextAction {
+    /// Show exactly which history entries would be sent, after redaction
+    History,
+    /// Show the context JSON and system prompt a query from here would send
+    Show,
+}
+
 #[derive(Subcommand)]
 enum DaemonAction {
     /// Start the daemon in the background
@@ -109,12 +189,51 @@ enum DaemonAction {
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Client-side diagnostics, such as a skipped context provider, stay
+    // quiet unless RUST_LOG asks for them. The daemon sets up its own.
+    if !matches!(cli.command, Some(Commands::Daemon { .. })) {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_env_filter(EnvFilter::from_default_env())
+            .init();
+    }
+
     match cli.command {
         Some(Commands::Daemon { action }) => handle_daemon(action).await,
         Some(Commands::Models { action }) => handle_models(action).await,
+        Some(Commands::Context { action }) => handle_context(action),
         Some(Commands::Config) => handle_config(),
-        Some(Commands::Install) => handle_install(),
+        Some(Commands::Install {
+            aliases,
+            fix,
+            guard,
+        }) => handle_install(aliases, fix, guard),
+        Some(Commands::Fix {
+            command,
+            status,
+            stderr_file,
+            hint,
+        }) => {
+            let model_selection = ModelSelection {
+                model: cli.model,
+                profile: cli.profile,
+                fast: cli.fast,
+            };
+            handle_fix(
+                command,
+                status,
+                stderr_file,
+                hint,
+                cli.explain,
+                model_selection,
+            )
+            .await
+        }
         Some(Commands::Profiles) => handle_profiles(),
+        Some(Commands::Guard { confirm, command }) => {
+            handle_guard(command.join(" "), confirm).await
+        }
+        Some(Commands::Check { json, command }) => handle_check(command, json),
         None => {
             // Build model selection from CLI args
             let model_selection = ModelSelection {
@@ -123,9 +242,81 @@ async fn main() -> Result<()> {
                 fast: cli.fast,
             };
             // Client mode - send query to daemon
-            handle_query(cli.query, cli.pipe, cli.explain, model_selection).await
+            // An empty command line means there is nothing to edit
+            let edit = cli.edit.filter(|command| !command.trim().is_empty());
+            handle_query(
+                cli.query,
+                cli.pipe,
+                cli.explain,
+                edit,
+                cli.stdin,
+                cli.file,
+                model_selection,
+            )
+            .await
+        }
+    }
+}
+
+/// Handle context subcommands.
+fn handle_context(action: ContextAction) -> Result<()> {
+    match action {
+        ContextAction::History => preview_history(&config::Config::load()?),
+        ContextAction::Show => show_context(&config::Config::load()?),
+    }
+}
+
+/// Print the `Context` a query from this directory would send, and the
+/// system prompt the daemon would build from it.
+fn show_context(config: &config::Config) -> Result<()> {
+    let context = context::gather_context(config)?;
+    println!("Context:");
+    println!("{}", serde_json::to_string_pretty(&context)?);
+    println!("\nSystem prompt:");
+    let prompt = config.build_system_prompt(&context);
+    println!("{}", prompt);
+    let model = config.model_name();
+    let tokens = prompt_budget::estimate_tokens(&prompt);
+    match config.prompt_budget(&model) {
+        Some(budget) => println!(
+            "\n(~{} tokens of {} budgeted for {})",
+            tokens, budget, model
+        ),
+        None => println!("\n(~{} tokens; no prompt budget for {})", tokens, model),
+    }
+    Ok(())
+}
+
+/// Print the history context exactly as a query would send it.
+fn preview_history(config: &config::Config) -> Result<()> {
+    let settings = &config.context.history;
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    let history = history::collect(settings, &shell)?;
+
+    if settings.enabled {
+        println!("History context: enabled ({:?} mode)", settings.mode);
+    } else {
+        println!("History context: disabled. Nothing is sent.");
+        println!("Set enabled = true under [context.history] to send the entries below.");
+    }
+    match &history.source {
+        Some(path) => println!("Source: {}", path.display()),
+        None => println!("Source: none found for shell {:?}", shell),
+    }
+
+    let entries = match settings.mode {
+        config::HistoryMode::Commands => &history.commands,
+        config::HistoryMode::AliasesOnly => &history.aliases,
+    };
+    if entries.is_empty() {
+        println!("\n(no entries)");
+    } else {
+        println!();
+        for entry in entries {
+            println!("  {}", entry);
         }
     }
+    Ok(())
 }
 
 /// Handle daemon subcommands.
@@ -259,6 +450,17 @@ async fn daemon_status() -> Result<()> {
         println!("Default model: {}", config.model_name());
         println!("Default profile: {}", config.default_profile());
         println!("Endpoint: {}", transport::endpoint()?);
+        // The daemon's own view: its environment may differ from ours.
+        match daemon::server::request_status().await {
+            Ok(status) => {
+                for line in status.lines() {
+                    if line.starts_with("Network:") || line.starts_with("Context:") {
+                        println!("{}", line);
+                    }
+                }
+            }
+            Err(e) => println!("Warning: the daemon did not report its status: {:#}", e),
+        }
     } else {
         println!("Daemon: not running");
         println!("Start with: incant daemon start");
@@ -290,94 +492,138 @@ async fn run_daemon_foreground() -> Result<()> {
     server.run().await
 }
 
-/// Handle models subcommand (for Ollama).
+/// Handle models subcommand.
 async fn handle_models(action: ModelsAction) -> Result<()> {
     let config = config::Config::load()?;
-
-    // Get Ollama host from config
-    let host = match &config.backend {
-        config::BackendConfig::Ollama { host, .. } => host.clone(),
-        _ => {
-            // Use default Ollama host even if not the active backend
-            "http://localhost:11434".to_string()
-        }
-    };
+    // Every action talks to the backend, so the network policy applies as
+    // it does to queries in the daemon.
+    let enforcement = privacy::enforcement(&config)?;
 
     match action {
-        ModelsAction::List => list_models(&host).await,
-        ModelsAction::Pull { model } => pull_model(&host, &model).await,
-        ModelsAction::Remove { model } => remove_model(&host, &model).await,
+        ModelsAction::List => {
+            privacy::Locality::of(&config.backend).check(&enforcement)?;
+            list_models(&config).await
+        }
+        ModelsAction::Ensure => {
+            privacy::Locality::of(&config.backend).check(&enforcement)?;
+            ensure_models(&config).await
+        }
+        ModelsAction::Pull { model } => {
+            pull_model(&ollama_host(&config, &enforcement)?, &model).await
+        }
+        ModelsAction::Remove { model } => {
+            remove_model(&ollama_host(&config, &enforcement)?, &model).await
+        }
     }
 }
 
-/// List available Ollama models.
-async fn list_models(host: &str) -> Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/api/tags", host);
-
-    let response = client
-        .get(&url)
-        .timeout(std::time::Duration::from_secs(5))
-        .send()
-        .await
-        .context("Failed to connect to Ollama. Is it running?")?;
-
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Failed to list models: {}",
-            response.status()
-        ));
+/// The Ollama host for pull/remove, if the network policy allows it.
+fn ollama_host(config: &config::Config, enforcement: &privacy::Enforcement) -> Result<String> {
+    match &config.backend {
+        config::BackendConfig::Ollama { host, .. } => {
+            privacy::Locality::of(&config.backend).check(enforcement)?;
+            Ok(host.clone())
+        }
+        config::BackendConfig::Exec { .. } => Err(anyhow::anyhow!(
+            "Exec plugins manage their own models; pull and remove are not supported"
+        )),
+        // Use default Ollama host even if not the active backend
+        _ => Ok("http://localhost:11434".to_string()),
     }
+}
 
-    let data: serde_json::Value = response.json().await?;
+/// List the configured backend's models, marking which profiles use each
+/// and flagging profiles whose model the backend does not offer.
+async fn list_models(config: &config::Config) -> Result<()> {
+    let backend = daemon::llm::create_backend(config)?;
+    let models = backend.list_models().await?;
 
-    println!("Available Models");
+    println!("Available Models ({})", backend.name());
     println!("================\n");
 
-    if let Some(models) = data.get("models").and_then(|m| m.as_array()) {
-        if models.is_empty() {
-            println!("No models installed.");
+    if models.is_empty() {
+        println!("No models installed.");
+        if backend.name() == "ollama" {
             println!("\nPull a model with: incant models pull <model>");
             println!("Example: incant models pull qwen2.5-coder:7b");
-        } else {
-            for model in models {
-                let name = model
-                    .get("name")
-                    .and_then(|n| n.as_str())
-                    .unwrap_or("unknown");
-                let size = model
-                    .get("size")
-                    .and_then(|s| s.as_u64())
-                    .map(format_size)
-                    .unwrap_or_else(|| "?".to_string());
-                let modified = model
-                    .get("modified_at")
-                    .and_then(|m| m.as_str())
-                    .map(|s| s.split('T').next().unwrap_or(s))
-                    .unwrap_or("?");
-
-                println!("  {} ({}) - {}", name, size, modified);
+        }
+    }
+
+    let mut profile_names: Vec<_> = config.profiles.keys().collect();
+    profile_names.sort();
+
+    for model in &models {
+        let mut line = format!("  {}", model.name);
+        match (model.size, &model.modified) {
+            (Some(size), Some(modified)) => line.push_str(&format!(
+                " ({}) - {}",
+                daemon::llm::ollama::format_size(size),
+                modified
+            )),
+            (Some(size), None) => {
+                line.push_str(&format!(" ({})", daemon::llm::ollama::format_size(size)))
             }
+            (None, Some(modified)) => line.push_str(&format!(" - {}", modified)),
+            (None, None) => {}
+        }
+        let users: Vec<&str> = profile_names
+            .iter()
+            .filter(|name| daemon::llm::model_matches(&model.name, &config.profiles[**name].model))
+            .map(|name| name.as_str())
+            .collect();
+        if !users.is_empty() {
+            line.push_str(&format!("  [profile: {}]", users.join(", ")));
+        }
+        println!("{}", line);
+    }
+
+    let missing: Vec<_> = profile_names
+        .iter()
+        .map(|name| (name, &config.profiles[*name].model))
+        .filter(|(_, wanted)| {
+            !models
+                .iter()
+                .any(|m| daemon::llm::model_matches(&m.name, wanted))
+        })
+        .collect();
+    if !missing.is_empty() {
+        println!("\nProfiles referencing unavailable models:");
+        for (name, model) in missing {
+            println!("  {} -> {}", name, model);
+        }
+        if backend.name() == "ollama" {
+            println!("\nInstall them with: incant models ensure");
         }
-    } else {
-        println!("No models found.");
     }
 
     Ok(())
 }
 
-/// Format bytes to human-readable size.
-fn format_size(bytes: u64) -> String {
-    const GB: u64 = 1024 * 1024 * 1024;
-    const MB: u64 = 1024 * 1024;
-
-    if bytes >= GB {
-        format!("{:.1}GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.0}MB", bytes as f64 / MB as f64)
-    } else {
-        format!("{}B", bytes)
+/// Pull every model referenced by a profile that Ollama does not have yet.
+async fn ensure_models(config: &config::Config) -> Result<()> {
+    let config::BackendConfig::Ollama { host, .. } = &config.backend else {
+        return Err(anyhow::anyhow!(
+            "models ensure pulls Ollama models; the active backend is {}",
+            config.backend_type()
+        ));
+    };
+    let installed = daemon::llm::create_backend(config)?.list_models().await?;
+
+    let mut wanted: Vec<&str> = config.profiles.values().map(|p| p.model.as_str()).collect();
+    wanted.sort_unstable();
+    wanted.dedup();
+
+    for model in wanted {
+        if installed
+            .iter()
+            .any(|m| daemon::llm::model_matches(&m.name, model))
+        {
+            println!("{}: already installed", model);
+        } else {
+            pull_model(host, model).await?;
+        }
     }
+    Ok(())
 }
 
 /// Pull/download a model from Ollama.
@@ -385,79 +631,19 @@ async fn pull_model(host: &str, model: &str) -> Result<()> {
     println!("Pulling model: {}", model);
     println!("This may take a while depending on model size...\n");
 
-    let client = reqwest::Client::new();
-    let url = format!("{}/api/pull", host);
-
-    let response = client
-        .post(&url)
-        .json(&serde_json::json!({ "name": model, "stream": true }))
-        .timeout(std::time::Duration::from_secs(3600)) // 1 hour timeout for large models
-        .send()
-        .await
-        .context("Failed to connect to Ollama. Is it running?")?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!(
-            "Failed to pull model: {} - {}",
-            status,
-            body
-        ));
-    }
-
-    // Stream the response to show progress
-    use futures::StreamExt;
-    let mut stream = response.bytes_stream();
+    // Download progress rewrites one line; each new phase starts a new one.
     let mut last_status = String::new();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk?;
-        // Each line is a JSON object
-        for line in String::from_utf8_lossy(&chunk).lines() {
-            if line.is_empty() {
-                continue;
-            }
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(line) {
-                if let Some(status) = json.get("status").and_then(|s| s.as_str()) {
-                    // Only print if status changed
-                    if status != last_status {
-                        if let Some(completed) = json.get("completed").and_then(|c| c.as_u64()) {
-                            if let Some(total) = json.get("total").and_then(|t| t.as_u64()) {
-                                let pct = (completed as f64 / total as f64 * 100.0) as u32;
-                                print!(
-                                    "\r{}: {}% ({}/{})",
-                                    status,
-                                    pct,
-                                    format_size(completed),
-                                    format_size(total)
-                                );
-                                std::io::Write::flush(&mut std::io::stdout())?;
-                            }
-                        } else {
-                            println!("{}", status);
-                        }
-                        last_status = status.to_string();
-                    } else if json.get("completed").is_some() {
-                        // Update progress on same line
-                        if let Some(completed) = json.get("completed").and_then(|c| c.as_u64()) {
-                            if let Some(total) = json.get("total").and_then(|t| t.as_u64()) {
-                                let pct = (completed as f64 / total as f64 * 100.0) as u32;
-                                print!(
-                                    "\r{}: {}% ({}/{})",
-                                    status,
-                                    pct,
-                                    format_size(completed),
-                                    format_size(total)
-                                );
-                                std::io::Write::flush(&mut std::io::stdout())?;
-                            }
-                        }
-                    }
-                }
-            }
+    let mut stdout = std::io::stdout();
+    daemon::llm::ollama::pull(&reqwest::Client::new(), host, model, |progress| {
+        if progress.total.is_some() {
+            print!("\r{}", progress);
+        } else if progress.status != last_status {
+            println!("{}", progress);
         }
-    }
+        let _ = std::io::Write::flush(&mut stdout);
+        last_status.clone_from(&progress.status);
+    })
+    .await?;
 
     println!("\n\nModel '{}' pulled successfully!", model);
     Ok(())
@@ -529,57 +715,289 @@ fn handle_config() -> Result<()> {
 }
 
 /// Handle the install command for shell integration.
-fn handle_install() -> Result<()> {
+fn handle_install(aliases: bool, fix: bool, guard: bool) -> Result<()> {
     println!("Shell Integration Setup");
     println!("=======================\n");
 
     let shell = std::env::var("SHELL").unwrap_or_default();
 
+    // With --aliases the widget hands incant the live shell's aliases and
+    // function names for that one invocation; nothing is written to disk.
     if shell.contains("zsh") {
+        let invoke = if aliases {
+            r#"INCANT_ALIASES="$(alias)" INCANT_FUNCTIONS="${(k)functions}" incant"#
+        } else {
+            "incant"
+        };
         println!("Add to ~/.zshrc:\n");
         println!(
             r#"function _incant_widget() {{
     local cmd
-    cmd=$(incant </dev/tty)
+    cmd=$({invoke} --edit "$BUFFER" </dev/tty)
     if [[ -n "$cmd" ]]; then
-        LBUFFER+="$cmd"
+        BUFFER="$cmd"
+        CURSOR=${{#BUFFER}}
     fi
     zle redisplay
 }}
 zle -N _incant_widget
 bindkey '^k' _incant_widget"#
         );
+        if fix {
+            println!("\n{}", ZSH_FIX_HOOK);
+        }
+        if guard {
+            println!("\n{}", ZSH_GUARD_HOOK);
+        }
     } else if shell.contains("bash") {
+        let invoke = if aliases {
+            r#"INCANT_ALIASES="$(alias)" INCANT_FUNCTIONS="$(compgen -A function)" incant"#
+        } else {
+            "incant"
+        };
         println!("Add to ~/.bashrc:\n");
         println!(
             r#"_incant_readline() {{
     local cmd
-    cmd=$(incant </dev/tty)
-    READLINE_LINE="${{READLINE_LINE}}${{cmd}}"
-    READLINE_POINT=${{#READLINE_LINE}}
+    cmd=$({invoke} --edit "$READLINE_LINE" </dev/tty)
+    if [[ -n "$cmd" ]]; then
+        READLINE_LINE="$cmd"
+        READLINE_POINT=${{#READLINE_LINE}}
+    fi
 }}
 bind -x '"\C-k": _incant_readline'"#
         );
+        if fix {
+            println!("\n{}", BASH_FIX_HOOK);
+        }
+        if guard {
+            // One DEBUG trap per shell: with both hooks it runs both.
+            let hook = if fix {
+                BASH_GUARD_HOOK.replace(
+                    "trap '_incant_guard_debug' DEBUG",
+                    "trap '_incant_fix_debug; _incant_guard_debug' DEBUG",
+                )
+            } else {
+                BASH_GUARD_HOOK.to_string()
+            };
+            println!("\n{}", hook);
+        }
     } else if shell.contains("fish") {
+        let invoke = if aliases {
+            "INCANT_ALIASES=(alias | string collect) INCANT_FUNCTIONS=(functions --names | string collect) incant"
+        } else {
+            "incant"
+        };
         println!("Add to ~/.config/fish/config.fish:\n");
         println!(
             r#"function _incant_fish
-    set -l cmd (incant </dev/tty)
-    commandline -i $cmd
+    set -l cmd ({invoke} --edit=(commandline | string collect) </dev/tty | string collect)
+    if test -n "$cmd"
+        commandline -r -- $cmd
+    end
+    commandline -f repaint
 end
 bind \ck _incant_fish"#
         );
+        if fix {
+            println!("\n{}", FISH_FIX_HOOK);
+        }
+        if guard {
+            println!("\n{}", FISH_GUARD_HOOK);
+        }
     } else {
         println!("Unknown shell: {}", shell);
         println!("\nManual setup required. See documentation for shell integration examples.");
     }
 
+    println!("\nWith text on the command line, Ctrl+K rewrites it as you describe");
+    println!("(\"make this recursive\", \"add a dry run\") instead of writing a new command.");
+    if aliases {
+        println!("\nAliases and function names are filtered by [context.aliases] in your config.");
+    }
+    if fix {
+        println!("\nPress Esc Esc after a failed command to get a corrected one in your buffer.");
+        println!("Text already on the line is sent as a hint. The command and its stderr are");
+        println!("redacted with the [context.history] patterns before they are sent.");
+        println!("The zsh and bash hooks route stderr through tee, so some tools stop");
+        println!("coloring it; delete the `exec 2>` line to send only the command and status.");
+    }
+    if guard {
+        println!("\nCommands you run are checked against the safety rules first, and the same");
+        println!("warnings are printed above their output. Add --confirm to the `incant guard`");
+        println!("call to be asked before a destructive command runs. The check goes to the");
+        println!("daemon when it is running and happens in-process otherwise.");
+        if shell.contains("bash") {
+            println!("\nThe bash hook rebinds Enter to save the line before running it, and");
+            println!("turns on `shopt -s extdebug` for the whole shell. That also changes");
+            println!("`declare -F` output and how DEBUG trap results affect your functions.");
+        }
+    }
     println!("\n\nAfter adding the integration, restart your shell or run:");
     println!("  source ~/.zshrc  # or your shell's config file");
 
     Ok(())
 }
 
+/// zsh hook for `incant fix`: preexec records the command line and truncates
+/// the stderr capture, precmd records the exit status. The capture lives in
+/// a fresh owner-only directory from mktemp, so its name cannot be guessed
+/// and nobody else can plant a symlink there.
+const ZSH_FIX_HOOK: &str = r#"_incant_stderr=""
+if _incant_stderr_dir=$(mktemp -d "${XDG_RUNTIME_DIR:-${TMPDIR:-/tmp}}/incant-fix.XXXXXX"); then
+    _incant_stderr="$_incant_stderr_dir/stderr"
+    exec 2> >(umask 077; tee -a "$_incant_stderr" >&2)
+    function _incant_fix_cleanup() {
+        rm -rf -- "$_incant_stderr_dir"
+    }
+    zshexit_functions+=(_incant_fix_cleanup)
+fi
+_incant_last_cmd=""
+_incant_last_status=0
+function _incant_fix_preexec() {
+    _incant_last_cmd="$1"
+    [[ -z "$_incant_stderr" ]] || : >| "$_incant_stderr"
+}
+function _incant_fix_precmd() {
+    _incant_last_status=$?
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec _incant_fix_preexec
+precmd_functions=(_incant_fix_precmd $precmd_functions)
+function _incant_fix_widget() {
+    local cmd
+    cmd=$(incant fix --command "$_incant_last_cmd" --status "$_incant_last_status" \
+        --stderr-file "$_incant_stderr" -- "$BUFFER" </dev/tty)
+    if [[ -n "$cmd" ]]; then
+        BUFFER="$cmd"
+        CURSOR=${#BUFFER}
+    fi
+    zle redisplay
+}
+zle -N _incant_fix_widget
+bindkey '\e\e' _incant_fix_widget"#;
+
+/// bash hook for `incant fix`. bash has no preexec, so a DEBUG trap armed
+/// by PROMPT_COMMAND truncates the capture once per command line. bash
+/// draws its prompt on stderr; the NUL written before it marks where the
+/// command's output ends. The capture lives in a private mktemp directory,
+/// as in zsh; an EXIT trap of your own is left alone, at the cost of the
+/// directory outliving the shell.
+const BASH_FIX_HOOK: &str = r#"_incant_stderr=""
+if _incant_stderr_dir=$(mktemp -d "${XDG_RUNTIME_DIR:-${TMPDIR:-/tmp}}/incant-fix.XXXXXX"); then
+    _incant_stderr="$_incant_stderr_dir/stderr"
+    exec 2> >(umask 077; tee -a "$_incant_stderr" >&2)
+    [[ -n $(trap -p EXIT) ]] || trap 'rm -rf -- "$_incant_stderr_dir"' EXIT
+fi
+_incant_last_status=0
+_incant_fix_armed=1
+_incant_fix_debug() {
+    [[ $_incant_fix_armed == 1 && $BASH_COMMAND != _incant_* ]] || return 0
+    _incant_fix_armed=0
+    [[ -z $_incant_stderr ]] || : > "$_incant_stderr"
+}
+_incant_fix_status() {
+    _incant_last_status=$?
+}
+_incant_fix_arm() {
+    printf '\0' >&2
+    _incant_fix_armed=1
+}
+trap '_incant_fix_debug' DEBUG
+PROMPT_COMMAND="_incant_fix_status${PROMPT_COMMAND:+;$PROMPT_COMMAND};_incant_fix_arm"
+_incant_fix_readline() {
+    local cmd
+    cmd=$(incant fix --command "$(fc -ln -1)" --status "$_incant_last_status" \
+        --stderr-file "$_incant_stderr" -- "$READLINE_LINE" </dev/tty)
+    if [[ -n "$cmd" ]]; then
+        READLINE_LINE="$cmd"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+bind -x '"\e\e": _incant_fix_readline'"#;
+
+/// fish hook for `incant fix`. fish_postexec gives the command line and
+/// status; fish has no way to tee its own stderr, so none is sent.
+const FISH_FIX_HOOK: &str = r#"set -g _incant_last_cmd ""
+set -g _incant_last_status 0
+function _incant_fix_postexec --on-event fish_postexec
+    set -g _incant_last_status $status
+    set -g _incant_last_cmd $argv[1]
+end
+function _incant_fix
+    set -l cmd (incant fix --command "$_incant_last_cmd" --status "$_incant_last_status" \
+        -- (commandline) </dev/tty | string collect)
+    if test -n "$cmd"
+        commandline -r -- $cmd
+    end
+    commandline -f repaint
+end
+bind \e\e _incant_fix"#;
+
+/// zsh hook for `incant guard`: wraps accept-line, so a declined command
+/// stays in the buffer for editing.
+const ZSH_GUARD_HOOK: &str = r#"function _incant_guard_accept_line() {
+    if [[ -n "${BUFFER//[[:space:]]/}" ]]; then
+        zle -I
+        incant guard -- "$BUFFER" </dev/tty || return 0
+    fi
+    zle .accept-line
+}
+zle -N accept-line _incant_guard_accept_line"#;
+
+/// bash hook for `incant guard`. Enter first saves the readline buffer,
+/// so the check sees the typed line even when history skips it
+/// (`HISTCONTROL=ignorespace`, `set +o history`). The first DEBUG trap
+/// after the prompt checks that line; extdebug skips a command whose trap
+/// fails, and every later command of a declined line fails too, so
+/// `cd / && rm -rf *` cannot run its second half. The prompt resets it
+/// without touching `$?`.
+const BASH_GUARD_HOOK: &str = r#"_incant_guard_state=idle
+_incant_guard_line=
+_incant_guard_capture() {
+    _incant_guard_line+=$READLINE_LINE$'\n'
+}
+_incant_guard_debug() {
+    [[ $BASH_COMMAND != _incant_* ]] || return 0
+    [[ $_incant_guard_state != declined ]] || return 1
+    [[ $_incant_guard_state == armed ]] || return 0
+    _incant_guard_state=checked
+    local line=${_incant_guard_line%$'\n'}
+    incant guard -- "${line:-$BASH_COMMAND}" </dev/tty && return 0
+    _incant_guard_state=declined
+    return 1
+}
+_incant_guard_idle() {
+    local status=$?
+    _incant_guard_state=idle
+    return $status
+}
+_incant_guard_arm() {
+    _incant_guard_state=armed
+    _incant_guard_line=
+}
+for _incant_keymap in emacs vi-insert vi-command; do
+    bind -m $_incant_keymap -x '"\C-x\C-g": _incant_guard_capture'
+    bind -m $_incant_keymap '"\C-x\C-j": accept-line'
+    bind -m $_incant_keymap '"\C-m": "\C-x\C-g\C-x\C-j"'
+done
+unset _incant_keymap
+shopt -s extdebug
+trap '_incant_guard_debug' DEBUG
+PROMPT_COMMAND="_incant_guard_idle${PROMPT_COMMAND:+;$PROMPT_COMMAND};_incant_guard_arm""#;
+
+/// fish hook for `incant guard`. fish_preexec cannot stop a command, so
+/// Enter is bound instead; a declined line stays on the command line.
+const FISH_GUARD_HOOK: &str = r#"function _incant_guard_execute
+    set -l line (commandline | string collect)
+    if string match -qr '\S' -- $line; and not incant guard -- $line </dev/tty
+        commandline -f repaint
+        return
+    end
+    commandline -f execute
+end
+bind \r _incant_guard_execute"#;
+
 /// Handle the profiles subcommand.
 fn handle_profiles() -> Result<()> {
     let config = config::Config::load()?;
@@ -607,6 +1025,8 @@ fn handle_profiles() -> Result<()> {
         }
     }
 
+    print_context_tables(&config.context);
+
     println!("Usage:");
     println!("  incant --fast \"query\"           # Use 'fast' profile");
     println!("  incant --profile heavy \"query\"  # Use 'heavy' profile");
@@ -615,37 +1035,50 @@ fn handle_profiles() -> Result<()> {
     Ok(())
 }
 
+/// List the effective tool and marker tables, flagging invalid entries.
+fn print_context_tables(context: &config::ContextConfig) {
+    println!("Context Tables");
+    println!("==============\n");
+    println!("  tools ({}):", context.tools_mode);
+    for tool in context::tool_table(context) {
+        match context.tool_description(&tool) {
+            Some(description) => println!("    {} - {}", tool, description),
+            None => println!("    {}", tool),
+        }
+    }
+    println!("  markers ({}):", context.markers_mode);
+    for (file, project) in context::marker_table(context) {
+        println!("    {} -> {}", file, project);
+    }
+    if let Err(e) = context.validate() {
+        println!("\n  {:#}", e);
+        println!("  (invalid entries are ignored; the daemon refuses to start)");
+    }
+    println!();
+}
+
 /// Handle query mode (TUI or pipe).
 async fn handle_query(
     query: Option<String>,
     pipe_mode: bool,
     explain: bool,
+    edit: Option<String>,
+    stdin: bool,
+    file: Option<std::path::PathBuf>,
     model_selection: ModelSelection,
 ) -> Result<()> {
     // Load config to resolve model selection
     let config = config::Config::load()?;
+    // Read the input before anything else touches stdin. The TUI reads the
+    // terminal directly, so a piped sample and the popup coexist.
+    let sample = client::sample::gather(&config, stdin, file.as_deref())?;
     let resolved_model = model_selection.resolve_model(&config);
     let resolved_temperature = model_selection.resolve_temperature(&config);
 
-    // Ensure daemon is running, try to auto-start if not
-    if !daemon::server::probe_daemon_status().await? {
-        if pipe_mode {
-            eprintln!("Daemon not running. Start with: incant daemon start");
-            std::process::exit(1);
-        }
-
-        // Try to auto-start. start_daemon() only returns once the daemon
-        // has reported readiness, so no settling delay is needed.
-        eprintln!("Starting daemon...");
-        start_daemon().await?;
-
-        if !daemon::server::probe_daemon_status().await? {
-            eprintln!("Failed to start daemon. Check your configuration.");
-            std::process::exit(1);
-        }
-    }
+    ensure_daemon(pipe_mode).await?;
 
     // Get the query
+    let interactive = query.is_none() && !pipe_mode;
     let final_query = match query {
         // Direct mode: an explicit query means "translate this now" — never
         // gate the answer behind an interactive popup.
@@ -653,7 +1086,7 @@ async fn handle_query(
         None if pipe_mode => {
             return Err(anyhow::anyhow!("Query required in --pipe mode"));
         }
-        None => match client::run_tui()? {
+        None => match client::run_tui(edit.as_deref())? {
             client::tui::TuiResult::Query(q) => q,
             client::tui::TuiResult::Cancelled => {
                 // SIGINT convention (like fzf): lets widgets and scripts
@@ -664,19 +1097,32 @@ async fn handle_query(
     };
 
     // Gather context
-    let ctx = context::gather_context()?;
+    let ctx = context::gather_context(&config)?;
 
     // Send query to daemon with model override
-    match client::send_query(
-        final_query,
-        ctx,
-        Some(resolved_model),
-        Some(resolved_temperature),
+    match client::send_query(protocol::Request {
+        query: final_query,
+        context: ctx,
+        model: Some(resolved_model),
+        temperature: Some(resolved_temperature),
         explain,
-    )
+        progress: true,
+        failed: None,
+        edit: edit.clone(),
+        sample,
+    })
     .await
     {
         Ok(generated) => {
+            // An interactive edit shows the rewrite next to the original
+            // before it replaces the command line.
+            if let Some(original) = edit.as_deref().filter(|_| interactive) {
+                if original.trim() != generated.command.trim()
+                    && !client::tui::review_edit(original, &generated.command)?
+                {
+                    std::process::exit(130);
+                }
+            }
             // Advisory safety warnings go to stderr so stdout stays a
             // clean command for shell-buffer injection.
             if config.preferences.safety_warnings {
@@ -707,6 +1153,182 @@ async fn handle_query(
     Ok(())
 }
 
+/// Handle `incant fix`: send the failed command, its status, and its
+/// captured stderr, and print the corrected command.
+async fn handle_fix(
+    command: String,
+    status: Option<i32>,
+    stderr_file: Option<std::path::PathBuf>,
+    hint: Option<String>,
+    explain: bool,
+    model_selection: ModelSelection,
+) -> Result<()> {
+    let config = config::Config::load()?;
+    let failed = client::fix::failed_command(&config, &command, status, stderr_file.as_deref())?;
+    ensure_daemon(false).await?;
+
+    let ctx = context::gather_context(&config)?;
+    let generated = match client::send_query(protocol::Request {
+        query: hint.unwrap_or_default(),
+        context: ctx,
+        model: Some(model_selection.resolve_model(&config)),
+        temperature: Some(model_selection.resolve_temperature(&config)),
+        explain,
+        progress: true,
+        failed: Some(failed),
+        edit: None,
+        sample: None,
+    })
+    .await
+    {
+        Ok(generated) => generated,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if config.preferences.safety_warnings {
+        if let Some(risk) = &generated.risk {
+            print_risk_warnings(risk);
+        }
+    }
+    if let Some(explanation) = &generated.explanation {
+        eprintln!("{}", explanation.trim());
+    }
+    // The hook puts this in the edit buffer for review; nothing is copied.
+    println!("{}", generated.command);
+    Ok(())
+}
+
+/// Make sure the daemon is up, auto-starting it unless `pipe_mode` asks
+/// for no side effects.
+async fn ensure_daemon(pipe_mode: bool) -> Result<()> {
+    if daemon::server::probe_daemon_status().await? {
+        return Ok(());
+    }
+    if pipe_mode {
+        eprintln!("Daemon not running. Start with: incant daemon start");
+        std::process::exit(1);
+    }
+
+    // Try to auto-start. start_daemon() only returns once the daemon
+    // has reported readiness, so no settling delay is needed.
+    eprintln!("Starting daemon...");
+    start_daemon().await?;
+
+    if !daemon::server::probe_daemon_status().await? {
+        eprintln!("Failed to start daemon. Check your configuration.");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print warnings for a command line the user is about to run, asking
+/// first when `confirm` is set and it is destructive. Exits 1 only when
+/// the user declines: an unreachable daemon or a broken config never keeps
+/// a command from running.
+async fn handle_guard(command: String, confirm: bool) -> Result<()> {
+    use std::io::Write;
+
+    if command.trim().is_empty() {
+        return Ok(());
+    }
+    let risk = match client::socket::assess(&command).await {
+        Ok(risk) => risk,
+        Err(e) => {
+            tracing::debug!("Assessing in-process: {:#}", e);
+            let rules = config::Config::load()
+                .and_then(|config| safety::CustomRules::compile(&config.safety.rules));
+            match rules {
+                Ok(rules) => safety::assess_with(&command, &rules),
+                Err(e) => {
+                    eprintln!("incant guard: {:#}", e);
+                    return Ok(());
+                }
+            }
+        }
+    };
+    print_risk_warnings(&risk);
+
+    if confirm && risk.level == safety::RiskLevel::Destructive {
+        eprint!("Run it anyway? [y/N] ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Assess `command`, or each line of stdin when it is empty, with the
+/// built-in and configured rules, and exit with the worst level.
+fn handle_check(command: Vec<String>, json: bool) -> Result<()> {
+    use std::io::{BufRead, IsTerminal};
+
+    #[derive(serde::Serialize)]
+    struct Report<'a> {
+        command: &'a str,
+        #[serde(flatten)]
+        risk: &'a safety::Assessment,
+    }
+
+    let config = config::Config::load()?;
+    let rules = safety::CustomRules::compile(&config.safety.rules)?;
+    let commands = if command.is_empty() {
+        if std::io::stdin().is_terminal() {
+            anyhow::bail!("Pass a command after `--`, or pipe commands on stdin, one per line");
+        }
+        std::io::stdin()
+            .lock()
+            .lines()
+            .collect::<std::io::Result<Vec<_>>>()
+            .context("Failed to read commands from stdin")?
+    } else if let [line] = command.as_slice() {
+        vec![line.clone()]
+    } else {
+        // The shell already removed the quotes; put them back so
+        // `incant check -- echo 'rm -rf /'` stays an echo.
+        let words: Vec<_> = command.iter().map(|word| shell_quote(word)).collect();
+        vec![words.join(" ")]
+    };
+
+    let mut worst = safety::RiskLevel::Safe;
+    for command in commands.iter().filter(|line| !line.trim().is_empty()) {
+        let risk = safety::assess_with(command, &rules);
+        worst = worst.max(risk.level);
+        if json {
+            let report = Report {
+                command,
+                risk: &risk,
+            };
+            println!("{}", serde_json::to_string(&report)?);
+        } else if !risk.is_safe() {
+            println!("{}", command);
+            for finding in &risk.findings {
+                println!("  {} [{}]: {}", finding.level, finding.rule, finding.reason);
+            }
+        }
+    }
+    std::process::exit(match worst {
+        safety::RiskLevel::Safe => 0,
+        safety::RiskLevel::Caution => 3,
+        safety::RiskLevel::Destructive => 4,
+    });
+}
+
+/// `word` as a POSIX shell word: unchanged when it has no special
+/// characters, single-quoted otherwise.
+fn shell_quote(word: &str) -> std::borrow::Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.into();
+    }
+    format!("'{}'", word.replace('\'', r"'\''")).into()
+}
+
 /// Print advisory safety findings to stderr, colored when stderr is a
 /// terminal and NO_COLOR is unset.
 fn print_risk_warnings(risk: &safety::Assessment) {
@@ -0,0 +1,335 @@
+//! Network policy enforcement.
+//!
+//! By default incant talks to whichever backend the user configured. Under
+//! `network = "local-only"` the daemon refuses any backend that could send
+//! queries or context off the machine: only loopback hosts, Unix sockets, and
+//! the offline replay backend are allowed.
+//!
+//! The policy can be set in three places, and the strictest one wins, so a
+//! per-user config can never loosen a policy set by an administrator:
+//! - `[privacy] network` in the user's config file
+//! - the `INCANT_NETWORK` environment variable
+//! - `[privacy] network` in the system policy file (see [`system_policy_path`])
+
+use crate::config::{BackendConfig, Config};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+/// Environment variable that forces a network policy.
+pub const NETWORK_ENV: &str = "INCANT_NETWORK";
+
+/// Which backends the daemon may talk to.
+///
+/// Ordering matters: `Any < LocalOnly`. The effective policy is the maximum
+/// across sources.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkPolicy {
+    /// Any configured backend, local or cloud.
+    #[default]
+    Any,
+    /// Only backends that stay on this machine.
+    LocalOnly,
+}
+
+impl fmt::Display for NetworkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkPolicy::Any => write!(f, "any"),
+            NetworkPolicy::LocalOnly => write!(f, "local-only"),
+        }
+    }
+}
+
+impl std::str::FromStr for NetworkPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "any" => Ok(NetworkPolicy::Any),
+            "local-only" => Ok(NetworkPolicy::LocalOnly),
+            other => Err(anyhow!(
+                "Unknown network policy {:?} (expected \"any\" or \"local-only\")",
+                other
+            )),
+        }
+    }
+}
+
+/// `[privacy]` config section.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    /// Network policy (default: "any").
+    #[serde(default)]
+    pub network: NetworkPolicy,
+}
+
+/// The effective policy and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enforcement {
+    pub policy: NetworkPolicy,
+    /// Human-readable source, e.g. "INCANT_NETWORK". None for the default.
+    pub source: Option<String>,
+}
+
+impl fmt::Display for Enforcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{} (set by {})", self.policy, source),
+            None => write!(f, "{}", self.policy),
+        }
+    }
+}
+
+/// Path of the administrator's policy file.
+///
+/// `/etc/incant/policy.toml` on Unix, `%ProgramData%\incant\policy.toml` on
+/// Windows. It uses the same `[privacy]` section as the user config.
+pub fn system_policy_path() -> Option<PathBuf> {
+    #[cfg(unix)]
+    {
+        Some(PathBuf::from("/etc/incant/policy.toml"))
+    }
+    #[cfg(windows)]
+    {
+        std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("incant\\policy.toml"))
+    }
+}
+
+/// Resolve the effective policy for `config` from every source.
+///
+/// Malformed values fail closed: an unreadable policy is an error, never a
+/// silent fallback to "any".
+pub fn enforcement(config: &Config) -> Result<Enforcement> {
+    let env = std::env::var(NETWORK_ENV).ok();
+    let system_path = system_policy_path();
+    resolve(
+        config.privacy.network,
+        env.as_deref(),
+        system_path.as_deref(),
+    )
+}
+
+/// Pure core of [`enforcement`], with each source passed in.
+fn resolve(user: NetworkPolicy, env: Option<&str>, system: Option<&Path>) -> Result<Enforcement> {
+    let mut effective = Enforcement {
+        policy: user,
+        source: (user != NetworkPolicy::Any).then(|| "config file".to_string()),
+    };
+
+    if let Some(value) = env.filter(|v| !v.trim().is_empty()) {
+        let policy: NetworkPolicy = value
+            .parse()
+            .with_context(|| format!("Invalid {}", NETWORK_ENV))?;
+        if policy > effective.policy {
+            effective = Enforcement {
+                policy,
+                source: Some(NETWORK_ENV.to_string()),
+            };
+        }
+    }
+
+    if let Some(path) = system.filter(|p| p.exists()) {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read system policy: {}", path.display()))?;
+        let file: SystemPolicy = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse system policy: {}", path.display()))?;
+        if file.privacy.network > effective.policy {
+            effective = Enforcement {
+                policy: file.privacy.network,
+                source: Some(path.display().to_string()),
+            };
+        }
+    }
+
+    Ok(effective)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SystemPolicy {
+    #[serde(default)]
+    privacy: PrivacyConfig,
+}
+
+/// Whether a backend keeps queries on this machine. Judging it can
+/// resolve a host name, which blocks, so the daemon works it out once at
+/// startup and checks every query against the stored answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Locality {
+    Local,
+    /// Why queries could leave the machine.
+    Remote(String),
+}
+
+impl Locality {
+    /// Where `backend` sends queries. Blocks while host names resolve.
+    pub fn of(backend: &BackendConfig) -> Self {
+        let remote = Locality::Remote;
+        match backend {
+            BackendConfig::Ollama { host, .. } => match is_local_url(host) {
+                Ok(true) => Locality::Local,
+                Ok(false) => remote(format!("Ollama host {} is not on this machine", host)),
+                Err(e) => remote(format!("cannot verify Ollama host {}: {:#}", host, e)),
+            },
+            BackendConfig::OpenAI { base_url, .. } => match is_local_url(base_url) {
+                Ok(true) => Locality::Local,
+                Ok(false) => remote(format!(
+                    "OpenAI base_url {} is not on this machine",
+                    base_url
+                )),
+                Err(e) => remote(format!(
+                    "cannot verify OpenAI base_url {}: {:#}",
+                    base_url, e
+                )),
+            },
+            BackendConfig::Anthropic { .. } => {
+                remote("the anthropic backend is a cloud API".to_string())
+            }
+            // A plugin is an arbitrary program; incant cannot see where it
+            // sends queries, so it cannot vouch for it.
+            BackendConfig::Exec { command, .. } => remote(format!(
+                "exec plugin '{}' cannot be verified to stay local",
+                command
+            )),
+            BackendConfig::Replay { .. } => Locality::Local,
+        }
+    }
+
+    /// Check that a backend with this locality is allowed under
+    /// `enforcement`.
+    pub fn check(&self, enforcement: &Enforcement) -> Result<()> {
+        match self {
+            Locality::Remote(reason) if enforcement.policy != NetworkPolicy::Any => Err(anyhow!(
+                "Network policy is {}: {}. Use a local backend such as Ollama on localhost.",
+                enforcement,
+                reason
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether `url` points at this machine: a Unix socket, or a host whose
+/// every resolved address is loopback.
+fn is_local_url(url: &str) -> Result<bool> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+    if parsed.scheme() == "unix" {
+        return Ok(true);
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow!("URL has no host: {}", url))?;
+    // Bracketed IPv6 literals come back with their brackets.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(ip.is_loopback());
+    }
+
+    // A name is only local if everything it resolves to is. This catches
+    // "localhost" remapped in /etc/hosts as well as ordinary remote names.
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .collect();
+    Ok(!addresses.is_empty() && addresses.iter().all(|a| a.ip().is_loopback()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_only(source: &str) -> Enforcement {
+        Enforcement {
+            policy: NetworkPolicy::LocalOnly,
+            source: Some(source.to_string()),
+        }
+    }
+
+    fn check_backend(backend: &BackendConfig, enforcement: &Enforcement) -> Result<()> {
+        Locality::of(backend).check(enforcement)
+    }
+
+    #[test]
+    fn strictest_source_wins() {
+        let any = resolve(NetworkPolicy::Any, None, None).unwrap();
+        assert_eq!(any.policy, NetworkPolicy::Any);
+        assert_eq!(any.to_string(), "any");
+
+        let env = resolve(NetworkPolicy::Any, Some("local-only"), None).unwrap();
+        assert_eq!(env, local_only(NETWORK_ENV));
+
+        // The environment cannot loosen the config file.
+        let config = resolve(NetworkPolicy::LocalOnly, Some("any"), None).unwrap();
+        assert_eq!(config, local_only("config file"));
+
+        assert!(resolve(NetworkPolicy::Any, Some("offline"), None).is_err());
+    }
+
+    #[test]
+    fn system_policy_file_applies_and_fails_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, "[privacy]\nnetwork = \"local-only\"\n").unwrap();
+        let system = resolve(NetworkPolicy::Any, Some("any"), Some(&path)).unwrap();
+        assert_eq!(system, local_only(&path.display().to_string()));
+
+        std::fs::write(&path, "[privacy]\nnetwork = \"sometimes\"\n").unwrap();
+        assert!(resolve(NetworkPolicy::Any, None, Some(&path)).is_err());
+
+        let missing = dir.path().join("absent.toml");
+        assert!(resolve(NetworkPolicy::Any, None, Some(&missing)).is_ok());
+    }
+
+    #[test]
+    fn local_only_allows_loopback_backends_only() {
+        let policy = local_only(NETWORK_ENV);
+        let ollama = |host: &str| BackendConfig::Ollama {
+            host: host.to_string(),
+            default_profile: "default".to_string(),
+            auto_pull: false,
+            record: false,
+        };
+
+        for host in [
+            "http://localhost:11434",
+            "http://127.0.0.1:11434",
+            "http://127.8.9.10",
+            "http://[::1]:11434",
+            "unix:///run/ollama.sock",
+        ] {
+            assert!(check_backend(&ollama(host), &policy).is_ok(), "{host}");
+        }
+        for host in ["http://10.0.0.5:11434", "http://[2001:db8::1]", "not a url"] {
+            assert!(check_backend(&ollama(host), &policy).is_err(), "{host}");
+        }
+
+        let anthropic = BackendConfig::Anthropic {
+            default_profile: "default".to_string(),
+            api_key: None,
+            record: false,
+        };
+        let error = check_backend(&anthropic, &policy).unwrap_err().to_string();
+        assert!(
+            error.contains("local-only (set by INCANT_NETWORK)"),
+            "{error}"
+        );
+        assert!(check_backend(
+            &anthropic,
+            &Enforcement {
+                policy: NetworkPolicy::Any,
+                source: None,
+            }
+        )
+        .is_ok());
+
+        let replay = BackendConfig::Replay {
+            cassette: None,
+            default_profile: "default".to_string(),
+        };
+        assert!(check_backend(&replay, &policy).is_ok());
+    }
+}
@@ -0,0 +1,185 @@
+//! Fitting the system prompt into a model's context window.
+//!
+//! A 1.5b model served with a small `num_ctx` silently drops the start of
+//! an oversized prompt, which is where the rules are. The prompt is instead
+//! assembled from sections, each with a [`Priority`] and optionally a
+//! shorter variant. While the estimated size is over budget, sections are
+//! first shortened from the lowest priority up, and only if every short
+//! form together is not enough are they dropped, again lowest first.
+//! Required sections (the rules, OS, shell, cwd) are never touched, so a
+//! tiny budget degrades to the bare prompt rather than to nothing.
+
+use tracing::debug;
+
+/// How much a section is worth keeping. Lower values go first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Required,
+}
+
+/// One piece of the prompt, kept in place in the output.
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// Shown in the trim report.
+    pub name: &'static str,
+    pub priority: Priority,
+    pub text: String,
+    /// A cheaper version to try before dropping the section.
+    pub short: Option<String>,
+}
+
+impl Section {
+    pub fn new(name: &'static str, priority: Priority, text: String) -> Self {
+        Self {
+            name,
+            priority,
+            text,
+            short: None,
+        }
+    }
+
+    /// Offer `short` as the shortened form, unless it saves no estimated
+    /// tokens. A cut list's "and N more" line can cost more than it saves.
+    pub fn or_shorter(mut self, short: String) -> Self {
+        if estimate_tokens(&short) < estimate_tokens(&self.text) {
+            self.short = Some(short);
+        }
+        self
+    }
+}
+
+/// Rough token count: one per punctuation character and one per four
+/// characters of each alphanumeric run. Real tokenizers merge common words
+/// and split rare ones; this leans high on paths and flags, which is the
+/// safe side for a budget.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            run += 1;
+            continue;
+        }
+        tokens += run.div_ceil(4);
+        run = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + run.div_ceil(4)
+}
+
+/// Join `sections` in order, trimming low-priority ones until the result
+/// is at most `budget` tokens (when there is a budget). What was trimmed is
+/// logged at debug level.
+pub fn fit(mut sections: Vec<Section>, budget: Option<usize>) -> String {
+    let Some(budget) = budget else {
+        return join(&sections);
+    };
+    let mut total: usize = sections.iter().map(|s| estimate_tokens(&s.text)).sum();
+    let mut trimmed = Vec::new();
+    // Stable sort by priority: among equals, later sections go first.
+    let mut order: Vec<usize> = (0..sections.len()).collect();
+    order.sort_by_key(|&i| (sections[i].priority, std::cmp::Reverse(i)));
+
+    order.retain(|&i| sections[i].priority != Priority::Required);
+
+    // A short form is kept even when it does not close the gap alone: the
+    // next section may close the rest, and then nothing is dropped.
+    for &index in &order {
+        if total <= budget {
+            break;
+        }
+        let section = &mut sections[index];
+        if let Some(short) = section.short.take() {
+            let saved = estimate_tokens(&section.text).saturating_sub(estimate_tokens(&short));
+            total = total.saturating_sub(saved);
+            section.text = short;
+            trimmed.push(format!("shortened {}", section.name));
+        }
+    }
+    for &index in &order {
+        if total <= budget {
+            break;
+        }
+        let section = &mut sections[index];
+        if section.text.is_empty() {
+            continue;
+        }
+        total = total.saturating_sub(estimate_tokens(&section.text));
+        section.text.clear();
+        trimmed.push(format!("dropped {}", section.name));
+    }
+    if !trimmed.is_empty() {
+        debug!(
+            "System prompt trimmed to ~{} of {} tokens: {}",
+            total,
+            budget,
+            trimmed.join(", ")
+        );
+    }
+    join(&sections)
+}
+
+fn join(sections: &[Section]) -> String {
+    sections.iter().map(|s| s.text.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_words_and_symbols() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("git status"), 3);
+        assert_eq!(estimate_tokens("--recursive"), 5);
+        assert_eq!(estimate_tokens("/usr/local/bin"), 7);
+    }
+
+    #[test]
+    fn trims_lowest_priority_first_and_keeps_order() {
+        let sections = || {
+            vec![
+                Section::new("rules", Priority::Required, "rules rules ".repeat(10)),
+                Section::new("git", Priority::High, "Git: main ".to_string()),
+                Section::new("history", Priority::Low, "old ".repeat(40))
+                    .or_shorter("old ".repeat(5)),
+                Section::new("listing", Priority::Low, "file ".repeat(20)),
+            ]
+        };
+        let full = fit(sections(), None);
+        let size = estimate_tokens(&full);
+        assert_eq!(fit(sections(), Some(size)), full);
+
+        // Shortening comes before dropping, even of a lower section.
+        let prompt = fit(sections(), Some(size - 10));
+        assert!(prompt.ends_with(&format!("{}{}", "old ".repeat(5), "file ".repeat(20))));
+
+        // The short history is not enough; the later of the two Low
+        // sections is dropped first, and the short history stays.
+        let prompt = fit(sections(), Some(size - 40));
+        assert!(prompt.ends_with(&format!("Git: main {}", "old ".repeat(5))));
+
+        // Two short forms together close a gap neither closes alone.
+        let two_short = || {
+            vec![
+                Section::new("rules", Priority::Required, "rules ".to_string()),
+                Section::new("aliases", Priority::Medium, "alias ".repeat(20))
+                    .or_shorter("alias ".repeat(10)),
+                Section::new("history", Priority::Low, "old ".repeat(20))
+                    .or_shorter("old ".repeat(10)),
+            ]
+        };
+        let size = estimate_tokens(&fit(two_short(), None));
+        let prompt = fit(two_short(), Some(size - 15));
+        assert!(prompt.contains(&"alias ".repeat(10)));
+        assert!(prompt.ends_with(&"old ".repeat(10)));
+
+        // Required sections survive any budget.
+        assert_eq!(fit(sections(), Some(0)), "rules rules ".repeat(10));
+    }
+}
@@ -3,6 +3,8 @@
 //! The protocol uses length-prefixed JSON over the platform-native IPC stream.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::PathBuf;
 
 /// Request sent from client to daemon.
@@ -21,6 +23,136 @@ pub struct Request {
     /// Request a short explanation of the generated command.
     #[serde(default)]
     pub explain: bool,
+    /// The client can render interim progress frames (e.g. a model pull)
+    /// before the final response.
+    #[serde(default)]
+    pub progress: bool,
+    /// Fix mode (`incant fix`): the command to correct. `query` is then an
+    /// optional hint about what the command was meant to do.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<FailedCommand>,
+    /// Edit mode: the command line already typed, which `query` says how
+    /// to rewrite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit: Option<String>,
+    /// A sample of the data piped to incant or named with `--file`, which
+    /// the command is meant to process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<DataSample>,
+}
+
+/// A command that just failed, as captured by the shell hook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedCommand {
+    /// The command line as typed, redacted.
+    pub command: String,
+    /// Its exit status, when the hook captured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The tail of its stderr, redacted. Empty when not captured.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+}
+
+/// The head of the input data and the structure detected in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataSample {
+    /// The file the data is in, as given. None when it is piped on stdin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub format: DataFormat,
+    /// JSON paths, or CSV/table columns, or logfmt keys, redacted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub structure: Vec<String>,
+    /// The first lines of the data, capped and redacted.
+    #[serde(default)]
+    pub head: String,
+    /// The data continues past `head`.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Format of a [`DataSample`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    Json,
+    #[serde(rename = "jsonl")]
+    JsonLines,
+    Csv,
+    Tsv,
+    /// Whitespace-aligned columns under an upper-case header (ps, kubectl).
+    Table,
+    Logfmt,
+    Text,
+    Binary,
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataFormat::Json => write!(f, "JSON"),
+            DataFormat::JsonLines => write!(f, "JSON lines"),
+            DataFormat::Csv => write!(f, "CSV"),
+            DataFormat::Tsv => write!(f, "TSV"),
+            DataFormat::Table => write!(f, "table"),
+            DataFormat::Logfmt => write!(f, "logfmt"),
+            DataFormat::Text => write!(f, "text"),
+            DataFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+/// A snapshot of the top-level entries of cwd, git-ignored ones left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirListing {
+    /// The most recently modified entries, newest first.
+    #[serde(default)]
+    pub entries: Vec<ListingEntry>,
+    /// Entries left out past the limit.
+    #[serde(default)]
+    pub omitted: usize,
+    /// When the listing was taken, in Unix seconds. Ages in the prompt are
+    /// relative to it.
+    #[serde(default)]
+    pub taken_at: u64,
+}
+
+/// One entry of a [`DirListing`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListingEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    /// Size in bytes, for regular files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Modification time in Unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+/// What a [`ListingEntry`] is. Symlinks are not followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// Who commands run as and how packages get installed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostContext {
+    /// Commands run as root (effective uid 0).
+    #[serde(default)]
+    pub root: bool,
+    /// Privilege escalation commands on PATH, preferred first: "sudo", "doas".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub escalation: Vec<String>,
+    /// Package managers on PATH, system package manager first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_managers: Vec<String>,
 }
 
 /// Windows-specific host details used to select PowerShell command policy.
@@ -41,10 +173,108 @@ pub struct WindowsContext {
     pub diagnostic_tools: Vec<String>,
 }
 
+/// Which implementation of a core utility is first on PATH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UtilityFlavor {
+    Gnu,
+    Bsd,
+    Busybox,
+    Uutils,
+}
+
+impl fmt::Display for UtilityFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtilityFlavor::Gnu => write!(f, "GNU"),
+            UtilityFlavor::Bsd => write!(f, "BSD"),
+            UtilityFlavor::Busybox => write!(f, "BusyBox"),
+            UtilityFlavor::Uutils => write!(f, "uutils"),
+        }
+    }
+}
+
+/// Cloud and cluster targets that kubectl, aws, gcloud, and docker commands
+/// would act on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InfraContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kube_context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kube_namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcloud_project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docker_context: Option<String>,
+}
+
+impl InfraContext {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Repository state from `git status` and the `.git` directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitContext {
+    /// Checked-out branch. None when HEAD is detached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Abbreviated HEAD commit when detached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detached_at: Option<String>,
+    /// Upstream of the branch, e.g. "origin/main".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// Commits ahead of the upstream.
+    #[serde(default)]
+    pub ahead: u32,
+    /// Commits behind the upstream.
+    #[serde(default)]
+    pub behind: u32,
+    /// Tracked files with staged changes.
+    #[serde(default)]
+    pub staged: u32,
+    /// Tracked files with unstaged changes.
+    #[serde(default)]
+    pub unstaged: u32,
+    /// Files with unresolved conflicts.
+    #[serde(default)]
+    pub conflicted: u32,
+    /// Operation in progress: "merge", "rebase", "cherry-pick", "revert",
+    /// "am", or "bisect".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// Configured remote names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<String>,
+    /// Other local branches, most recently checked out first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_branches: Vec<String>,
+}
+
+impl GitContext {
+    /// The one-line form older daemons expect in `Context::git`, e.g.
+    /// "branch main, dirty" or "detached HEAD, clean".
+    pub fn summary(&self) -> String {
+        let head = match &self.branch {
+            Some(branch) => format!("branch {}", branch),
+            None => "detached HEAD".to_string(),
+        };
+        let dirty = self.staged + self.unstaged + self.conflicted > 0;
+        format!("{}, {}", head, if dirty { "dirty" } else { "clean" })
+    }
+}
+
 /// System context gathered by the client.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Context {
-    /// Current working directory.
+    /// Current working directory, as shared: empty when withheld, and
+    /// possibly `~`-relative or redacted (see `[context.send]`).
     pub cwd: PathBuf,
     /// User's shell (from $SHELL).
     pub shell: String,
@@ -56,15 +286,51 @@ pub struct Context {
     /// Detected project types in cwd (e.g. "rust", "node").
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub projects: Vec<String>,
+    /// Runnable project tasks, one summary line per source, e.g.
+    /// "make targets: build, test".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub project_tasks: Vec<String>,
     /// Modern CLI tools available on PATH (e.g. "rg", "fd", "jq").
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<String>,
+    /// Flavor of key utilities (sed, find, date, ...), keyed by tool name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub userland: BTreeMap<String, UtilityFlavor>,
+    /// Active cloud and cluster targets. Empty unless enabled under
+    /// `[context.infra]`.
+    #[serde(default, skip_serializing_if = "InfraContext::is_empty")]
+    pub infra: InfraContext,
     /// Git state of cwd, e.g. "branch main, dirty". None outside a repo.
+    /// Still sent for daemons that predate `git_state`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub git: Option<String>,
-    /// Environment flags: "ssh", "tmux", "docker".
+    /// Full repository state, preferred over `git` when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_state: Option<GitContext>,
+    /// Environment flags, e.g. "ssh", "tmux", "docker", "wsl",
+    /// "python venv (api)".
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub env_flags: Vec<String>,
+    /// Privileges and package managers on a POSIX host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<HostContext>,
+    /// Top-level entries of cwd. None unless enabled under
+    /// `[context.listing]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listing: Option<DirListing>,
+    /// Recent shell commands, redacted. Empty unless history context is
+    /// enabled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<String>,
+    /// User alias definitions, e.g. `glog='git log --graph'`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Names of user-defined shell functions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<String>,
+    /// Key/value facts from `[[context.providers]]` programs.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
     /// Windows-specific context. Absent on non-Windows hosts and older clients.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub windows: Option<WindowsContext>,
@@ -85,6 +351,10 @@ pub struct Response {
     /// Short explanation of the command (present when requested).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub explanation: Option<String>,
+    /// Interim status line. A frame carrying this is not the final response;
+    /// only sent to clients that set `Request::progress`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<String>,
 }
 
 impl Response {
@@ -95,6 +365,7 @@ impl Response {
             error: None,
             risk: Some(risk),
             explanation: None,
+            progress: None,
         }
     }
 
@@ -112,6 +383,18 @@ impl Response {
             error: None,
             risk: None,
             explanation: None,
+            progress: None,
+        }
+    }
+
+    /// Create an interim progress frame.
+    pub fn progress(status: String) -> Self {
+        Self {
+            command: None,
+            error: None,
+            risk: None,
+            explanation: None,
+            progress: Some(status),
         }
     }
 
@@ -122,6 +405,7 @@ impl Response {
             error: Some(message.into()),
             risk: None,
             explanation: None,
+            progress: None,
         }
     }
 }
@@ -132,6 +416,9 @@ impl Response {
 pub enum Message {
     /// A query request from the client.
     Query(Box<Request>),
+    /// Assess a command line the user is about to run. Answered with a
+    /// [`Response::success`] carrying the same command; no backend call.
+    Assess { command: String },
     /// Request daemon status.
     Status,
     /// Shutdown the daemon gracefully.
@@ -215,6 +502,15 @@ mod tests {
         assert_eq!(resp.error, Some("Connection failed".to_string()));
     }
 
+    #[test]
+    fn progress_frames_are_omitted_from_final_responses() {
+        let json = serde_json::to_string(&Response::error("x")).unwrap();
+        assert!(!json.contains("progress"));
+        let frame: Response = serde_json::from_str(r#"{"progress":"pulling manifest"}"#).unwrap();
+        assert_eq!(frame.progress.as_deref(), Some("pulling manifest"));
+        assert!(frame.command.is_none() && frame.error.is_none());
+    }
+
     #[test]
     fn test_request_serialization() {
         let req = Request {
@@ -225,14 +521,28 @@ mod tests {
                 os: "Linux 5.15.0".to_string(),
                 distro: Some("Ubuntu 22.04".to_string()),
                 projects: Vec::new(),
+                project_tasks: Vec::new(),
+                userland: BTreeMap::new(),
+                infra: InfraContext::default(),
                 tools: Vec::new(),
                 git: None,
+                git_state: None,
                 env_flags: Vec::new(),
+                host: None,
+                listing: None,
+                extra: BTreeMap::new(),
+                history: Vec::new(),
+                aliases: Vec::new(),
+                functions: Vec::new(),
                 windows: None,
             },
             model: None,
             temperature: None,
             explain: false,
+            progress: false,
+            failed: None,
+            edit: None,
+            sample: None,
         };
         let json = serde_json::to_string(&req).unwrap();
         let parsed: Request = serde_json::from_str(&json).unwrap();
@@ -249,14 +559,28 @@ mod tests {
                 os: "Linux 5.15.0".to_string(),
                 distro: None,
                 projects: Vec::new(),
+                project_tasks: Vec::new(),
+                userland: BTreeMap::new(),
+                infra: InfraContext::default(),
                 tools: Vec::new(),
                 git: None,
+                git_state: None,
                 env_flags: Vec::new(),
+                host: None,
+                listing: None,
+                extra: BTreeMap::new(),
+                history: Vec::new(),
+                aliases: Vec::new(),
+                functions: Vec::new(),
                 windows: None,
             },
             model: Some("qwen2.5-coder:1.5b".to_string()),
             temperature: Some(0.2),
             explain: false,
+            progress: false,
+            failed: None,
+            edit: None,
+            sample: None,
         };
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains("qwen2.5-coder:1.5b"));
@@ -280,19 +604,57 @@ mod tests {
                 os: "linux".to_string(),
                 distro: None,
                 projects: Vec::new(),
+                project_tasks: Vec::new(),
+                userland: BTreeMap::new(),
+                infra: InfraContext::default(),
                 tools: Vec::new(),
                 git: None,
+                git_state: None,
                 env_flags: Vec::new(),
+                host: None,
+                listing: None,
+                extra: BTreeMap::new(),
+                history: Vec::new(),
+                aliases: Vec::new(),
+                functions: Vec::new(),
                 windows: None,
             },
+            failed: None,
+            edit: None,
+            sample: None,
             model: None,
             temperature: None,
             explain: true,
+            progress: false,
         };
         let round: Request = serde_json::from_str(&serde_json::to_string(&req).unwrap()).unwrap();
         assert!(round.explain);
     }
 
+    #[test]
+    fn git_string_from_older_clients_still_deserializes() {
+        let json = r#"{"cwd":"/","shell":"sh","os":"linux","git":"branch main, dirty"}"#;
+        let context: Context = serde_json::from_str(json).unwrap();
+        assert_eq!(context.git.as_deref(), Some("branch main, dirty"));
+        assert!(context.git_state.is_none());
+
+        // Newer clients send both; older daemons read the string.
+        let state = GitContext {
+            branch: Some("main".to_string()),
+            staged: 1,
+            ..Default::default()
+        };
+        assert_eq!(state.summary(), "branch main, dirty");
+        let context = Context {
+            git: Some(state.summary()),
+            git_state: Some(state),
+            ..context
+        };
+        let value = serde_json::to_value(&context).unwrap();
+        assert_eq!(value["git"], "branch main, dirty");
+        assert_eq!(value["git_state"]["branch"], "main");
+    }
+
     #[test]
     fn context_without_windows_details_remains_wire_compatible() {
         let json = r#"{"cwd":"/","shell":"sh","os":"linux"}"#;
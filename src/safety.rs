@@ -68,16 +68,93 @@ impl Assessment {
     }
 }
 
+/// `[safety]` config section.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    /// Site-specific rules checked alongside the built-in table.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A `[[safety.rules]]` entry: the fields of a built-in [`Rule`], with the
+/// patterns as regex source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleConfig {
+    /// Identifier shown in findings; must not reuse a built-in one.
+    pub id: String,
+    /// `caution` or `destructive`.
+    pub level: RiskLevel,
+    /// Explanation shown with the warning.
+    pub reason: String,
+    /// Patterns that must all match.
+    pub all: Vec<String>,
+    /// Pattern that suppresses the finding when it matches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unless: Option<String>,
+    /// What the patterns are matched against.
+    #[serde(default)]
+    pub scope: RuleScope,
+}
+
+/// What a rule's patterns are matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleScope {
+    /// The whole command line as generated, quotes and all.
+    #[default]
+    Command,
+    /// Each POSIX simple command on its own, quotes removed. A match that
+    /// starts in a quoted or escaped argument does not count.
+    Posix,
+    /// Each PowerShell statement, ignoring strings and comments.
+    PowerShell,
+}
+
+/// Compiled `[[safety.rules]]`, checked by [`assess_with`].
+#[derive(Default)]
+pub struct CustomRules(Vec<Rule>);
+
+impl CustomRules {
+    /// Compile the configured rules, reporting every bad entry.
+    pub fn compile(rules: &[RuleConfig]) -> anyhow::Result<Self> {
+        let mut compiled = Vec::new();
+        let mut problems = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            match Rule::from_config(rule) {
+                Ok(rule) => compiled.push(rule),
+                Err(problem) => problems.push(format!(
+                    "safety.rules[{}] ({:?}): {}",
+                    index, rule.id, problem
+                )),
+            }
+            if rules[..index].iter().any(|earlier| earlier.id == rule.id) {
+                problems.push(format!(
+                    "safety.rules[{}]: duplicate id {:?}",
+                    index, rule.id
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(Self(compiled))
+        } else {
+            anyhow::bail!(
+                "Invalid [[safety.rules]] entries:\n  {}",
+                problems.join("\n  ")
+            )
+        }
+    }
+}
+
 /// A detection rule. `all` patterns must every one match; a match is
 /// suppressed when `unless` matches (the regex crate has no lookaround, so
 /// exceptions are expressed structurally instead).
 struct Rule {
-    id: &'static str,
+    id: String,
     level: RiskLevel,
-    reason: &'static str,
+    reason: String,
     all: Vec<Regex>,
     unless: Option<Regex>,
-    segment_scoped: bool,
+    scope: RuleScope,
 }
 
 impl Rule {
@@ -88,18 +165,55 @@ impl Rule {
         all: &[&str],
         unless: Option<&str>,
     ) -> Self {
-        let segment_scoped = id.starts_with("powershell-") || id.starts_with("windows-");
+        let scope = if id.starts_with("powershell-") || id.starts_with("windows-") {
+            RuleScope::PowerShell
+        } else if WHOLE_COMMAND_RULES.contains(&id) {
+            RuleScope::Command
+        } else {
+            RuleScope::Posix
+        };
         Self {
-            id,
+            id: id.to_string(),
             level,
-            reason,
+            reason: reason.to_string(),
             all: all
                 .iter()
                 .map(|p| Regex::new(p).expect("static safety pattern must compile"))
                 .collect(),
             unless: unless.map(|p| Regex::new(p).expect("static safety pattern must compile")),
-            segment_scoped,
+            scope,
+        }
+    }
+
+    /// A rule from config. Unlike the built-in table, bad patterns are the
+    /// user's to fix, so they are reported rather than panicking.
+    fn from_config(rule: &RuleConfig) -> Result<Self, String> {
+        if rule.id.trim().is_empty() {
+            return Err("empty id".to_string());
+        }
+        if RULES.iter().any(|builtin| builtin.id == rule.id) {
+            return Err("id is already used by a built-in rule".to_string());
         }
+        if rule.level == RiskLevel::Safe {
+            return Err("level must be caution or destructive".to_string());
+        }
+        if rule.reason.trim().is_empty() {
+            return Err("empty reason".to_string());
+        }
+        if rule.all.is_empty() {
+            return Err("`all` needs at least one pattern".to_string());
+        }
+        let compile = |pattern: &String| {
+            Regex::new(pattern).map_err(|e| format!("pattern {:?}: {}", pattern, e))
+        };
+        Ok(Self {
+            id: rule.id.clone(),
+            level: rule.level,
+            reason: rule.reason.clone(),
+            all: rule.all.iter().map(compile).collect::<Result<_, _>>()?,
+            unless: rule.unless.as_ref().map(compile).transpose()?,
+            scope: rule.scope,
+        })
     }
     fn specialized_match(
         &self,
@@ -110,7 +224,7 @@ impl Rule {
             powershell_launcher_start(source, operation_start)
                 .map(|(launcher_start, _)| launcher_start)
         };
-        match self.id {
+        match self.id.as_str() {
             "powershell-encoded-command" => Some(
                 launcher_start()
                     .is_some_and(|start| matches_encoded_powershell_command(source, start)),
@@ -123,10 +237,32 @@ impl Rule {
         }
     }
 
-    fn matches(&self, command: &str, sources: &[PowerShellSource<'_>]) -> bool {
-        if !self.segment_scoped {
-            return self.all.iter().all(|re| re.is_match(command))
-                && self.unless.as_ref().is_none_or(|re| !re.is_match(command));
+    fn matches(
+        &self,
+        command: &str,
+        sources: &[PowerShellSource<'_>],
+        segments: &[PosixSegment],
+    ) -> bool {
+        match self.scope {
+            RuleScope::Command => {
+                return self.all.iter().all(|re| re.is_match(command))
+                    && self.unless.as_ref().is_none_or(|re| !re.is_match(command));
+            }
+            RuleScope::Posix => {
+                return segments.iter().any(|segment| {
+                    self.all.iter().all(|re| {
+                        re.captures_iter(&segment.text).any(|found| {
+                            let whole = found.get(0).expect("group 0 always matches");
+                            segment.is_code(whole.start(), whole.end())
+                                && !segment.quotes_target(&found)
+                        })
+                    }) && self.unless.as_ref().is_none_or(|re| {
+                        !re.captures_iter(&segment.text)
+                            .any(|found| !segment.quotes_target(&found))
+                    })
+                });
+            }
+            RuleScope::PowerShell => {}
         }
 
         sources.iter().any(|source| {
@@ -1064,16 +1200,708 @@ fn powershell_subexpression_end(command: &str, open_paren: usize) -> Option<usiz
     }
     None
 }
+/// One POSIX simple command, as the safety rules see it.
+struct PosixSegment {
+    /// The words, quotes removed, joined by single spaces. Quoted or
+    /// escaped whitespace and operators become `_`, so a quoted argument
+    /// stays one word.
+    text: String,
+    /// Per byte of `text`: whether it came from a quoted or escaped
+    /// argument. A command name runs however it is quoted, so its bytes
+    /// never count.
+    literal: Vec<bool>,
+}
+
+impl PosixSegment {
+    /// Whether a match is code rather than quoted text, judged by its first
+    /// alphanumeric byte.
+    fn is_code(&self, start: usize, end: usize) -> bool {
+        let bytes = self.text.as_bytes();
+        let first = (start..end)
+            .find(|&index| bytes[index].is_ascii_alphanumeric())
+            .unwrap_or(start);
+        self.literal.get(first).is_none_or(|&literal| !literal)
+    }
+
+    /// Whether a pattern's `target` group matched a quoted glob or
+    /// expansion: `rm -rf '*'` removes a file named `*`, not everything.
+    fn quotes_target(&self, found: &regex::Captures<'_>) -> bool {
+        let bytes = self.text.as_bytes();
+        found.name("target").is_some_and(|target| {
+            (target.start()..target.end())
+                .any(|index| b"*?~$".contains(&bytes[index]) && self.literal[index])
+        })
+    }
+}
+
+/// A word being read.
+#[derive(Default)]
+struct PosixWord {
+    /// The word as the command receives it.
+    value: String,
+    /// `value` with quoted whitespace and operators masked, for matching.
+    text: String,
+    literal: Vec<bool>,
+    /// Set by quotes too, so `''` is a word.
+    started: bool,
+    /// A redirection operator such as `2>` or `<<-`.
+    operator: bool,
+}
+
+impl PosixWord {
+    fn push(&mut self, c: char, literal: bool) {
+        self.started = true;
+        self.value.push(c);
+        let shown = if literal && (c.is_whitespace() || ";&|<>()".contains(c)) {
+            '_'
+        } else {
+            c
+        };
+        self.text.push(shown);
+        self.literal
+            .extend(std::iter::repeat_n(literal, shown.len_utf8()));
+    }
+}
+
+/// A heredoc whose body starts after the next newline.
+struct PosixHeredoc {
+    delimiter: String,
+    /// `<<-` strips leading tabs before comparing with the delimiter.
+    strip_tabs: bool,
+    /// Index of the segment that reads it.
+    segment: usize,
+}
+
+/// How a finished segment, empty ones included, passes code to a shell.
+#[derive(Default)]
+struct PosixStage {
+    /// A shell reading its script from stdin.
+    shell_stdin: bool,
+    /// Its output is piped into the next segment.
+    piped: bool,
+    /// Its quoted arguments: a script when piped into a shell, as in
+    /// `echo 'rm -rf /' | sh`.
+    quoted: Vec<String>,
+}
+
+/// What a simple command does with code passed to it.
+enum PosixInvocation {
+    /// `sh -c SCRIPT` or `eval ARGS`.
+    Script(String),
+    /// A shell reading its script from stdin, e.g. a heredoc.
+    ShellStdin,
+    Other,
+}
+
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "ash"];
+const POSIX_WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "nice", "exec", "command", "time", "timeout", "xargs",
+];
+const POSIX_RESERVED: &[&str] = &[
+    "!", "{", "}", "if", "then", "elif", "else", "fi", "do", "done", "while", "until", "time",
+];
+
+/// Splits a POSIX command line into simple commands. Like the PowerShell
+/// lexer it handles only what the advisory rules need: quotes, escapes,
+/// comments, heredocs, separators, and the places code hides in a command
+/// (`$(...)`, backticks, process substitution), which are collected as
+/// scripts to lex in turn.
+struct PosixLexer {
+    chars: Vec<char>,
+    index: usize,
+    word: PosixWord,
+    words: Vec<PosixWord>,
+    segments: Vec<PosixSegment>,
+    stages: Vec<PosixStage>,
+    /// Set after `<<` (true for `<<-`): the next word is a delimiter.
+    heredoc_next: Option<bool>,
+    heredocs: Vec<PosixHeredoc>,
+    scripts: Vec<String>,
+}
+
+impl PosixLexer {
+    fn new(command: &str) -> Self {
+        Self {
+            chars: command.chars().collect(),
+            index: 0,
+            word: PosixWord::default(),
+            words: Vec::new(),
+            segments: Vec::new(),
+            stages: Vec::new(),
+            heredoc_next: None,
+            heredocs: Vec::new(),
+            scripts: Vec::new(),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    /// The segments of the command and the scripts it runs inline.
+    fn run(mut self) -> (Vec<PosixSegment>, Vec<String>) {
+        while let Some(c) = self.peek(0) {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.end_word();
+                    self.index += 1;
+                }
+                '\n' => {
+                    self.index += 1;
+                    self.end_segment();
+                    self.read_heredocs();
+                }
+                '#' if !self.word.started => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.index += 1;
+                    }
+                }
+                '\\' => {
+                    match self.peek(1) {
+                        Some('\n') => {}
+                        Some(escaped) => self.word.push(escaped, true),
+                        None => self.word.push('\\', false),
+                    }
+                    self.index += 2;
+                }
+                '\'' => {
+                    self.index += 1;
+                    self.quoted_until('\'', false);
+                }
+                '"' => self.double_quoted(),
+                '`' => self.backticks(),
+                '$' => self.dollar(false),
+                '<' | '>' if self.peek(1) == Some('(') => {
+                    let end = self.closing_paren(self.index + 1);
+                    self.substitution(self.index + 2, end);
+                }
+                '<' | '>' => self.redirect(),
+                '&' if self.peek(1) == Some('>') => self.redirect(),
+                ';' | '&' | '|' | '(' | ')' => {
+                    let doubled = matches!(
+                        (c, self.peek(1)),
+                        (';', Some(';')) | ('&', Some('&')) | ('|', Some('|' | '&'))
+                    );
+                    // `|` and `|&` pipe; `||` does not.
+                    let pipe = c == '|' && self.peek(1) != Some('|');
+                    self.index += 1 + usize::from(doubled);
+                    self.end_segment();
+                    if let Some(stage) = self.stages.last_mut().filter(|_| pipe) {
+                        stage.piped = true;
+                    }
+                }
+                _ => {
+                    self.word.push(c, false);
+                    self.index += 1;
+                }
+            }
+        }
+        self.end_segment();
+        (self.segments, self.scripts)
+    }
+
+    /// Read quoted text up to `close`, with backslash escapes when
+    /// `escapes` is set (`$'...'`).
+    fn quoted_until(&mut self, close: char, escapes: bool) {
+        self.word.started = true;
+        while let Some(c) = self.peek(0) {
+            self.index += 1;
+            if c == close {
+                return;
+            }
+            if escapes && c == '\\' {
+                if let Some(escaped) = self.peek(0) {
+                    self.word.push(escaped, true);
+                    self.index += 1;
+                }
+                continue;
+            }
+            self.word.push(c, true);
+        }
+    }
+
+    fn double_quoted(&mut self) {
+        self.index += 1;
+        self.word.started = true;
+        while let Some(c) = self.peek(0) {
+            match c {
+                '"' => {
+                    self.index += 1;
+                    return;
+                }
+                '\\' => match self.peek(1) {
+                    Some('\n') => self.index += 2,
+                    Some(escaped @ ('$' | '`' | '"' | '\\')) => {
+                        self.word.push(escaped, true);
+                        self.index += 2;
+                    }
+                    _ => {
+                        self.word.push('\\', true);
+                        self.index += 1;
+                    }
+                },
+                '$' => self.dollar(true),
+                '`' => self.backticks(),
+                _ => {
+                    self.word.push(c, true);
+                    self.index += 1;
+                }
+            }
+        }
+    }
+
+    fn dollar(&mut self, quoted: bool) {
+        match self.peek(1) {
+            Some('(') => {
+                let end = self.closing_paren(self.index + 1);
+                if self.peek(2) == Some('(') {
+                    // Arithmetic: no commands to find, but keep it one word.
+                    self.push_opaque(self.index, end + 1);
+                    self.index = end + 1;
+                } else {
+                    self.substitution(self.index + 2, end);
+                }
+            }
+            Some('{') => {
+                let end = (self.index..self.chars.len())
+                    .find(|&index| self.chars[index] == '}')
+                    .unwrap_or(self.chars.len());
+                self.push_opaque(self.index, end + 1);
+                self.index = end + 1;
+            }
+            Some('\'') if !quoted => {
+                self.index += 2;
+                self.quoted_until('\'', true);
+            }
+            // An expansion, quoted or not: `"$HOME"` is still the home.
+            _ => {
+                self.word.push('$', false);
+                self.index += 1;
+            }
+        }
+    }
+
+    fn backticks(&mut self) {
+        let mut end = self.index + 1;
+        while end < self.chars.len() && self.chars[end] != '`' {
+            end += 1 + usize::from(self.chars[end] == '\\');
+        }
+        let end = end.min(self.chars.len());
+        self.scripts
+            .push(self.chars[self.index + 1..end].iter().collect());
+        self.push_opaque(self.index, end + 1);
+        self.index = end + 1;
+    }
+
+    /// `$(...)`, `<(...)`, or `>(...)`: the body from `body_start` up to
+    /// the closing paren at `end` is a script of its own, and the whole
+    /// construct one opaque piece of the current word.
+    fn substitution(&mut self, body_start: usize, end: usize) {
+        let body_start = body_start.min(end);
+        self.scripts
+            .push(self.chars[body_start..end].iter().collect());
+        self.push_opaque(self.index, end + 1);
+        self.index = end + 1;
+    }
+
+    fn push_opaque(&mut self, start: usize, end: usize) {
+        for index in start..end.min(self.chars.len()) {
+            self.word.push(self.chars[index], true);
+        }
+    }
+
+    /// Index of the paren that closes the one at `open`, or the end of the
+    /// input when it is unclosed.
+    fn closing_paren(&self, open: usize) -> usize {
+        let chars = &self.chars;
+        let mut depth = 0_usize;
+        let mut index = open;
+        while index < chars.len() {
+            match chars[index] {
+                '\\' => index += 1,
+                '\'' => {
+                    index += 1;
+                    while index < chars.len() && chars[index] != '\'' {
+                        index += 1;
+                    }
+                }
+                '"' => {
+                    index += 1;
+                    while index < chars.len() && chars[index] != '"' {
+                        index += 1 + usize::from(chars[index] == '\\');
+                    }
+                }
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return index;
+                    }
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        chars.len()
+    }
+
+    fn redirect(&mut self) {
+        // A file descriptor number belongs to the operator: `2>/dev/null`.
+        let mut operator = if self.word.started
+            && !self.word.literal.contains(&true)
+            && self.word.value.chars().all(|c| c.is_ascii_digit())
+        {
+            std::mem::take(&mut self.word).value
+        } else {
+            self.end_word();
+            String::new()
+        };
+        if self.peek(0) == Some('&') {
+            operator.push('&');
+            self.index += 1;
+        }
+        let Some(first) = self.peek(0) else {
+            return;
+        };
+        operator.push(first);
+        self.index += 1;
+        match (first, self.peek(0)) {
+            ('<', Some('<')) => {
+                operator.push('<');
+                self.index += 1;
+                match self.peek(0) {
+                    Some('<') => {
+                        operator.push('<');
+                        self.index += 1;
+                    }
+                    next => {
+                        let strip_tabs = next == Some('-');
+                        if strip_tabs {
+                            operator.push('-');
+                            self.index += 1;
+                        }
+                        self.heredoc_next = Some(strip_tabs);
+                    }
+                }
+            }
+            ('>', Some(next @ ('>' | '|' | '&'))) | ('<', Some(next @ ('&' | '>'))) => {
+                operator.push(next);
+                self.index += 1;
+            }
+            _ => {}
+        }
+        self.words.push(PosixWord {
+            literal: vec![false; operator.len()],
+            text: operator.clone(),
+            value: operator,
+            started: true,
+            operator: true,
+        });
+    }
+
+    fn end_word(&mut self) {
+        if !self.word.started {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        if let Some(strip_tabs) = self.heredoc_next.take() {
+            self.heredocs.push(PosixHeredoc {
+                delimiter: word.value.clone(),
+                strip_tabs,
+                segment: self.stages.len(),
+            });
+        }
+        self.words.push(word);
+    }
+
+    fn end_segment(&mut self) {
+        self.end_word();
+        let words = std::mem::take(&mut self.words);
+        let command = posix_command_index(&words);
+        let invocation = command.map_or(PosixInvocation::Other, |command| {
+            posix_invocation(&words, command)
+        });
+        let shell_stdin = matches!(invocation, PosixInvocation::ShellStdin);
+        if shell_stdin {
+            // Whatever is piped in or given as a here-string is the script.
+            for stage in self.stages.iter().rev().take_while(|stage| stage.piped) {
+                self.scripts.extend(stage.quoted.iter().cloned());
+            }
+            for pair in words.windows(2) {
+                if pair[0].operator && pair[0].value.ends_with("<<<") {
+                    self.scripts.push(pair[1].value.clone());
+                }
+            }
+        }
+        if let PosixInvocation::Script(script) = invocation {
+            self.scripts.push(script);
+        }
+        let quoted = words
+            .iter()
+            .enumerate()
+            .filter(|&(index, word)| {
+                Some(index) != command && !word.operator && word.literal.contains(&true)
+            })
+            .map(|(_, word)| word.value.clone())
+            .collect();
+        self.stages.push(PosixStage {
+            shell_stdin,
+            piped: false,
+            quoted,
+        });
+        if words.is_empty() {
+            return;
+        }
+
+        let mut text = String::new();
+        let mut literal = Vec::new();
+        for (index, word) in words.iter().enumerate() {
+            if index > 0 {
+                text.push(' ');
+                literal.push(false);
+            }
+            text.push_str(&word.text);
+            if Some(index) == command {
+                literal.extend(std::iter::repeat_n(false, word.text.len()));
+            } else {
+                literal.extend(&word.literal);
+            }
+        }
+        self.segments.push(PosixSegment { text, literal });
+    }
+
+    /// Whether segment `index` is a shell reading stdin or pipes into one.
+    fn feeds_shell(&self, index: usize) -> bool {
+        self.stages[index..]
+            .iter()
+            .find(|stage| stage.shell_stdin || !stage.piped)
+            .is_some_and(|stage| stage.shell_stdin)
+    }
+
+    /// Read the bodies of the heredocs opened on the line just ended. A
+    /// body fed to a shell, directly or through a pipe, is a script; any
+    /// other is data.
+    fn read_heredocs(&mut self) {
+        for heredoc in std::mem::take(&mut self.heredocs) {
+            let mut body = String::new();
+            while self.index < self.chars.len() {
+                let end = (self.index..self.chars.len())
+                    .find(|&index| self.chars[index] == '\n')
+                    .unwrap_or(self.chars.len());
+                let line: String = self.chars[self.index..end].iter().collect();
+                self.index = (end + 1).min(self.chars.len());
+                let bare = if heredoc.strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if bare == heredoc.delimiter {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            if self.feeds_shell(heredoc.segment) {
+                self.scripts.push(body);
+            }
+        }
+    }
+}
+
+/// The word a simple command runs, after assignments, redirections, and
+/// reserved words such as `if` or `{`.
+fn posix_command_index(words: &[PosixWord]) -> Option<usize> {
+    let mut index = 0;
+    while let Some(word) = words.get(index) {
+        if word.operator {
+            index += 2;
+            continue;
+        }
+        let assignment = word.value.split_once('=').is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        if !assignment && !POSIX_RESERVED.contains(&word.value.as_str()) {
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+/// How the simple command `words` treats code: `eval`, a program given a
+/// script anywhere in the command (`docker exec c sh -c ...`, `ssh host
+/// ...`, `su -c ...`, `watch ...`), or a shell reading stdin, directly or
+/// behind sudo and similar wrappers.
+fn posix_invocation(words: &[PosixWord], command: usize) -> PosixInvocation {
+    // `/usr/bin/sudo` is sudo.
+    fn name(word: &PosixWord) -> &str {
+        word.value.rsplit('/').next().unwrap_or_default()
+    }
+
+    let words: Vec<&PosixWord> = posix_arguments(&words[command..]).collect();
+    if name(words[0]) == "eval" {
+        let script: Vec<&str> = words[1..].iter().map(|word| word.value.as_str()).collect();
+        return PosixInvocation::Script(script.join(" "));
+    }
+    for (index, word) in words.iter().enumerate() {
+        let rest = &words[index + 1..];
+        let script = match name(word) {
+            shell if POSIX_SHELLS.contains(&shell) => match posix_shell(rest) {
+                PosixInvocation::Script(script) => Some(script),
+                _ => None,
+            },
+            "ssh" => ssh_command(rest),
+            "su" => su_command(rest),
+            "watch" => watch_command(rest),
+            _ => None,
+        };
+        if let Some(script) = script {
+            return PosixInvocation::Script(script);
+        }
+    }
+
+    let shell = if POSIX_WRAPPERS.contains(&name(words[0])) {
+        words
+            .iter()
+            .position(|word| POSIX_SHELLS.contains(&name(word)))
+    } else {
+        Some(0)
+    };
+    match shell {
+        Some(shell) if POSIX_SHELLS.contains(&name(words[shell])) => {
+            posix_shell(&words[shell + 1..])
+        }
+        _ => PosixInvocation::Other,
+    }
+}
+
+/// What a shell given `arguments` runs: `-c SCRIPT`, a script file, or
+/// stdin.
+fn posix_shell(arguments: &[&PosixWord]) -> PosixInvocation {
+    let mut script_next = false;
+    for word in arguments {
+        match word.value.strip_prefix('-') {
+            Some(flags) if !flags.starts_with('-') => {
+                script_next |= flags.contains('c');
+            }
+            Some(_) => {}
+            None if script_next => return PosixInvocation::Script(word.value.clone()),
+            // A script file.
+            None => return PosixInvocation::Other,
+        }
+    }
+    PosixInvocation::ShellStdin
+}
+
+/// The remote command of `ssh [OPTIONS] HOST COMMAND...`, which the remote
+/// shell runs as a script.
+fn ssh_command(arguments: &[&PosixWord]) -> Option<String> {
+    // Options that take a value, as in `-p 22` or `-o Foo=bar`.
+    const WITH_VALUE: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+    let mut index = 0;
+    while let Some(flags) = arguments
+        .get(index)
+        .and_then(|word| word.value.strip_prefix('-'))
+    {
+        let takes_value = flags
+            .char_indices()
+            .find(|&(_, flag)| WITH_VALUE.contains(flag))
+            .is_some_and(|(at, _)| at + 1 == flags.len());
+        index += 1 + usize::from(takes_value);
+    }
+    joined_command(arguments.get(index + 1..)?)
+}
+
+/// The command of `su -c COMMAND` or `su --command=COMMAND`.
+fn su_command(arguments: &[&PosixWord]) -> Option<String> {
+    let mut rest = arguments.iter();
+    while let Some(word) = rest.next() {
+        let value = word.value.as_str();
+        if let Some(command) = value.strip_prefix("--command=") {
+            return Some(command.to_string());
+        }
+        let short = value
+            .strip_prefix('-')
+            .is_some_and(|flags| !flags.starts_with('-') && flags.ends_with('c'));
+        if short || value == "--command" {
+            return rest.next().map(|word| word.value.clone());
+        }
+    }
+    None
+}
+
+/// The command of `watch [OPTIONS] COMMAND...`, which watch runs with
+/// `sh -c`.
+fn watch_command(arguments: &[&PosixWord]) -> Option<String> {
+    let mut index = 0;
+    while let Some(word) = arguments.get(index) {
+        match word.value.as_str() {
+            "--" => {
+                index += 1;
+                break;
+            }
+            "-n" | "--interval" | "-q" | "--equexit" => index += 2,
+            option if option.starts_with('-') => index += 1,
+            _ => break,
+        }
+    }
+    joined_command(arguments.get(index..)?)
+}
+
+/// Command words as the program hands them to a shell: joined by spaces.
+fn joined_command(words: &[&PosixWord]) -> Option<String> {
+    let words: Vec<&str> = words.iter().map(|word| word.value.as_str()).collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// `words` without redirections and their targets.
+fn posix_arguments(words: &[PosixWord]) -> impl Iterator<Item = &PosixWord> {
+    let mut target = false;
+    words.iter().filter(move |word| {
+        let argument = !word.operator && !target;
+        target = word.operator;
+        argument
+    })
+}
+
+/// Simple commands of `command` and of the scripts it runs inline: command
+/// and process substitutions, backticks, `sh -c`, `eval`, `ssh`, `su -c`,
+/// and `watch` arguments, and text piped or heredocs fed to a shell.
+fn posix_segments(command: &str) -> Vec<PosixSegment> {
+    let mut pending = vec![command.to_string()];
+    let mut segments = Vec::new();
+    let mut index = 0;
+
+    while index < pending.len() {
+        let (found, scripts) = PosixLexer::new(&pending[index]).run();
+        segments.extend(found);
+        for script in scripts {
+            if pending.len() < 16 && !pending.contains(&script) {
+                pending.push(script);
+            }
+        }
+        index += 1;
+    }
+    segments
+}
+
+// Built-in rules that are not per POSIX command: SQL lives inside quoted
+// arguments, and the others span a pipeline or a function definition.
+const WHOLE_COMMAND_RULES: &[&str] = &["fork-bomb", "pipe-download-to-shell", "sql-drop"];
+
 // Building blocks reused across rm/chmod rules.
 //
 // "Broad target" = an argument that expands to the filesystem root, the home
 // directory, or everything in the current directory. The argument must END at
 // the match (whitespace/EOL/separator), so `/tmp/foo` or `~/old` never count.
+// The `target` group lets the POSIX matcher skip a quoted `*` or `~`, which
+// names a file rather than expanding.
 const RM_RECURSIVE: &str = r"\brm\s[^|;&]*(-[a-zA-Z]*[rR]|--recursive\b)";
 const RM_FORCE: &str = r"\brm\s[^|;&]*(-[a-zA-Z]*f|--force\b)";
-const RM_BROAD_TARGET: &str = r#"\brm\s[^|;&]*\s(/\*?|~/?|"?\$HOME"?/?|\*|\.\.|\.)\s*($|;|&|\|)"#;
+const RM_BROAD_TARGET: &str =
+    r#"\brm\s[^|;&]*\s(?P<target>/\*?|~/?|"?\$HOME"?/?|\*|\.\.|\.)\s*($|;|&|\|)"#;
 const CHMOD_RECURSIVE_WORLD: &str = r"\bchmod\s[^|;&]*-[a-zA-Z]*R[^|;&]*\b(777|a\+rwx)\b";
-const CHMOD_BROAD_TARGET: &str = r#"\bchmod\s[^|;&]*\s(/|~/?|"?\$HOME"?/?)\s*($|;|&|\|)"#;
+const CHMOD_BROAD_TARGET: &str = r#"\bchmod\s[^|;&]*\s(?P<target>/|~/?|"?\$HOME"?/?)\s*($|;|&|\|)"#;
 
 // PowerShell common parameters are case-insensitive. `-WhatIf` suppresses only
 // cmdlet rules that honor it; appending it to a native executable is not a
@@ -1424,19 +2252,28 @@ static RULES: LazyLock<Vec<Rule>> = LazyLock::new(|| {
     ]
 });
 
-/// Assess a generated command against the rule table.
+/// Assess a command against the built-in table alone.
+#[cfg(test)]
+pub fn assess(command: &str) -> Assessment {
+    assess_with(command, &CustomRules::default())
+}
+
+/// Assess a generated command against the rule table, then the configured
+/// `[[safety.rules]]`.
 ///
 /// Every matching rule becomes a [`Finding`]; the overall level is the most
 /// severe finding (or [`RiskLevel::Safe`] when nothing matches).
-pub fn assess(command: &str) -> Assessment {
+pub fn assess_with(command: &str, custom: &CustomRules) -> Assessment {
     let sources = powershell_sources(command);
+    let segments = posix_segments(command);
     let mut findings: Vec<Finding> = RULES
         .iter()
-        .filter(|rule| rule.matches(command, &sources))
+        .chain(&custom.0)
+        .filter(|rule| rule.matches(command, &sources, &segments))
         .map(|rule| Finding {
-            rule: rule.id.to_string(),
+            rule: rule.id.clone(),
             level: rule.level,
-            reason: rule.reason.to_string(),
+            reason: rule.reason.clone(),
         })
         .collect();
 
@@ -1779,6 +2616,216 @@ mod tests {
         assert!(RiskLevel::Caution < RiskLevel::Destructive);
     }
 
+    // ── POSIX lexing ───────────────────────────────────────────────────
+
+    #[test]
+    fn quoted_and_commented_text_is_not_a_command() {
+        for cmd in [
+            "echo \"don't rm -rf /\"",
+            "git commit -m 'rm -rf / was a bad idea'",
+            "grep -n 'mkfs.ext4 /dev/sdb' runbook.md",
+            "echo crontab\\ -r",
+            "ls # then rm -rf ~",
+            "cat <<'EOF' > notes.md\nrm -rf /\ngit push --force\nEOF\nwc -l notes.md",
+        ] {
+            assert_safe(cmd);
+        }
+        // Quoting the command name or its target changes nothing.
+        assert_flags(
+            "\"rm\" -rf /",
+            "rm-recursive-force-broad",
+            RiskLevel::Destructive,
+        );
+        assert_flags(
+            "rm -rf '/'",
+            "rm-recursive-force-broad",
+            RiskLevel::Destructive,
+        );
+        assert_flags(
+            "find . -name '*.log' -exec rm {} \\;",
+            "find-delete",
+            RiskLevel::Caution,
+        );
+        // SQL is always quoted, so that rule still reads the whole line.
+        assert_flags("psql -c 'DROP TABLE users'", "sql-drop", RiskLevel::Caution);
+        // Unterminated constructs run to the end of the input.
+        for cmd in [
+            "echo 'a", "echo $(", "`", "cat <<", "echo \\", "${", "$'", "2>",
+        ] {
+            assess(cmd);
+        }
+    }
+
+    #[test]
+    fn code_run_inline_is_lexed_too() {
+        for cmd in [
+            "bash -c 'rm -rf /'",
+            "sudo sh -ec \"rm -rf ~\"",
+            "echo \"cleaned $(rm -rf ~)\"",
+            "echo `rm -rf /`",
+            "eval 'rm -rf /'",
+            "diff <(rm -rf /) b",
+            "bash <<EOF\nrm -rf /\nEOF",
+        ] {
+            assert_flags(cmd, "rm-recursive-force-broad", RiskLevel::Destructive);
+        }
+        assert_safe("bash deploy.sh 'rm -rf /'");
+    }
+
+    #[test]
+    fn text_fed_to_a_shell_is_lexed_as_code() {
+        for cmd in [
+            "echo \"rm -rf /\" | sh",
+            "printf 'rm -rf ~' | bash",
+            "echo 'rm -rf /' | tee run.log | sudo sh",
+            "cat <<EOF | sh\nrm -rf /\nEOF",
+            "sh <<< 'rm -rf /'",
+            "ssh host 'rm -rf /'",
+            "ssh -p 2222 -o BatchMode=yes admin@host rm -rf '~'",
+            "docker exec c sh -c 'rm -rf /'",
+            "kubectl exec p -- sh -c \"rm -rf /\"",
+            "busybox sh -c 'rm -rf /'",
+            "su -c 'rm -rf /'",
+            "sudo su - root --command='rm -rf /'",
+            "watch 'rm -rf ~'",
+            "watch -n 5 -- rm -rf '/'",
+        ] {
+            assert_flags(cmd, "rm-recursive-force-broad", RiskLevel::Destructive);
+        }
+        for cmd in [
+            "echo 'rm -rf /' | grep rm",
+            "echo 'rm -rf /' || sh",
+            "cat <<EOF | wc -l\nrm -rf /\nEOF",
+            "ssh host",
+            "docker run -it ubuntu bash",
+            "watch -n 5 ls",
+        ] {
+            assert_safe(cmd);
+        }
+    }
+
+    #[test]
+    fn a_quoted_glob_or_tilde_is_a_file_name() {
+        for cmd in ["rm -rf '*'", "rm -rf \\*", "rm -rf \"~\"", "rm -rf '$HOME'"] {
+            assert_not_rule(cmd, "rm-recursive-force-broad");
+            assert_flags(cmd, "rm-recursive-force", RiskLevel::Caution);
+        }
+        assert_not_rule("chmod -R 777 '~'", "chmod-world-writable-broad");
+        assert_flags(
+            "chmod -R 777 '~'",
+            "chmod-world-writable",
+            RiskLevel::Caution,
+        );
+    }
+
+    #[test]
+    fn exceptions_apply_to_their_own_command() {
+        // --force-with-lease in the second push does not excuse the first.
+        assert_flags(
+            "git push -f origin main; git push --force-with-lease backup main",
+            "git-push-force",
+            RiskLevel::Caution,
+        );
+        assert_flags(
+            "dd if=/dev/zero of=/dev/sda && dd if=a of=b",
+            "dd-write",
+            RiskLevel::Caution,
+        );
+        assert_safe("ls -la 2>&1 | tee out.txt && echo 'kill -9 1' >&2");
+    }
+
+    fn custom(toml: &str) -> anyhow::Result<CustomRules> {
+        let config: SafetyConfig = toml::from_str(toml).unwrap();
+        CustomRules::compile(&config.rules)
+    }
+
+    #[test]
+    fn custom_rules_are_checked_with_the_builtin_table() {
+        let rules = custom(
+            r#"
+[[rules]]
+id = "terraform-destroy"
+level = "destructive"
+reason = "destroys every resource in the workspace"
+all = ['\bterraform\s+destroy\b']
+unless = '-target\b'
+
+[[rules]]
+id = "ourctl-db-reset"
+level = "caution"
+reason = "wipes the service database"
+all = ['\bourctl\b', '\bdb\s+reset\b']
+scope = "posix"
+"#,
+        )
+        .unwrap();
+
+        let a = assess_with("terraform destroy -auto-approve && rm -rf /", &rules);
+        let ids: Vec<&str> = a.findings.iter().map(|f| f.rule.as_str()).collect();
+        assert!(ids.contains(&"terraform-destroy") && ids.contains(&"rm-recursive-force-broad"));
+        assert_eq!(a.level, RiskLevel::Destructive);
+
+        assert!(assess_with("terraform destroy -target=aws_instance.a", &rules).is_safe());
+        let a = assess_with("ourctl --env staging db reset", &rules);
+        assert_eq!(
+            (a.level, a.findings[0].reason.as_str()),
+            (RiskLevel::Caution, "wipes the service database")
+        );
+        assert!(assess_with("ourctl db status", &rules).is_safe());
+        assert!(assess_with("echo 'ourctl db reset'", &rules).is_safe());
+        // Without the config they are unknown.
+        assert!(assess("ourctl db reset").is_safe());
+    }
+
+    #[test]
+    fn bad_custom_rules_are_reported_together() {
+        let error = custom(
+            r#"
+[[rules]]
+id = "unclosed"
+level = "caution"
+reason = "r"
+all = ['(terraform']
+
+[[rules]]
+id = "dd-to-block-device"
+level = "caution"
+reason = "r"
+all = ['dd']
+
+[[rules]]
+id = "harmless"
+level = "safe"
+reason = "r"
+all = []
+
+[[rules]]
+id = "unclosed"
+level = "caution"
+reason = "r"
+all = ['x']
+"#,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(
+            error.starts_with("Invalid [[safety.rules]] entries:"),
+            "{error}"
+        );
+        assert!(
+            error.contains("safety.rules[0] (\"unclosed\"): pattern \"(terraform\""),
+            "{error}"
+        );
+        assert!(error.contains(
+            "safety.rules[1] (\"dd-to-block-device\"): id is already used by a built-in rule"
+        ));
+        assert!(
+            error.contains("safety.rules[2] (\"harmless\"): level must be caution or destructive")
+        );
+        assert!(error.contains("safety.rules[3]: duplicate id \"unclosed\""));
+    }
+
     #[test]
     fn assessment_serializes_for_ipc() {
         let a = assess("rm -rf /");
@@ -1880,10 +2927,9 @@ mod tests {
             }
         }
 
-        for rule in RULES
-            .iter()
-            .filter(|rule| rule.segment_scoped && rule.level == RiskLevel::Destructive)
-        {
+        for rule in RULES.iter().filter(|rule| {
+            rule.scope == RuleScope::PowerShell && rule.level == RiskLevel::Destructive
+        }) {
             assert!(
                 cases.iter().any(|(_, case_rule)| *case_rule == rule.id),
                 "missing boundary coverage for {}",
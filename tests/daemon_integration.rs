@@ -15,32 +15,44 @@ use std::io::{Read, Write};
 use std::net::TcpListener;
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command};
+use std::process::{Child, Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 /// A canned-response mock Ollama server.
 ///
-/// `GET /api/tags` always succeeds (daemon health check). `POST
-/// /api/generate` answers with the configured status and body.
+/// `GET /api/tags` always succeeds (daemon health check) and lists the
+/// installed models, initially only `mock-model`. `POST /api/generate`
+/// answers 404 for a model that is not installed and otherwise with the
+/// configured status and body. `POST /api/pull` streams progress and
+/// installs the model. Generate and pull request bodies are recorded.
 struct MockOllama {
     port: u16,
     requests: Arc<Mutex<Vec<serde_json::Value>>>,
 }
 
+/// Shared state of one mock server.
+struct MockState {
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    installed: Mutex<Vec<String>>,
+}
+
 impl MockOllama {
     fn start(generate_status: u16, generate_body: String) -> Self {
         let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock");
         let port = listener.local_addr().unwrap().port();
         let requests = Arc::new(Mutex::new(Vec::new()));
-        let server_requests = Arc::clone(&requests);
+        let state = Arc::new(MockState {
+            requests: Arc::clone(&requests),
+            installed: Mutex::new(vec!["mock-model".to_string()]),
+        });
         std::thread::spawn(move || {
             for stream in listener.incoming() {
                 let Ok(mut stream) = stream else { continue };
                 let body = generate_body.clone();
-                let requests = Arc::clone(&server_requests);
+                let state = Arc::clone(&state);
                 std::thread::spawn(move || {
-                    let _ = serve_one(&mut stream, generate_status, &body, &requests);
+                    let _ = serve_one(&mut stream, generate_status, &body, &state);
                 });
             }
         });
@@ -64,7 +76,7 @@ fn serve_one(
     stream: &mut std::net::TcpStream,
     generate_status: u16,
     generate_body: &str,
-    requests: &Mutex<Vec<serde_json::Value>>,
+    state: &MockState,
 ) -> std::io::Result<()> {
     stream.set_read_timeout(Some(Duration::from_secs(5)))?;
 
@@ -101,19 +113,60 @@ fn serve_one(
     }
     request_body.truncate(content_length);
 
+    let request: serde_json::Value =
+        serde_json::from_slice(&request_body).unwrap_or(serde_json::Value::Null);
+    let record = |request: &serde_json::Value| {
+        state
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request.clone());
+    };
+    let mut installed = state
+        .installed
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
     let (status, body) = if request_line.starts_with("GET /api/tags") {
-        (200u16, r#"{"models":[{"name":"mock-model"}]}"#.to_string())
+        let models: Vec<_> = installed
+            .iter()
+            .map(|name| serde_json::json!({ "name": name }))
+            .collect();
+        (200u16, serde_json::json!({ "models": models }).to_string())
     } else if request_line.starts_with("POST /api/generate") {
-        if let Ok(request) = serde_json::from_slice(&request_body) {
-            requests
-                .lock()
-                .unwrap_or_else(|poisoned| poisoned.into_inner())
-                .push(request);
+        if !request.is_null() {
+            record(&request);
+        }
+        match request["model"].as_str() {
+            Some(model) if !installed.iter().any(|m| m == model) => (
+                404,
+                format!(r#"{{"error":"model '{model}' not found, try pulling it first"}}"#),
+            ),
+            _ => (generate_status, generate_body.to_string()),
+        }
+    } else if request_line.starts_with("POST /api/pull") {
+        if !request.is_null() {
+            record(&request);
+        }
+        if let Some(name) = request["name"].as_str() {
+            installed.push(name.to_string());
         }
-        (generate_status, generate_body.to_string())
+        (
+            200,
+            concat!(
+                r#"{"status":"pulling manifest"}"#,
+                "\n",
+                r#"{"status":"pulling 1234","completed":512,"total":1024}"#,
+                "\n",
+                r#"{"status":"success"}"#,
+                "\n"
+            )
+            .to_string(),
+        )
     } else {
         (404, r#"{"error":"not found"}"#.to_string())
     };
+    drop(installed);
 
     let reason = if status < 400 { "OK" } else { "Error" };
     write!(
@@ -124,12 +177,48 @@ fn serve_one(
     stream.flush()
 }
 
+/// An isolated HOME, config, and runtime dir for running `incant` with no
+/// daemon, or for starting one.
+struct ClientFixture {
+    home: tempfile::TempDir,
+    runtime_dir: PathBuf,
+}
+
+impl ClientFixture {
+    /// Write `config` as the config file of a fresh isolated environment.
+    fn new(config: &str) -> Self {
+        let home = tempfile::tempdir().expect("tempdir");
+        let runtime_dir = home.path().join("runtime");
+        std::fs::create_dir_all(home.path().join("config/incant")).unwrap();
+        std::fs::create_dir_all(&runtime_dir).unwrap();
+        let fixture = Self { home, runtime_dir };
+        fixture.write_config(config);
+        fixture
+    }
+
+    /// Replace the config file, for configs that name paths in the home.
+    fn write_config(&self, config: &str) {
+        let path = self.home.path().join("config/incant/config.toml");
+        std::fs::write(path, config).unwrap();
+    }
+
+    /// An `incant` invocation wired to this environment only.
+    fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_incant"));
+        command
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", self.home.path())
+            .env("XDG_CONFIG_HOME", self.home.path().join("config"))
+            .env("XDG_RUNTIME_DIR", &self.runtime_dir);
+        command
+    }
+}
+
 /// An isolated daemon process plus the paths it lives under.
 struct DaemonFixture {
     child: Child,
-    #[allow(dead_code)]
-    home: tempfile::TempDir,
-    runtime_dir: PathBuf,
+    client: ClientFixture,
     socket_path: PathBuf,
     mock: MockOllama,
 }
@@ -151,39 +240,30 @@ impl DaemonFixture {
         preferences: &str,
     ) -> Self {
         let mock = MockOllama::start(generate_status, generate_body.to_string());
+        let config = format!(
+            "[backend]\ntype = \"ollama\"\nhost = \"{}\"\ndefault_profile = \"default\"\n\n[profiles.default]\nmodel = \"mock-model\"\ntemperature = 0.1\n\n[preferences]\n{}\n",
+            mock.host(),
+            preferences
+        );
+        Self::start_with_config(mock, &config)
+    }
 
-        let home = tempfile::tempdir().expect("tempdir");
-        let config_home = home.path().join("config");
-        let runtime_dir = home.path().join("runtime");
-        std::fs::create_dir_all(config_home.join("incant")).unwrap();
-        std::fs::create_dir_all(&runtime_dir).unwrap();
-        std::fs::write(
-            config_home.join("incant/config.toml"),
-            format!(
-                "[backend]\ntype = \"ollama\"\nhost = \"{}\"\ndefault_profile = \"default\"\n\n[profiles.default]\nmodel = \"mock-model\"\ntemperature = 0.1\n\n[preferences]\n{}\n",
-                mock.host(),
-                preferences
-            ),
-        )
-        .unwrap();
-
-        let child = Command::new(env!("CARGO_BIN_EXE_incant"))
+    /// Start a daemon from a complete config file. `mock` is kept alive for
+    /// the fixture's lifetime whether or not the config points at it.
+    fn start_with_config(mock: MockOllama, config: &str) -> Self {
+        let client = ClientFixture::new(config);
+        let child = client
+            .command()
             .args(["daemon", "run"])
-            .env_clear()
-            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
-            .env("HOME", home.path())
-            .env("XDG_CONFIG_HOME", &config_home)
-            .env("XDG_RUNTIME_DIR", &runtime_dir)
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
             .spawn()
             .expect("spawn daemon");
 
-        let socket_path = runtime_dir.join("incant.sock");
+        let socket_path = client.runtime_dir.join("incant.sock");
         let fixture = Self {
             child,
-            home,
-            runtime_dir,
+            client,
             socket_path,
             mock,
         };
@@ -244,14 +324,7 @@ impl DaemonFixture {
 
     /// A client `incant` invocation wired to this fixture's isolated env.
     fn client_command(&self) -> Command {
-        let mut command = Command::new(env!("CARGO_BIN_EXE_incant"));
-        command
-            .env_clear()
-            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
-            .env("HOME", self.home.path())
-            .env("XDG_CONFIG_HOME", self.home.path().join("config"))
-            .env("XDG_RUNTIME_DIR", &self.runtime_dir);
-        command
+        self.client.command()
     }
 }
 
@@ -379,6 +452,29 @@ fn destructive_command_carries_risk_assessment() {
         .any(|f| f["rule"] == "rm-recursive-force-broad"));
 }
 
+#[test]
+fn configured_safety_rules_reach_the_daemon() {
+    let daemon = DaemonFixture::start_with_preferences(
+        200,
+        r#"{"response":"terraform destroy -auto-approve","done":true}"#,
+        r#"
+[[safety.rules]]
+id = "terraform-destroy"
+level = "destructive"
+reason = "destroys managed infrastructure"
+all = ['\bterraform\s+destroy\b']
+unless = '-target\b'
+"#,
+    );
+    let resp = daemon.query("tear down the stack", false);
+    assert_eq!(resp["risk"]["level"], "destructive");
+    assert_eq!(resp["risk"]["findings"][0]["rule"], "terraform-destroy");
+    assert_eq!(
+        resp["risk"]["findings"][0]["reason"],
+        "destroys managed infrastructure"
+    );
+}
+
 #[test]
 fn explain_request_returns_explanation() {
     let daemon = DaemonFixture::start(200, r#"{"response":"df -h","done":true}"#);
@@ -406,6 +502,570 @@ fn status_message_reports_backend() {
     assert!(text.contains("mock-model"), "unexpected status: {text}");
 }
 
+#[test]
+fn assess_message_checks_typed_commands_without_the_backend() {
+    // The mock would answer 500: assessing must not touch the backend.
+    let daemon = DaemonFixture::start(500, r#"{"error":"unused"}"#);
+    let mut stream = daemon.connect();
+    write_frame(
+        &mut stream,
+        &serde_json::json!({"type": "assess", "command": "cd / && rm -rf *"}),
+    );
+    let resp = read_frame(&mut stream);
+    assert_eq!(resp["command"], "cd / && rm -rf *");
+    assert_eq!(resp["risk"]["level"], "destructive");
+    assert!(daemon.generate_requests().is_empty());
+
+    let guard = |args: &[&str], answer: &str| {
+        let mut child = daemon
+            .client_command()
+            .arg("guard")
+            .args(args)
+            .args(["--", "rm -rf ~"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run client");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answer.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+    let (code, stderr) = guard(&[], "");
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stderr.starts_with("!! destructive: "), "{stderr}");
+    let (code, stderr) = guard(&["--confirm"], "n\n");
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("Run it anyway? [y/N]"), "{stderr}");
+    assert_eq!(guard(&["--confirm"], "y\n").0, Some(0));
+
+    // Without a daemon the check happens in-process.
+    let output = daemon
+        .client_command()
+        .env(
+            "XDG_RUNTIME_DIR",
+            daemon.client.home.path().join("elsewhere"),
+        )
+        .args(["guard", "--", "git push --force"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("! caution: "));
+}
+
+#[test]
+fn exec_plugin_backend_round_trips_json_lines() {
+    let plugin = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/exec_plugin.sh");
+    let config = format!(
+        "[backend]\ntype = \"exec\"\ncommand = \"sh\"\nargs = [{:?}]\n\n[profiles.default]\nmodel = \"plugin-small\"\n\n[preferences]\nclipboard = false\n",
+        plugin.display().to_string()
+    );
+    // The Ollama mock stays idle; the plugin answers everything.
+    let daemon = DaemonFixture::start_with_config(MockOllama::start(500, String::new()), &config);
+
+    let resp = daemon.query("hello", false);
+    assert_eq!(resp["command"], "echo hello");
+    assert_eq!(resp["risk"]["level"], "safe");
+
+    let resp = daemon.query("fail", false);
+    let error = resp["error"].as_str().unwrap();
+    assert!(error.contains("model plugin-small refused"), "{error}");
+
+    // The plugin survives an error reply and keeps serving.
+    assert_eq!(daemon.query("again", false)["command"], "echo again");
+    assert!(daemon.generate_requests().is_empty());
+
+    let output = daemon
+        .client_command()
+        .args(["models", "list"])
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "models list failed: {stdout}");
+    assert!(stdout.contains("plugin-small") && stdout.contains("plugin-large"));
+}
+
+#[test]
+fn recorded_responses_replay_without_the_backend() {
+    let mock = MockOllama::start(200, r#"{"response":"du -sh *","done":true}"#.to_string());
+    let config = format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\nrecord = true\n\n[profiles.default]\nmodel = \"mock-model\"\n",
+        mock.host()
+    );
+    let recorder = DaemonFixture::start_with_config(mock, &config);
+    assert_eq!(recorder.query("folder sizes", false)["command"], "du -sh *");
+
+    let cassette = recorder
+        .client
+        .home
+        .path()
+        .join("config/incant/cassette.jsonl");
+    let recorded = std::fs::read_to_string(&cassette).expect("cassette written");
+    assert_eq!(recorded.lines().count(), 1);
+    assert_eq!(mode_of(&cassette), 0o600, "cassettes embed context");
+
+    // A replay daemon answers from the cassette; its mock would only fail.
+    let replay_config = format!(
+        "[backend]\ntype = \"replay\"\ncassette = {:?}\n\n[profiles.default]\nmodel = \"mock-model\"\n",
+        cassette.display().to_string()
+    );
+    let replayer =
+        DaemonFixture::start_with_config(MockOllama::start(500, String::new()), &replay_config);
+    let resp = replayer.query("folder sizes", false);
+    assert_eq!(resp["command"], "du -sh *");
+    assert!(replayer.generate_requests().is_empty());
+
+    let miss = replayer.query("something never recorded", false);
+    let error = miss["error"].as_str().unwrap();
+    assert!(error.contains("No recorded response"), "{error}");
+}
+
+#[test]
+fn models_list_and_ensure_check_profiles_against_the_backend() {
+    let mock = MockOllama::start(200, String::new());
+    let config = format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\n\n[profiles.default]\nmodel = \"mock-model\"\n\n[profiles.heavy]\nmodel = \"absent:32b\"\n",
+        mock.host()
+    );
+    let daemon = DaemonFixture::start_with_config(mock, &config);
+
+    let output = daemon
+        .client_command()
+        .args(["models", "list"])
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "models list failed: {stdout}");
+    assert!(
+        stdout.contains("mock-model  [profile: default]"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("heavy -> absent:32b"), "{stdout}");
+
+    let output = daemon
+        .client_command()
+        .args(["models", "ensure"])
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "models ensure failed: {stdout}");
+    assert!(stdout.contains("mock-model: already installed"), "{stdout}");
+    let pulls: Vec<_> = daemon
+        .generate_requests()
+        .into_iter()
+        .filter_map(|r| r["name"].as_str().map(String::from))
+        .collect();
+    assert_eq!(pulls, vec!["absent:32b".to_string()]);
+}
+
+#[test]
+fn missing_models_fail_with_a_typed_error_or_auto_pull() {
+    let mock = MockOllama::start(200, r#"{"response":"ls","done":true}"#.to_string());
+    let config = format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\n\n[profiles.default]\nmodel = \"absent:7b\"\n",
+        mock.host()
+    );
+    let daemon = DaemonFixture::start_with_config(mock, &config);
+    let error = daemon.query("list files", false)["error"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("not installed"), "{error}");
+    assert!(error.contains("incant models pull absent:7b"), "{error}");
+    drop(daemon);
+
+    let mock = MockOllama::start(200, r#"{"response":"ls","done":true}"#.to_string());
+    let config = format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\nauto_pull = true\n\n[profiles.default]\nmodel = \"fresh:1b\"\n",
+        mock.host()
+    );
+    let daemon = DaemonFixture::start_with_config(mock, &config);
+    let mut stream = daemon.connect();
+    write_frame(
+        &mut stream,
+        &serde_json::json!({
+            "type": "query",
+            "query": "list files",
+            "context": {"cwd": "/tmp", "shell": "/bin/sh", "os": "TestOS 1.0"},
+            "progress": true,
+        }),
+    );
+    // Zero or more progress frames, depending on whether the startup pull
+    // already finished, then the answer.
+    let response = loop {
+        let frame = read_frame(&mut stream);
+        match frame["progress"].as_str() {
+            Some(status) => assert!(status.starts_with("Pulling fresh:1b"), "{status}"),
+            None => break frame,
+        }
+    };
+    assert_eq!(response["command"], "ls", "{response}");
+    let pulls = daemon
+        .generate_requests()
+        .into_iter()
+        .filter(|r| r.get("name").is_some())
+        .count();
+    assert_eq!(pulls, 1, "the startup and query pulls must be shared");
+}
+
+#[test]
+fn local_only_policy_refuses_cloud_backends_and_shows_in_status() {
+    let client = ClientFixture::new("[backend]\ntype = \"anthropic\"\napi_key = \"sk-test\"\n");
+    let output = client
+        .command()
+        .args(["daemon", "run"])
+        .env("INCANT_NETWORK", "local-only")
+        .output()
+        .expect("run daemon");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "cloud backend must be refused");
+    assert!(
+        stderr.contains("local-only (set by INCANT_NETWORK)"),
+        "{stderr}"
+    );
+    assert!(!client.runtime_dir.join("incant.sock").exists());
+
+    let mock = MockOllama::start(200, r#"{"response":"ls","done":true}"#.to_string());
+    let config = format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\n\n[profiles.default]\nmodel = \"mock-model\"\n\n[privacy]\nnetwork = \"local-only\"\n",
+        mock.host()
+    );
+    let daemon = DaemonFixture::start_with_config(mock, &config);
+    assert_eq!(daemon.query("list files", false)["command"], "ls");
+    let output = daemon
+        .client_command()
+        .args(["daemon", "status"])
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Network: local-only (set by config file)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn local_only_policy_refuses_model_commands_on_remote_backends() {
+    let client = ClientFixture::new(
+        "[backend]\ntype = \"ollama\"\nhost = \"http://192.0.2.1:11434\"\n\n[privacy]\nnetwork = \"local-only\"\n",
+    );
+    for args in [
+        &["models", "list"][..],
+        &["models", "pull", "qwen2.5-coder:7b"],
+    ] {
+        let output = client.command().args(args).output().expect("run client");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{args:?} must be refused");
+        assert!(
+            stderr.contains("Network policy is local-only (set by config file)"),
+            "{stderr}"
+        );
+    }
+
+    client.write_config("[backend]\ntype = \"anthropic\"\napi_key = \"sk-test\"\n");
+    let output = client
+        .command()
+        .args(["models", "list"])
+        .env("INCANT_NETWORK", "local-only")
+        .output()
+        .expect("run client");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("anthropic backend is a cloud API"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn context_history_previews_redacted_entries_without_a_daemon() {
+    let client = ClientFixture::new("");
+    let history = client.home.path().join("history");
+    std::fs::write(
+        &history,
+        "#1700000000\ngit status\n export SECRET_PLAN=1\ncurl -u x https://bob:pw@example.com\n",
+    )
+    .unwrap();
+    client.write_config(&format!("[context.history]\nfile = {:?}\n", history));
+
+    let output = client
+        .command()
+        .args(["context", "history"])
+        .env("SHELL", "/bin/bash")
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("disabled. Nothing is sent."), "{stdout}");
+    assert!(
+        stdout.contains("  git status\n  curl -u x https://[REDACTED]@example.com\n"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("SECRET_PLAN"), "{stdout}");
+    assert!(!stdout.contains("1700000000"), "{stdout}");
+}
+
+#[test]
+fn check_assesses_commands_without_a_daemon() {
+    let client = ClientFixture::new(
+        "[[safety.rules]]\nid = \"ourctl-db-reset\"\nlevel = \"caution\"\nreason = \"wipes the service database\"\nall = ['\\bourctl\\s+db\\s+reset\\b']\n",
+    );
+    let check = |args: &[&str], stdin: &str| {
+        let mut child = client
+            .command()
+            .arg("check")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("run client");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    };
+
+    let (code, stdout) = check(&["--", "rm", "-rf", "/"], "");
+    assert_eq!(code, Some(4), "{stdout}");
+    assert!(
+        stdout.starts_with("rm -rf /\n  destructive [rm-recursive-force-broad]: "),
+        "{stdout}"
+    );
+    assert_eq!(check(&["--", "ls", "-la"], ""), (Some(0), String::new()));
+    // Words are re-quoted, so a quoted argument stays data.
+    assert_eq!(
+        check(&["--", "echo", "rm -rf /"], ""),
+        (Some(0), String::new())
+    );
+    let (code, stdout) = check(&["--", "sh", "-c", "rm -rf /"], "");
+    assert_eq!(code, Some(4), "{stdout}");
+    assert!(stdout.starts_with("sh -c 'rm -rf /'\n"), "{stdout}");
+
+    let (code, stdout) = check(&["--json"], "ls\n\nourctl db reset\necho 'rm -rf /'\n");
+    assert_eq!(code, Some(3), "{stdout}");
+    let reports: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(reports.len(), 3, "{stdout}");
+    assert_eq!(reports[1]["command"], "ourctl db reset");
+    assert_eq!(reports[1]["level"], "caution");
+    assert_eq!(reports[1]["findings"][0]["rule"], "ourctl-db-reset");
+    assert_eq!(reports[2]["level"], "safe");
+}
+
+#[test]
+fn install_guard_hooks_bind_enter_to_the_check() {
+    let install = |shell: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_incant"))
+            .args(["install", "--guard"])
+            .env("SHELL", shell)
+            .output()
+            .expect("run client");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let fish = install("/usr/bin/fish");
+    assert!(
+        fish.contains("\nbind \\r _incant_guard_execute\n"),
+        "{fish}"
+    );
+    for line in fish.lines().filter(|line| line.starts_with("bind ")) {
+        assert_eq!(line.split_whitespace().count(), 3, "{line:?}");
+    }
+
+    // bash checks the readline buffer, not history, which skips
+    // space-prefixed lines under HISTCONTROL=ignorespace.
+    let bash = install("/bin/bash");
+    assert!(
+        bash.contains("_incant_guard_line+=$READLINE_LINE"),
+        "{bash}"
+    );
+    assert!(bash.contains(r#"'"\C-m": "\C-x\C-g\C-x\C-j"'"#), "{bash}");
+    assert!(!bash.contains("history 1"), "{bash}");
+    assert!(bash.contains("`shopt -s extdebug` for the whole shell"));
+}
+
+#[test]
+fn context_show_prints_the_filtered_context_and_prompt() {
+    let client = ClientFixture::new(
+        "[context.send]\ngit = false\nenv_flags = false\nhome_as_tilde = true\nredact_paths = [\"acme-[a-z]+\"]\n",
+    );
+    let project = client.home.path().join("work/acme-app");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(project.join("Cargo.toml"), "").unwrap();
+
+    let output = client
+        .command()
+        .args(["context", "show"])
+        .current_dir(&project)
+        .env("TMUX", "/tmp/tmux-1000/default,1,0")
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains(r#""cwd": "~/work/[REDACTED]""#), "{stdout}");
+    assert!(stdout.contains(r#""projects": ["#), "{stdout}");
+    assert!(!stdout.contains(r#""env_flags""#), "{stdout}");
+    assert!(!stdout.contains(r#""git""#), "{stdout}");
+    assert!(stdout.contains("\nSystem prompt:\n"), "{stdout}");
+    assert!(stdout.contains("CWD: ~/work/[REDACTED]"), "{stdout}");
+    assert!(!stdout.contains("acme"), "{stdout}");
+}
+
+#[test]
+fn edit_rewrites_the_command_line_and_ignores_an_empty_one() {
+    let daemon = DaemonFixture::start(200, r#"{"response":"fd -e log","done":true}"#);
+
+    let output = daemon
+        .client_command()
+        .args(["--edit", "find . -name '*.log'", "use fd"])
+        .output()
+        .expect("run client");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "fd -e log");
+    let requests = daemon.generate_requests();
+    let request = requests.last().expect("generate request");
+    assert!(request["system"].as_str().unwrap().contains("Edit mode:"));
+    assert_eq!(
+        request["prompt"],
+        "Command: find . -name '*.log'\nChange: use fd"
+    );
+
+    // A blank command line is an ordinary query.
+    let output = daemon
+        .client_command()
+        .args(["--edit", "  ", "list logs"])
+        .output()
+        .expect("run client");
+    assert!(output.status.success());
+    let requests = daemon.generate_requests();
+    let request = requests.last().expect("generate request");
+    assert!(!request["system"].as_str().unwrap().contains("Edit mode:"));
+    assert_eq!(request["prompt"], "list logs");
+}
+
+#[test]
+fn piped_data_and_files_are_sampled_into_the_prompt() {
+    let daemon = DaemonFixture::start(200, r#"{"response":"jq -r .items[].name","done":true}"#);
+
+    let piped = |args: &[&str]| {
+        let mut child = daemon
+            .client_command()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run client");
+        // A client that refuses its arguments may exit before reading.
+        let _ = child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(br#"{"items":[{"name":"api","token":"password=hunter2"}]}"#);
+        child.wait_with_output().expect("wait for client")
+    };
+    let output = piped(&["--stdin", "names of the items"]);
+    assert!(output.status.success());
+    let requests = daemon.generate_requests();
+    let system = requests.last().expect("generate request")["system"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(
+        system.contains("Input data: JSON piped on stdin"),
+        "{}",
+        system
+    );
+    assert!(system.contains("  .items[].name (string)"));
+    assert!(!system.contains("hunter2"), "{}", system);
+
+    let file = daemon.client.home.path().join("users.csv");
+    std::fs::write(&file, "id,email\n1,a@example.com\n").unwrap();
+    let output = daemon
+        .client_command()
+        .args(["--pipe", "--file"])
+        .arg(&file)
+        .arg("emails only")
+        .output()
+        .expect("run client");
+    assert!(output.status.success());
+    let requests = daemon.generate_requests();
+    let system = requests.last().unwrap()["system"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(system.contains(&format!("Input data: CSV in the file {}", file.display())));
+    assert!(system.contains("  columns: id, email"));
+
+    // Without --stdin, piped input is left alone; --pipe refuses it.
+    let output = piped(&["--pipe", "list files"]);
+    assert!(output.status.success());
+    let requests = daemon.generate_requests();
+    assert!(!requests.last().unwrap()["system"]
+        .as_str()
+        .unwrap()
+        .contains("Input data:"));
+    assert!(!piped(&["--pipe", "--stdin", "list files"]).status.success());
+}
+
+#[test]
+fn fix_sends_the_failed_command_and_its_stderr() {
+    let daemon = DaemonFixture::start(200, r#"{"response":"git push -u origin feat","done":true}"#);
+    let stderr_file = daemon.client.home.path().join("stderr");
+    std::fs::write(
+        &stderr_file,
+        "fatal: The current branch feat has no upstream branch.\n\0$ ",
+    )
+    .unwrap();
+
+    let output = daemon
+        .client_command()
+        .args([
+            "fix",
+            "--command",
+            "git push",
+            "--status",
+            "128",
+            "--stderr-file",
+        ])
+        .arg(&stderr_file)
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout.trim(), "git push -u origin feat");
+
+    let requests = daemon.generate_requests();
+    let request = requests.last().expect("generate request");
+    assert!(request["system"].as_str().unwrap().contains("Fix mode:"));
+    let prompt = request["prompt"].as_str().unwrap();
+    assert!(prompt.contains("Failed command: git push"), "{prompt}");
+    assert!(prompt.contains("Exit status: 128"), "{prompt}");
+    assert!(prompt.ends_with("has no upstream branch."), "{prompt}");
+    assert!(!prompt.contains("$ "), "the prompt after the marker is cut");
+}
+
 // ── error propagation ──────────────────────────────────────────────────
 
 #[test]
@@ -486,7 +1146,7 @@ fn socket_is_owner_only_inside_private_runtime_dir() {
     let daemon = DaemonFixture::start(200, r#"{"response":"x","done":true}"#);
     assert_eq!(mode_of(&daemon.socket_path), 0o600, "socket must be 0600");
     assert_eq!(
-        mode_of(&daemon.runtime_dir),
+        mode_of(&daemon.client.runtime_dir),
         0o700,
         "runtime dir must be 0700"
     );
@@ -560,6 +1220,119 @@ fn pipe_mode_still_translates_directly() {
     assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "df -h");
 }
 
+#[test]
+fn widget_exported_aliases_reach_the_prompt_filtered_and_redacted() {
+    let daemon = DaemonFixture::start_with_preferences(
+        200,
+        r#"{"response":"glog","done":true}"#,
+        "clipboard = false\n\n[context.aliases]\ndeny = [\"secret*\"]",
+    );
+
+    let output = daemon
+        .client_command()
+        .args(["--pipe", "show the commit graph"])
+        .env(
+            "INCANT_ALIASES",
+            "glog='git log --oneline --graph'\nsecretly='ssh vault'\npub='TOKEN=abc123 deploy'\n",
+        )
+        .env("INCANT_FUNCTIONS", "mkcd _private_helper")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run client");
+    assert!(
+        output.status.success(),
+        "client failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let requests = daemon.generate_requests();
+    let system = requests[0]["system"].as_str().unwrap();
+    assert!(
+        system.contains(
+            "User aliases: glog='git log --oneline --graph'; pub='TOKEN=[REDACTED] deploy'"
+        ),
+        "{system}"
+    );
+    assert!(system.contains("User shell functions: mkcd"), "{system}");
+    assert!(!system.contains("vault"), "{system}");
+    assert!(!system.contains("_private_helper"), "{system}");
+}
+
+#[test]
+fn configured_tools_and_markers_reach_the_prompt_and_status() {
+    let daemon = DaemonFixture::start_with_preferences(
+        200,
+        r#"{"response":"deployctl api prod","done":true}"#,
+        "clipboard = false\n\n[context]\nmarkers_mode = \"replace\"\n\n[context.tools]\ndeployctl = \"Internal deploy CLI: deployctl <service> <env>\"\n\n[context.markers]\n\"service.yaml\" = \"deploy\"",
+    );
+    let bin = tempfile::tempdir().expect("tempdir");
+    std::fs::write(bin.path().join("deployctl"), "").unwrap();
+    let project = tempfile::tempdir().expect("tempdir");
+    std::fs::write(project.path().join("service.yaml"), "").unwrap();
+    std::fs::write(project.path().join("Cargo.toml"), "").unwrap();
+    let path = std::env::join_paths(std::iter::once(bin.path().to_path_buf()).chain(
+        std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+    ))
+    .unwrap();
+
+    let output = daemon
+        .client_command()
+        .args(["--pipe", "deploy the api to prod"])
+        .env("PATH", path)
+        .current_dir(project.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run client");
+    assert!(
+        output.status.success(),
+        "client failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = daemon.generate_requests();
+    let system = requests[0]["system"].as_str().unwrap();
+    assert!(
+        system.contains("Installed tools:\n  deployctl: Internal deploy CLI"),
+        "{system}"
+    );
+    assert!(
+        !system
+            .lines()
+            .any(|line| line.contains("classic equivalents") && line.contains("deployctl")),
+        "{system}"
+    );
+    assert!(system.contains("Project: deploy"), "{system}");
+    assert!(!system.contains("rust"), "{system}");
+
+    let output = daemon
+        .client_command()
+        .args(["daemon", "status"])
+        .output()
+        .expect("run client");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Context: tools extend (+deployctl), markers replace (service.yaml)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn daemon_refuses_invalid_context_tables() {
+    let client = ClientFixture::new(
+        "[backend]\ntype = \"ollama\"\nhost = \"http://127.0.0.1:9\"\n\n[context.tools]\n\"bin/tool\" = \"\"\n",
+    );
+    let output = client
+        .command()
+        .args(["daemon", "run"])
+        .output()
+        .expect("run daemon");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "invalid tables must be refused");
+    assert!(
+        stderr.contains("\"bin/tool\" is not a bare executable name"),
+        "{stderr}"
+    );
+}
+
 // ── daemon detachment ──────────────────────────────────────────────────
 
 /// `incant daemon start` must detach the daemon into its own session.
@@ -570,30 +1343,18 @@ fn pipe_mode_still_translates_directly() {
 fn started_daemon_is_detached_from_launcher_session() {
     let mock = MockOllama::start(200, r#"{"response":"true","done":true}"#.to_string());
 
-    let home = tempfile::tempdir().expect("tempdir");
-    let config_home = home.path().join("config");
-    let runtime_dir = home.path().join("runtime");
-    std::fs::create_dir_all(config_home.join("incant")).unwrap();
-    std::fs::create_dir_all(&runtime_dir).unwrap();
-    std::fs::write(
-        config_home.join("incant/config.toml"),
-        format!(
-            "[backend]\ntype = \"ollama\"\nhost = \"{}\"\ndefault_profile = \"default\"\n\n[profiles.default]\nmodel = \"mock-model\"\ntemperature = 0.1\n",
-            mock.host()
-        ),
-    )
-    .unwrap();
+    let client = ClientFixture::new(&format!(
+        "[backend]\ntype = \"ollama\"\nhost = \"{}\"\ndefault_profile = \"default\"\n\n[profiles.default]\nmodel = \"mock-model\"\ntemperature = 0.1\n",
+        mock.host()
+    ));
+    let runtime_dir = &client.runtime_dir;
 
     // The launcher leads its own process group, standing in for an
     // interactive shell's foreground group.
     use std::os::unix::process::CommandExt;
-    let status = Command::new(env!("CARGO_BIN_EXE_incant"))
+    let status = client
+        .command()
         .args(["daemon", "start"])
-        .env_clear()
-        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
-        .env("HOME", home.path())
-        .env("XDG_CONFIG_HOME", &config_home)
-        .env("XDG_RUNTIME_DIR", &runtime_dir)
         .process_group(0)
         .status()
         .expect("run daemon start");
@@ -0,0 +1,37 @@
+#!/bin/sh
+# Reference incant exec-backend plugin.
+#
+# incant writes one JSON request per line on stdin and reads one JSON reply
+# per line from stdout:
+#
+#   {"type":"health"}                         -> {"ok":true}
+#   {"type":"list_models"}                    -> {"models":["name", ...]}
+#   {"type":"generate","model":"...","system":"...","prompt":"...","temperature":0.1}
+#                                             -> {"response":"<command>"}
+#
+# Any request may instead be answered with {"error":"<message>"}. A real
+# plugin forwards `generate` to a model; this one answers `echo <prompt>`
+# so tests can check the round trip without a network.
+
+while IFS= read -r line; do
+    case "$line" in
+        *'"type":"health"'*)
+            printf '{"ok":true}\n'
+            ;;
+        *'"type":"list_models"'*)
+            printf '{"models":["plugin-small","plugin-large"]}\n'
+            ;;
+        *'"type":"generate"'*)
+            prompt=$(printf '%s\n' "$line" | sed -n 's/.*"prompt":"\([^"]*\)".*/\1/p')
+            model=$(printf '%s\n' "$line" | sed -n 's/.*"model":"\([^"]*\)".*/\1/p')
+            if [ "$prompt" = "fail" ]; then
+                printf '{"error":"model %s refused"}\n' "$model"
+            else
+                printf '{"response":"echo %s"}\n' "$prompt"
+            fi
+            ;;
+        *)
+            printf '{"error":"unsupported request"}\n'
+            ;;
+    esac
+done